@@ -40,6 +40,14 @@ fn main() -> miette::Result<()> {
         }
     }
 
+    // the log sink forwarding S2 logging to tracing
+    if cfg!(target_os = "linux") {
+        for lib in ["absl_log_globals", "absl_log_sink", "absl_log_entry", 
+            "absl_log_internal_log_sink_set", "absl_log_severity"] {
+            println!("cargo:rustc-link-lib=dylib={lib}");
+        }
+    }
+
     println!("cargo:rustc-link-arg=-Wl,-rpath,/opt/homebrew/opt/abseil/lib");
     println!("cargo:rustc-link-arg=-Wl,-rpath,/opt/homebrew/opt/openssl@3/lib");
 
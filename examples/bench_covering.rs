@@ -0,0 +1,63 @@
+use stopwatch::Stopwatch;
+use tarantula_s2::config::{Config, Covering};
+use tarantula_s2::search::Search;
+
+// compares the interior covering fast path with the exact contains point query
+// on random points inside the bounding box of the loaded districts.
+// cargo run --release --example bench_covering -- 127.1 36.4 127.4 36.75 1000000
+fn main() {
+    let args: Vec<f64> = std::env::args()
+        .skip(1)
+        .map(|arg| arg.parse().expect("number argument"))
+        .collect();
+    let (min_lon, min_lat, max_lon, max_lat, count) = match args.as_slice() {
+        [a, b, c, d, n] => (*a, *b, *c, *d, *n as usize),
+        _ => (127.1, 36.4, 127.4, 36.75, 1_000_000),
+    };
+
+    let toml_string = std::fs::read_to_string("Config.toml").expect("failed to read config file");
+    let config: Config = toml::from_str(&toml_string).expect("failed to parse toml");
+
+    // xorshift, the same points for both runs
+    let mut state = 0x2545F4914F6CDD1Du64;
+    let points: Vec<(f64, f64)> = (0..count)
+        .map(|_| {
+            let mut next = || {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 11) as f64 / (1u64 << 53) as f64
+            };
+            (min_lon + (max_lon - min_lon) * next(), min_lat + (max_lat - min_lat) * next())
+        })
+        .collect();
+
+    let mut results = vec![];
+    for covering in [None, Some(Covering { max_cells: 64, max_level: 20 })] {
+        let mut search_config = config.search.clone();
+        search_config.cache = None;
+        search_config.covering = covering.clone();
+
+        let mut search = Search::new(search_config).expect("create search");
+        search.load().expect("load search");
+
+        let sw = Stopwatch::start_new();
+        let answers: Vec<Vec<(i32, String)>> = points
+            .iter()
+            .map(|(lon, lat)| {
+                search.search(*lon, *lat)
+                    .expect("search")
+                    .into_iter()
+                    .map(|info| (info.level, info.name))
+                    .collect()
+            })
+            .collect();
+        let elapsed = sw.elapsed();
+        println!("covering {:?}: {} points {:?} ({:?}/point)",
+            covering, count, elapsed, elapsed / count as u32);
+        results.push(answers);
+    }
+
+    let diff = results[0].iter().zip(results[1].iter()).filter(|(a, b)| a != b).count();
+    println!("different answers: {}", diff);
+}
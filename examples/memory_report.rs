@@ -0,0 +1,33 @@
+use stopwatch::Stopwatch;
+use tarantula_s2::config::Config;
+use tarantula_s2::search::Search;
+
+// loads Config.toml with the mutable and the compact index and prints the
+// memory used by each layer.
+// cargo run --release --example memory_report
+fn main() {
+    let toml_string = std::fs::read_to_string("Config.toml").expect("failed to read config file");
+    let config: Config = toml::from_str(&toml_string).expect("failed to parse toml");
+
+    for compact in [false, true] {
+        let mut search_config = config.search.clone();
+        search_config.compact = compact;
+        search_config.lazy = None;
+
+        let mut search = Search::new(search_config).expect("create search");
+        let sw = Stopwatch::start_new();
+        search.load().expect("load search");
+        let elapsed = sw.elapsed();
+
+        let report = search.memory_report().expect("memory report");
+        println!("compact {}: loaded in {:?}", compact, elapsed);
+        report.layers.iter().for_each(|layer| {
+            println!("  {} level {} polygons {} index {} KiB info {} KiB",
+                layer.district, layer.level, layer.polygons,
+                layer.index_bytes / 1024, layer.info_bytes / 1024);
+        });
+        println!("  total index {} MiB info {} MiB, {} interned strings ({} KiB)",
+            report.index_bytes / (1024 * 1024), report.info_bytes / (1024 * 1024),
+            report.interned_strings, report.interned_bytes / 1024);
+    }
+}
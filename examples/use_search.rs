@@ -15,6 +15,7 @@ async fn main() {
                 "CTP_KOR_NM".to_string(),
                 "CTP_ENG_NM".to_string(),
             ],
+            code: None,
         },
     );
 
@@ -25,20 +26,37 @@ async fn main() {
                 attributes,
             },
             districts: vec!["36000".to_string()],
+            discover: false,
+            include: vec![],
+            exclude: vec![],
             hierarchies: vec!["TL_SCCO_CTPRVN".to_string()],
             district_par: vec![],
             district_par_any: vec![],
             debug: true,
             debug_name: String::new(),
+            parcel_rank: false,
+            cache: None,
+            covering: None,
+            derive_parents: false,
+            check_codes: false,
+            border_distance: 0.0,
+            lazy: None,
+            compact: false,
         },
         rest: tarantula_s2::config::Rest {
             port: 8080,
             host: "127.0.0.1".to_string(),
+            admin_token: None,
         },
         grpc: tarantula_s2::config::Grpc {
             port: 8090,
             host: "127.0.0.1".to_string(),
         },
+        telemetry: None,
+        log: None,
+        validate: None,
+        versions: None,
+        shutdown_timeout_secs: 30,
     };
 
     let mut search = Search::new(config.search).expect("create search");
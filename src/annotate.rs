@@ -0,0 +1,550 @@
+//! Region codes appended to the rows of a point file, for the `annotate` command.
+//!
+//! The input is read in batches of `batch_size` rows, every batch is looked
+//! up on the rayon pool and written before the next one is read, so memory
+//! stays bounded by the batch whatever the size of the file.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use error_chain::bail;
+use crate::search::{Info, Search};
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type};
+use arrow_array::{Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::arrow::ArrowWriter;
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Level and column prefix of the appended `_code` and `_name` columns.
+pub const LEVELS: [(i32, &str); 5] = [
+    (1, "sido"),
+    (2, "sigungu"),
+    (3, "emd"),
+    (4, "li"),
+    (5, "parcel"),
+];
+
+pub fn annotation_columns() -> Vec<String> {
+    LEVELS
+        .iter()
+        .flat_map(|(_, prefix)| [format!("{}_code", prefix), format!("{}_name", prefix)])
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FileFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl FileFormat {
+    /// Format of the file extension, `.jsonl` is NDJSON too.
+    pub fn from_path(path: &Path) -> Option<FileFormat> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(FileFormat::Csv),
+            "ndjson" | "jsonl" => Some(FileFormat::Ndjson),
+            "parquet" => Some(FileFormat::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// Rows of a batch, `rows[i][j]` is the value of `columns[j]`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+pub trait BatchReader {
+    /// Up to `size` rows, `None` at the end of the input.
+    fn next_batch(&mut self, size: usize) -> Result<Option<Batch>>;
+}
+
+pub trait BatchWriter {
+    fn write(&mut self, batch: &Batch) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    pub rows: usize,
+    pub annotated: usize,
+    pub not_found: usize,
+    /// rows without a valid lon/lat
+    pub invalid: usize,
+    pub failed: usize,
+}
+
+/// Opens `path`, `-` is stdin for CSV and NDJSON.
+pub fn open_reader(path: &Path, format: FileFormat, batch_size: usize)
+    -> Result<Box<dyn BatchReader>> {
+    let input = || -> Result<Box<dyn Read>> {
+        if path == Path::new("-") {
+            return Ok(Box::new(std::io::stdin()));
+        }
+        let file = File::open(path).chain_err(|| format!("failed to open {}", path.display()))?;
+        Ok(Box::new(file))
+    };
+    Ok(match format {
+        FileFormat::Csv => Box::new(CsvReader::new(input()?)?),
+        FileFormat::Ndjson => Box::new(NdjsonReader::new(BufReader::new(input()?))),
+        FileFormat::Parquet => {
+            let file = File::open(path)
+                .chain_err(|| format!("failed to open {}", path.display()))?;
+            Box::new(ParquetReader::new(file, batch_size)?)
+        }
+    })
+}
+
+/// Creates `path`, `-` is stdout for CSV and NDJSON.
+pub fn open_writer(path: &Path, format: FileFormat) -> Result<Box<dyn BatchWriter>> {
+    let output = || -> Result<Box<dyn Write>> {
+        if path == Path::new("-") {
+            return Ok(Box::new(std::io::stdout()));
+        }
+        let file = File::create(path)
+            .chain_err(|| format!("failed to create {}", path.display()))?;
+        Ok(Box::new(file))
+    };
+    Ok(match format {
+        FileFormat::Csv => Box::new(CsvWriter::new(output()?)),
+        FileFormat::Ndjson => Box::new(NdjsonWriter::new(output()?)),
+        FileFormat::Parquet => {
+            let file = File::create(path)
+                .chain_err(|| format!("failed to create {}", path.display()))?;
+            Box::new(ParquetWriter::new(file))
+        }
+    })
+}
+
+/// Annotates every row of `reader` with the regions of `search`.
+pub fn annotate(search: &Search, reader: &mut dyn BatchReader, writer: Box<dyn BatchWriter>,
+    columns: (&str, &str), batch_size: usize) -> Result<Summary> {
+    annotate_with(reader, writer, columns, batch_size, |lon, lat| {
+        search.search(lon, lat).map_err(|e| e.to_string())
+    })
+}
+
+/// Annotates the rows with the regions of `lookup`, `columns` are the
+/// lon and lat column names.
+pub fn annotate_with<F>(reader: &mut dyn BatchReader, mut writer: Box<dyn BatchWriter>,
+    columns: (&str, &str), batch_size: usize, lookup: F) -> Result<Summary>
+where
+    F: Fn(f64, f64) -> std::result::Result<Vec<Info>, String> + Sync,
+{
+    let mut summary = Summary::default();
+    while let Some(mut batch) = reader.next_batch(batch_size.max(1))? {
+        let position = |name: &str| batch.columns
+            .iter()
+            .position(|column| column == name)
+            .chain_err(|| format!("no {} column", name));
+        let (lon, lat) = (position(columns.0)?, position(columns.1)?);
+
+        let annotations: Vec<(Outcome, Vec<Value>)> = batch.rows
+            .par_iter()
+            .map(|row| annotate_row(row, lon, lat, &lookup))
+            .collect();
+
+        // an annotation column of the input is overwritten
+        let targets: Vec<usize> = annotation_columns()
+            .into_iter()
+            .map(|name| match batch.columns.iter().position(|column| *column == name) {
+                Some(index) => index,
+                None => {
+                    batch.columns.push(name);
+                    batch.columns.len() - 1
+                }
+            })
+            .collect();
+        let width = batch.columns.len();
+        batch.rows.iter_mut().zip(annotations).for_each(|(row, (outcome, values))| {
+            row.resize(width, Value::Null);
+            targets.iter().zip(values).for_each(|(target, value)| row[*target] = value);
+            summary.rows += 1;
+            match outcome {
+                Outcome::Annotated => summary.annotated += 1,
+                Outcome::NotFound => summary.not_found += 1,
+                Outcome::Invalid => summary.invalid += 1,
+                Outcome::Failed => summary.failed += 1,
+            }
+        });
+
+        writer.write(&batch)?;
+        tracing::debug!(rows = summary.rows, "annotated batch");
+    }
+    writer.finish()?;
+    Ok(summary)
+}
+
+enum Outcome {
+    Annotated,
+    NotFound,
+    Invalid,
+    Failed,
+}
+
+fn coordinate(value: &Value) -> Option<f64> {
+    let coordinate = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+    coordinate.filter(|v| v.is_finite())
+}
+
+fn annotate_row<F>(row: &[Value], lon: usize, lat: usize, lookup: &F) -> (Outcome, Vec<Value>)
+where
+    F: Fn(f64, f64) -> std::result::Result<Vec<Info>, String>,
+{
+    let empty = || vec![Value::Null; LEVELS.len() * 2];
+    let point = row.get(lon).and_then(coordinate).zip(row.get(lat).and_then(coordinate));
+    let Some((lon, lat)) = point.filter(|(lon, lat)| lon.abs() <= 180.0 && lat.abs() <= 90.0)
+    else {
+        return (Outcome::Invalid, empty());
+    };
+    let infos = match lookup(lon, lat) {
+        Ok(infos) => infos,
+        Err(e) => {
+            tracing::warn!(lon, lat, error = %e, "annotate search failed");
+            return (Outcome::Failed, empty());
+        }
+    };
+    if infos.is_empty() {
+        return (Outcome::NotFound, empty());
+    }
+    let values = LEVELS
+        .iter()
+        .flat_map(|(level, _)| match infos.iter().find(|info| info.level == *level) {
+            Some(info) => [Value::String(info.code.clone()), Value::String(info.name.clone())],
+            None => [Value::Null, Value::Null],
+        })
+        .collect();
+    (Outcome::Annotated, values)
+}
+
+fn value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+// index of every column of `columns` in the batch. The output keeps the
+// columns of its first batch, a column showing up later is an error rather
+// than silently dropped.
+fn project(batch: &Batch, columns: &[String]) -> Result<Vec<Option<usize>>> {
+    if let Some(column) = batch.columns.iter().find(|column| !columns.contains(column)) {
+        bail!("column {} first appears after the first batch, raise --batch-size", column);
+    }
+    Ok(columns
+        .iter()
+        .map(|name| batch.columns.iter().position(|column| column == name))
+        .collect())
+}
+
+struct CsvReader {
+    reader: csv::Reader<Box<dyn Read>>,
+    columns: Vec<String>,
+}
+
+impl CsvReader {
+    fn new(input: Box<dyn Read>) -> Result<Self> {
+        let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+        let columns = reader
+            .headers()
+            .chain_err(|| "failed to read csv header")?
+            .iter()
+            .map(|column| column.to_string())
+            .collect();
+        Ok(CsvReader { reader, columns })
+    }
+}
+
+impl BatchReader for CsvReader {
+    fn next_batch(&mut self, size: usize) -> Result<Option<Batch>> {
+        let mut rows = vec![];
+        let mut record = csv::StringRecord::new();
+        while rows.len() < size && self.reader.read_record(&mut record).chain_err(|| "read csv")? {
+            rows.push(record.iter().map(|field| Value::String(field.to_string())).collect());
+        }
+        Ok((!rows.is_empty()).then(|| Batch { columns: self.columns.clone(), rows }))
+    }
+}
+
+struct NdjsonReader<R: BufRead> {
+    lines: std::io::Lines<R>,
+    columns: Vec<String>,
+    line: usize,
+}
+
+impl<R: BufRead> NdjsonReader<R> {
+    fn new(input: R) -> Self {
+        NdjsonReader { lines: input.lines(), columns: vec![], line: 0 }
+    }
+}
+
+impl<R: BufRead> BatchReader for NdjsonReader<R> {
+    fn next_batch(&mut self, size: usize) -> Result<Option<Batch>> {
+        let mut objects = vec![];
+        while objects.len() < size {
+            let Some(line) = self.lines.next() else {
+                break;
+            };
+            self.line += 1;
+            let line = line.chain_err(|| "read ndjson")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let object: Map<String, Value> = serde_json::from_str(&line)
+                .chain_err(|| format!("invalid json object at line {}", self.line))?;
+            // columns in order of first appearance
+            object.keys().for_each(|key| {
+                if !self.columns.contains(key) {
+                    self.columns.push(key.clone());
+                }
+            });
+            objects.push(object);
+        }
+        let rows: Vec<Vec<Value>> = objects
+            .into_iter()
+            .map(|mut object| self.columns
+                .iter()
+                .map(|column| object.remove(column).unwrap_or(Value::Null))
+                .collect())
+            .collect();
+        Ok((!rows.is_empty()).then(|| Batch { columns: self.columns.clone(), rows }))
+    }
+}
+
+struct ParquetReader {
+    batches: ParquetRecordBatchReader,
+    columns: Vec<String>,
+}
+
+impl ParquetReader {
+    fn new(file: File, batch_size: usize) -> Result<Self> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+            .chain_err(|| "failed to read parquet metadata")?;
+        let columns = builder.schema().fields().iter().map(|field| field.name().clone()).collect();
+        let batches = builder
+            .with_batch_size(batch_size.max(1))
+            .build()
+            .chain_err(|| "failed to read parquet")?;
+        Ok(ParquetReader { batches, columns })
+    }
+}
+
+impl BatchReader for ParquetReader {
+    fn next_batch(&mut self, _size: usize) -> Result<Option<Batch>> {
+        let Some(record_batch) = self.batches.next() else {
+            return Ok(None);
+        };
+        let record_batch = record_batch.chain_err(|| "read parquet")?;
+        let columns = record_batch
+            .columns()
+            .iter()
+            .map(column_values)
+            .collect::<Result<Vec<_>>>()?;
+        let rows = (0..record_batch.num_rows())
+            .map(|row| columns.iter().map(|values| values[row].clone()).collect())
+            .collect();
+        Ok(Some(Batch { columns: self.columns.clone(), rows }))
+    }
+}
+
+// numbers and booleans keep their type, anything else is read as text
+fn column_values(array: &ArrayRef) -> Result<Vec<Value>> {
+    let target = match array.data_type() {
+        data_type if data_type.is_integer() => DataType::Int64,
+        data_type if data_type.is_floating() => DataType::Float64,
+        DataType::Boolean => DataType::Boolean,
+        _ => DataType::Utf8,
+    };
+    let array = arrow_cast::cast(array, &target)
+        .chain_err(|| format!("failed to read {} column", array.data_type()))?;
+    Ok((0..array.len())
+        .map(|i| {
+            if array.is_null(i) {
+                return Value::Null;
+            }
+            match target {
+                DataType::Int64 => array.as_primitive::<Int64Type>().value(i).into(),
+                DataType::Float64 => array.as_primitive::<Float64Type>().value(i).into(),
+                DataType::Boolean => array.as_boolean().value(i).into(),
+                _ => array.as_string::<i32>().value(i).into(),
+            }
+        })
+        .collect())
+}
+
+struct CsvWriter {
+    writer: csv::Writer<Box<dyn Write>>,
+    columns: Option<Vec<String>>,
+}
+
+impl CsvWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        CsvWriter { writer: csv::Writer::from_writer(output), columns: None }
+    }
+}
+
+impl BatchWriter for CsvWriter {
+    fn write(&mut self, batch: &Batch) -> Result<()> {
+        if self.columns.is_none() {
+            self.writer.write_record(&batch.columns).chain_err(|| "write csv header")?;
+            self.columns = Some(batch.columns.clone());
+        }
+        let indices = project(batch, self.columns.as_deref().unwrap_or_default())?;
+        for row in batch.rows.iter() {
+            let record = indices.iter().map(|index| index
+                .and_then(|index| row.get(index))
+                .and_then(value_string)
+                .unwrap_or_default());
+            self.writer.write_record(record).chain_err(|| "write csv")?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush().chain_err(|| "flush csv")
+    }
+}
+
+struct NdjsonWriter {
+    writer: BufWriter<Box<dyn Write>>,
+}
+
+impl NdjsonWriter {
+    fn new(output: Box<dyn Write>) -> Self {
+        NdjsonWriter { writer: BufWriter::new(output) }
+    }
+}
+
+impl BatchWriter for NdjsonWriter {
+    fn write(&mut self, batch: &Batch) -> Result<()> {
+        for row in batch.rows.iter() {
+            let object: Map<String, Value> = batch.columns
+                .iter()
+                .cloned()
+                .zip(row.iter().cloned())
+                .collect();
+            serde_json::to_writer(&mut self.writer, &object).chain_err(|| "write ndjson")?;
+            self.writer.write_all(b"\n").chain_err(|| "write ndjson")?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush().chain_err(|| "flush ndjson")
+    }
+}
+
+struct ParquetWriter {
+    file: Option<File>,
+    writer: Option<ArrowWriter<File>>,
+    schema: Option<Arc<Schema>>,
+}
+
+impl ParquetWriter {
+    fn new(file: File) -> Self {
+        ParquetWriter { file: Some(file), writer: None, schema: None }
+    }
+}
+
+impl BatchWriter for ParquetWriter {
+    fn write(&mut self, batch: &Batch) -> Result<()> {
+        if let Some(file) = self.file.take() {
+            let fields: Vec<Field> = batch.columns
+                .iter()
+                .enumerate()
+                .map(|(index, name)| Field::new(name, infer_type(batch, index), true))
+                .collect();
+            let schema = Arc::new(Schema::new(fields));
+            self.writer = Some(ArrowWriter::try_new(file, schema.clone(), None)
+                .chain_err(|| "failed to create parquet writer")?);
+            self.schema = Some(schema);
+        }
+        let (Some(writer), Some(schema)) = (&mut self.writer, &self.schema) else {
+            bail!("parquet writer already closed");
+        };
+
+        let columns: Vec<String> = schema.fields().iter().map(|field| field.name().clone()).collect();
+        let arrays: Vec<ArrayRef> = project(batch, &columns)?
+            .into_iter()
+            .zip(schema.fields().iter())
+            .map(|(index, field)| to_array(field, batch, index))
+            .collect::<Result<_>>()?;
+        let record_batch = RecordBatch::try_new(schema.clone(), arrays)
+            .chain_err(|| "failed to build parquet batch")?;
+        writer.write(&record_batch).chain_err(|| "write parquet")
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.close().chain_err(|| "failed to close parquet")?;
+        }
+        Ok(())
+    }
+}
+
+// type of a column from the values of the first batch, mixed values are text
+fn infer_type(batch: &Batch, index: usize) -> DataType {
+    let mut data_type = None;
+    for value in batch.rows.iter().filter_map(|row| row.get(index)) {
+        let value_type = match value {
+            Value::Null => continue,
+            Value::Bool(_) => DataType::Boolean,
+            Value::Number(number) if number.is_i64() => DataType::Int64,
+            Value::Number(_) => DataType::Float64,
+            _ => return DataType::Utf8,
+        };
+        data_type = match (data_type, value_type) {
+            (None, value_type) => Some(value_type),
+            (Some(DataType::Int64), DataType::Float64) | (Some(DataType::Float64), DataType::Int64)
+                => Some(DataType::Float64),
+            (Some(current), value_type) if current == value_type => Some(current),
+            _ => return DataType::Utf8,
+        };
+    }
+    data_type.unwrap_or(DataType::Utf8)
+}
+
+// whether `value` is written to a column of `data_type` without loss
+fn fits(data_type: &DataType, value: &Value) -> bool {
+    match (data_type, value) {
+        (_, Value::Null) | (DataType::Utf8, _) => true,
+        (DataType::Int64, Value::Number(number)) => number.is_i64(),
+        (DataType::Float64, Value::Number(_)) => true,
+        (DataType::Boolean, Value::Bool(_)) => true,
+        _ => false,
+    }
+}
+
+fn to_array(field: &Field, batch: &Batch, index: Option<usize>) -> Result<ArrayRef> {
+    let data_type = field.data_type();
+    let values = batch.rows
+        .iter()
+        .map(|row| index.and_then(|index| row.get(index)).unwrap_or(&Value::Null));
+    if let Some(value) = values.clone().find(|value| !fits(data_type, value)) {
+        bail!("column {} has {} after the first batch typed it {}, raise --batch-size",
+            field.name(), value, data_type);
+    }
+    Ok(match data_type {
+        DataType::Int64 => Arc::new(values.map(|value| value.as_i64()).collect::<Int64Array>()),
+        DataType::Float64 => Arc::new(values.map(|value| value.as_f64()).collect::<Float64Array>()),
+        DataType::Boolean => Arc::new(values.map(|value| value.as_bool()).collect::<BooleanArray>()),
+        _ => Arc::new(values.map(value_string).collect::<StringArray>()),
+    })
+}
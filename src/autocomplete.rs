@@ -0,0 +1,210 @@
+//! Hangul aware autocomplete of region names.
+//!
+//! Names are decomposed into compatibility jamo so that partially typed
+//! syllables ("조ㅊ") and initial consonant queries ("ㅈㅊㅇ") match, and a
+//! jamo level edit distance makes the lookup tolerant to typos.
+
+use crate::lookup::{levenshtein, normalize, Layer, Region};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const HANGUL_BASE: u32 = 0xAC00;
+const HANGUL_LAST: u32 = 0xD7A3;
+
+const CHOSEONG: [char; 19] = [
+    'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ',
+    'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+
+const JUNGSEONG: [&str; 21] = [
+    "ㅏ", "ㅐ", "ㅑ", "ㅒ", "ㅓ", "ㅔ", "ㅕ", "ㅖ", "ㅗ", "ㅗㅏ", "ㅗㅐ",
+    "ㅗㅣ", "ㅛ", "ㅜ", "ㅜㅓ", "ㅜㅔ", "ㅜㅣ", "ㅠ", "ㅡ", "ㅡㅣ", "ㅣ",
+];
+
+const JONGSEONG: [&str; 28] = [
+    "", "ㄱ", "ㄲ", "ㄱㅅ", "ㄴ", "ㄴㅈ", "ㄴㅎ", "ㄷ", "ㄹ", "ㄹㄱ",
+    "ㄹㅁ", "ㄹㅂ", "ㄹㅅ", "ㄹㅌ", "ㄹㅍ", "ㄹㅎ", "ㅁ", "ㅂ", "ㅂㅅ", "ㅅ",
+    "ㅆ", "ㅇ", "ㅈ", "ㅊ", "ㅋ", "ㅌ", "ㅍ", "ㅎ",
+];
+
+// compound compatibility jamo typed as a single character
+const COMPOUND: [(char, &str); 18] = [
+    ('ㅘ', "ㅗㅏ"), ('ㅙ', "ㅗㅐ"), ('ㅚ', "ㅗㅣ"), ('ㅝ', "ㅜㅓ"), ('ㅞ', "ㅜㅔ"),
+    ('ㅟ', "ㅜㅣ"), ('ㅢ', "ㅡㅣ"), ('ㄳ', "ㄱㅅ"), ('ㄵ', "ㄴㅈ"), ('ㄶ', "ㄴㅎ"),
+    ('ㄺ', "ㄹㄱ"), ('ㄻ', "ㄹㅁ"), ('ㄼ', "ㄹㅂ"), ('ㄽ', "ㄹㅅ"), ('ㄾ', "ㄹㅌ"),
+    ('ㄿ', "ㄹㅍ"), ('ㅀ', "ㄹㅎ"), ('ㅄ', "ㅂㅅ"),
+];
+
+/// Choseong, jungseong and jongseong indexes of a precomposed syllable.
+pub fn decompose(c: char) -> Option<(usize, usize, usize)> {
+    let code = c as u32;
+    if !(HANGUL_BASE..=HANGUL_LAST).contains(&code) {
+        return None;
+    }
+    let idx = (code - HANGUL_BASE) as usize;
+    Some((idx / 588, (idx % 588) / 28, idx % 28))
+}
+
+pub fn is_choseong(c: char) -> bool {
+    CHOSEONG.contains(&c)
+}
+
+/// Keystroke level jamo sequence, other characters are kept lower cased.
+pub fn to_jamo(s: &str) -> String {
+    normalize(s)
+        .chars()
+        .fold(String::new(), |mut jamo, c| {
+            if let Some((cho, jung, jong)) = decompose(c) {
+                jamo.push(CHOSEONG[cho]);
+                jamo.push_str(JUNGSEONG[jung]);
+                jamo.push_str(JONGSEONG[jong]);
+            } else if let Some((_, split)) = COMPOUND.iter().find(|(k, _)| *k == c) {
+                jamo.push_str(split);
+            } else {
+                jamo.push(c);
+            }
+            jamo
+        })
+}
+
+/// Initial consonant of every syllable, other characters are kept lower cased.
+pub fn to_choseong(s: &str) -> String {
+    normalize(s)
+        .chars()
+        .map(|c| decompose(c).map(|(cho, _, _)| CHOSEONG[cho]).unwrap_or(c))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Suggestion {
+    pub code: String,
+    pub level: i32,
+    pub name: String,
+    pub eng_name: String,
+    pub district: String,
+    pub centroid: (f64, f64),
+    pub parcels: usize,
+}
+
+struct Entry {
+    suggestion: Suggestion,
+    jamo: Vec<char>,
+}
+
+#[derive(Default)]
+pub struct Autocomplete {
+    entries: Vec<Entry>,
+    jamo: Vec<(String, usize)>,
+    choseong: Vec<(String, usize)>,
+}
+
+fn prefix_range<'a>(keys: &'a [(String, usize)], prefix: &'a str)
+    -> impl Iterator<Item = usize> + 'a {
+    let start = keys.partition_point(|(k, _)| k.as_str() < prefix);
+    keys[start..]
+        .iter()
+        .take_while(move |(k, _)| k.starts_with(prefix))
+        .map(|(_, idx)| *idx)
+}
+
+impl Autocomplete {
+    /// `parcels` is the number of parcels inside each region, used as a
+    /// population proxy when ranking suggestions.
+    pub fn new(regions: &[Region], parcels: &HashMap<(Layer, usize), usize>) -> Self {
+        let mut entries = vec![];
+        let mut jamo = vec![];
+        let mut choseong = vec![];
+        regions.iter().for_each(|region| {
+            let idx = entries.len();
+            [&region.name, &region.eng_name]
+                .iter()
+                .filter(|name| !name.is_empty())
+                .for_each(|name| {
+                    jamo.push((to_jamo(name), idx));
+                    choseong.push((to_choseong(name), idx));
+                });
+            entries.push(Entry {
+                suggestion: Suggestion {
+                    code: region.code.clone(),
+                    level: region.level,
+                    name: region.name.clone(),
+                    eng_name: region.eng_name.clone(),
+                    district: region.district.clone(),
+                    centroid: region.centroid,
+                    parcels: parcels
+                        .get(&(region.layer.clone(), region.index))
+                        .copied()
+                        .unwrap_or(0),
+                },
+                jamo: to_jamo(&region.name).chars().collect(),
+            });
+        });
+        jamo.sort();
+        jamo.dedup();
+        choseong.sort();
+        choseong.dedup();
+
+        Self { entries, jamo, choseong }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn suggest(&self, query: &str, limit: usize) -> Vec<&Suggestion> {
+        let query = normalize(query);
+        if query.is_empty() {
+            return vec![];
+        }
+
+        // (typo distance, entry)
+        let mut found: HashMap<usize, usize> = HashMap::new();
+        if query.chars().all(is_choseong) {
+            prefix_range(&self.choseong, &query).for_each(|idx| {
+                found.insert(idx, 0);
+            });
+        }
+
+        let query_jamo = to_jamo(&query);
+        prefix_range(&self.jamo, &query_jamo).for_each(|idx| {
+            found.insert(idx, 0);
+        });
+
+        if found.len() < limit {
+            let query_jamo: Vec<char> = query_jamo.chars().collect();
+            let max_distance = (query_jamo.len() / 4).clamp(1, 2);
+            self.entries.iter().enumerate().for_each(|(idx, entry)| {
+                if found.contains_key(&idx) || entry.jamo.len() + max_distance < query_jamo.len() {
+                    return;
+                }
+                // compare against the prefix of the same length as the query
+                let distance = (query_jamo.len().saturating_sub(max_distance)
+                    ..=(query_jamo.len() + max_distance).min(entry.jamo.len()))
+                    .map(|len| levenshtein(&query_jamo, &entry.jamo[..len]))
+                    .min()
+                    .unwrap_or(usize::MAX);
+                if distance <= max_distance {
+                    found.insert(idx, distance);
+                }
+            });
+        }
+
+        let mut suggestions: Vec<(usize, &Suggestion)> = found
+            .into_iter()
+            .map(|(idx, distance)| (distance, &self.entries[idx].suggestion))
+            .collect();
+        suggestions.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then(a.1.level.cmp(&b.1.level))
+                .then(b.1.parcels.cmp(&a.1.parcels))
+                .then(a.1.name.len().cmp(&b.1.name.len()))
+                .then(a.1.code.cmp(&b.1.code))
+        });
+        suggestions.truncate(limit);
+        suggestions.into_iter().map(|(_, suggestion)| suggestion).collect()
+    }
+}
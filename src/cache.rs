@@ -0,0 +1,102 @@
+//! Sharded LRU cache of search results keyed by S2 cell id.
+//!
+//! `Search` only inserts cells that lie entirely inside a single polygon (or
+//! outside every polygon) of each probed layer, so a cached answer is the
+//! exact answer for any point of the cell.
+//!
+//! A reload or unload clears the cache and starts a new generation, a result
+//! computed from the layers before it is not inserted afterwards.
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub uncacheable: u64,
+    pub entries: usize,
+}
+
+pub struct Cache<V> {
+    shards: Vec<Mutex<LruCache<u64, V>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    uncacheable: AtomicU64,
+    generation: AtomicU64,
+}
+
+impl<V: Clone> Cache<V> {
+    pub fn new(capacity: usize, shards: usize) -> Self {
+        let shards = shards.max(1);
+        let per_shard = NonZeroUsize::new((capacity / shards).max(1)).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(LruCache::new(per_shard))).collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            uncacheable: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: u64) -> &Mutex<LruCache<u64, V>> {
+        // cell ids of neighbouring points share their high bits
+        let hash = key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        &self.shards[hash as usize % self.shards.len()]
+    }
+
+    pub fn get(&self, key: u64) -> Option<V> {
+        let value = self.shard(key)
+            .lock()
+            .ok()
+            .and_then(|mut shard| shard.get(&key).cloned());
+        match value {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        value
+    }
+
+    /// Generation to pass to `put`, read before looking up the layers.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Inserts `value` unless the cache was cleared since `generation`.
+    pub fn put(&self, key: u64, value: V, generation: u64) {
+        if let Ok(mut shard) = self.shard(key).lock() {
+            // checked under the shard lock, `clear` bumps it before taking the locks
+            if generation == self.generation.load(Ordering::SeqCst) {
+                shard.put(key, value);
+            }
+        }
+    }
+
+    pub fn skip(&self) {
+        self.uncacheable.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.shards.iter().for_each(|shard| {
+            if let Ok(mut shard) = shard.lock() {
+                shard.clear();
+            }
+        });
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            uncacheable: self.uncacheable.load(Ordering::Relaxed),
+            entries: self.shards
+                .iter()
+                .filter_map(|shard| shard.lock().ok().map(|shard| shard.len()))
+                .sum(),
+        }
+    }
+}
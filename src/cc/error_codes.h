@@ -5,5 +5,7 @@ enum class ErrorCode {
     FAILURE = 1,
     OUTER_CURVATURE = 2,
     INNER_CURVATURE = 3,
-    TOO_FEW_VERTICES = 4
+    TOO_FEW_VERTICES = 4,
+    CROSSING_EDGES = 5,
+    DUPLICATE_VERTICES = 6
 };
@@ -5,21 +5,123 @@
 #pragma GCC diagnostic ignored "-Wunused-private-field"
 
 #include "s2/s2point.h"
+#include "s2/s2cell_id.h"
+#include "s2/s2cell_union.h"
+#include "s2/s2lax_polygon_shape.h"
+#include "s2/s2shapeutil_coding.h"
+#include "s2/util/coding/coder.h"
+#include "s2/s2region_coverer.h"
 #include "s2/s2contains_point_query.h"
+#include "s2/s2closest_edge_query.h"
+#include "s2/s2earth.h"
 #include "s2/s2builderutil_snap_functions.h"
 #include "s2/s2builderutil_s2polygon_layer.h"
+#include "s2/s2shape_index_region.h"
+
+#include "absl/log/globals.h"
+#include "absl/log/log_entry.h"
+#include "absl/log/log_sink.h"
+#include "absl/log/log_sink_registry.h"
 
 #pragma GCC diagnostic pop
 
+#include <algorithm>
+#include <deque>
+#include <iterator>
+#include <mutex>
+#include <set>
+
+namespace {
+
+std::mutex logMutex;
+std::deque<std::string> logLines;
+// oldest lines are dropped when nobody drains them
+constexpr size_t MAX_LOG_LINES = 10000;
+
+class ForwardLogSink : public absl::LogSink {
+public:
+    void Send(const absl::LogEntry& entry) override {
+        std::string source = std::string(entry.source_basename()) + ":" 
+            + std::to_string(entry.source_line());
+        push_log(absl::LogSeverityName(entry.log_severity()), source.c_str(), 
+            std::string(entry.text_message()));
+    }
+};
+
+std::unique_ptr<Overlay> to_overlay(const S2Polygon& polygon) {
+    auto r = std::make_unique<Overlay>();
+    r->area_ = S2Earth::SteradiansToSquareMeters(polygon.GetArea());
+    for (int i = 0; i < polygon.num_loops(); ++i) {
+        const S2Loop* loop = polygon.loop(i);
+        for (int j = 0; j < loop->num_vertices(); ++j) {
+            S2LatLng latlng(loop->vertex(j));
+            r->lnglats_->push_back(LngLat(latlng.lng().degrees(), latlng.lat().degrees()));
+        }
+        // a hole encloses the rest of the sphere
+        double area = loop->GetArea();
+        area = std::min(area, 4 * M_PI - area);
+        r->loop_sizes_->push_back(loop->num_vertices());
+        r->loop_areas_->push_back(S2Earth::SteradiansToSquareMeters(area) 
+            * (loop->is_hole() ? -1 : 1));
+    }
+    return r;
+}
+
+}
+
+void init_logging() {
+    static ForwardLogSink sink;
+    static std::once_flag once;
+    std::call_once(once, [] {
+        absl::AddLogSink(&sink);
+        // forwarded to tracing, not written to stderr as well
+        absl::SetStderrThreshold(absl::LogSeverityAtLeast::kInfinity);
+    });
+}
+
+void push_log(const char* severity, const char* source, const std::string& message) {
+    std::lock_guard<std::mutex> lock(logMutex);
+    if (logLines.size() >= MAX_LOG_LINES) {
+        logLines.pop_front();
+    }
+    logLines.push_back(std::string(severity) + "\t" + source + "\t" + message);
+}
+
+std::string next_log() {
+    std::lock_guard<std::mutex> lock(logMutex);
+    if (logLines.empty()) {
+        return "";
+    }
+    std::string line = std::move(logLines.front());
+    logLines.pop_front();
+    return line;
+}
+
+
+uint64_t cell_id(double lng, double lat, int level) {
+    return S2CellId(S2LatLng::FromDegrees(lat, lng)).parent(level).id();
+}
 
 Polygons::Polygons() {
     polygonsIndex_ = std::make_unique<MutableS2ShapeIndex>();
+    covering_ = std::make_unique<std::vector<CoveringCell>>();
 }
 
 Polygons::~Polygons() {
 }
 
+const S2ShapeIndex* Polygons::index() const {
+    if (encodedIndex_) {
+        return encodedIndex_.get();
+    }
+    return polygonsIndex_.get();
+}
+
 ErrorCode Polygons::add(Polygon polygon) {
+    if (encodedIndex_) {
+        return ErrorCode::FAILURE;
+    }
+
     auto s2polygon = std::make_unique<S2Polygon>();
     std::vector<std::unique_ptr<S2Loop> > loops;
     for (auto a = polygon.loops_->begin(); a != polygon.loops_->end(); a++) {
@@ -36,10 +138,126 @@ ErrorCode Polygons::add(Polygon polygon) {
     return ErrorCode::SUCCESS;
 }
 
+void Polygons::build_covering(int max_cells, int max_level) {
+    S2RegionCoverer::Options options;
+    options.set_max_cells(max_cells);
+    options.set_max_level(max_level);
+    S2RegionCoverer coverer(options);
+
+    std::vector<CoveringCell> cells;
+    for (int id = 0; id < polygonsIndex_->num_shape_ids(); ++id) {
+        auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(polygonsIndex_->shape(id));
+        if (!poly_shape) {
+            continue;
+        }
+        S2CellUnion covering = coverer.GetInteriorCovering(*poly_shape->polygon());
+        for (const S2CellId& cell : covering) {
+            cells.push_back({cell.range_min().id(), cell.range_max().id(), id});
+        }
+    }
+
+    std::sort(cells.begin(), cells.end(), [](const CoveringCell& a, const CoveringCell& b) {
+        return a.min < b.min;
+    });
+
+    // overlapping polygons are ambiguous, drop both cells and fall back to the exact query
+    std::vector<CoveringCell> kept;
+    size_t reach = 0;
+    for (const auto& cell : cells) {
+        if (!kept.empty() && cell.min <= kept[reach].max) {
+            kept[reach].shape = -1;
+            continue;
+        }
+        kept.push_back(cell);
+        reach = kept.size() - 1;
+    }
+
+    covering_->clear();
+    std::copy_if(kept.begin(), kept.end(), std::back_inserter(*covering_),
+        [](const CoveringCell& cell) { return cell.shape >= 0; });
+    covering_->shrink_to_fit();
+}
+
+// re-encodes every polygon as a lax polygon into a single buffer and serves
+// queries from an EncodedS2ShapeIndex, shape ids are kept
+bool Polygons::compact() {
+    if (encodedIndex_) {
+        return true;
+    }
+
+    MutableS2ShapeIndex lax;
+    for (int id = 0; id < polygonsIndex_->num_shape_ids(); ++id) {
+        auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(polygonsIndex_->shape(id));
+        if (poly_shape) {
+            lax.Add(std::make_unique<S2LaxPolygonShape>(*poly_shape->polygon()));
+        } else {
+            lax.Add(std::make_unique<S2LaxPolygonShape>());
+        }
+    }
+    lax.ForceBuild();
+
+    Encoder encoder;
+    if (!s2shapeutil::CompactEncodeTaggedShapes(lax, &encoder)) {
+        return false;
+    }
+    lax.Encode(&encoder);
+    encoded_ = std::make_unique<std::string>(encoder.base(), encoder.length());
+
+    Decoder decoder(encoded_->data(), encoded_->size());
+    auto encodedIndex = std::make_unique<EncodedS2ShapeIndex>();
+    if (!encodedIndex->Init(&decoder, s2shapeutil::LazyDecodeShapeFactory(&decoder))) {
+        encoded_.reset();
+        return false;
+    }
+
+    encodedIndex_ = std::move(encodedIndex);
+    polygonsIndex_ = std::make_unique<MutableS2ShapeIndex>();
+    return true;
+}
+
+bool Polygons::is_compact() const {
+    return encodedIndex_ != nullptr;
+}
+
+size_t Polygons::covering_size() const {
+    return covering_->size();
+}
+
+// bytes of the index, the polygons and the covering
+size_t Polygons::space_used() const {
+    size_t size = covering_->capacity() * sizeof(CoveringCell);
+    if (encodedIndex_) {
+        return size + encoded_->capacity() + encodedIndex_->SpaceUsed();
+    }
+
+    size += polygonsIndex_->SpaceUsed();
+    for (int id = 0; id < polygonsIndex_->num_shape_ids(); ++id) {
+        auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(polygonsIndex_->shape(id));
+        if (poly_shape) {
+            size += poly_shape->polygon()->SpaceUsed();
+        }
+    }
+
+    return size;
+}
+
 int Polygons::search(double lng, double lat) const {
+    if (!covering_->empty()) {
+        uint64_t id = S2CellId(S2LatLng::FromDegrees(lat, lng)).id();
+        auto it = std::upper_bound(covering_->begin(), covering_->end(), id,
+            [](uint64_t value, const CoveringCell& cell) { return value < cell.min; });
+        if (it != covering_->begin() && id <= (--it)->max) {
+            return it->shape;
+        }
+    }
+
+    return search_exact(lng, lat);
+}
+
+int Polygons::search_exact(double lng, double lat) const {
     int r = -1;
     S2ContainsPointQueryOptions options(S2VertexModel::OPEN);       
-    auto containsPointQuery = MakeS2ContainsPointQuery(polygonsIndex_.get(), options);  
+    auto containsPointQuery = MakeS2ContainsPointQuery(index(), options);  
     auto containsPointQueryResult = containsPointQuery.GetContainingShapes(
       S2Point(S2LatLng::FromDegrees(lat, lng)));
     if (!containsPointQueryResult.empty()) {
@@ -50,27 +268,209 @@ int Polygons::search(double lng, double lat) const {
 }
 
 std::unique_ptr<SearchResult> Polygons::search_polygon(double lng, double lat) const {
+    return polygon(search(lng, lat));
+}
+
+std::unique_ptr<SearchResult> Polygons::polygon(int index) const {
     auto r = std::make_unique<SearchResult>();
-    S2ContainsPointQueryOptions options(S2VertexModel::OPEN);       
-    auto containsPointQuery = MakeS2ContainsPointQuery(polygonsIndex_.get(), options);  
-    auto containsPointQueryResult = containsPointQuery.GetContainingShapes(
-      S2Point(S2LatLng::FromDegrees(lat, lng)));
-    if (!containsPointQueryResult.empty()) {
-        r->index_ = containsPointQueryResult.front()->id();
-        const S2Shape* shape = polygonsIndex_->shape(r->index_);
-        auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(shape);
-        if (poly_shape) {
-            auto polygon = poly_shape->polygon();
-
-            for (int i = 0; i < polygon->num_loops(); ++i) {
-                const S2Loop* loop = polygon->loop(i);
-                for (int j = 0; j < loop->num_vertices(); ++j) {
-                    S2LatLng latlng(loop->vertex(j));
-                    r->lnglats_->push_back(LngLat(latlng.lng().degrees(), latlng.lat().degrees()));
-                }
+    const S2ShapeIndex* shapes = this->index();
+    if (index < 0 || index >= shapes->num_shape_ids()) {
+        return r;
+    }
+
+    r->index_ = index;
+    const S2Shape* shape = shapes->shape(index);
+    auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(shape);
+    if (poly_shape) {
+        auto polygon = poly_shape->polygon();
+
+        for (int i = 0; i < polygon->num_loops(); ++i) {
+            const S2Loop* loop = polygon->loop(i);
+            for (int j = 0; j < loop->num_vertices(); ++j) {
+                S2LatLng latlng(loop->vertex(j));
+                r->lnglats_->push_back(LngLat(latlng.lng().degrees(), latlng.lat().degrees()));
             }
         }
-    } 
+    } else if (shape) {
+        for (int i = 0; i < shape->num_chains(); ++i) {
+            for (int j = 0; j < shape->chain(i).length; ++j) {
+                S2LatLng latlng(shape->chain_edge(i, j).v0);
+                r->lnglats_->push_back(LngLat(latlng.lng().degrees(), latlng.lat().degrees()));
+            }
+        }
+    }
+
+    return r;
+}
+
+// the shape containing the whole cell, CELL_NONE if no shape intersects it
+// and CELL_MIXED if any shape edge crosses it
+int Polygons::cell_shape(uint64_t id) const {
+    S2CellId target(id);
+    S2ShapeIndex::Iterator it(index(), S2ShapeIndex::UNPOSITIONED);
+    auto relation = it.Locate(target);
+    if (relation == S2ShapeIndex::DISJOINT) {
+        return CELL_NONE;
+    } else if (relation == S2ShapeIndex::SUBDIVIDED) {
+        return CELL_MIXED;
+    }
+
+    const S2ShapeIndexCell& cell = it.cell();
+    int r = CELL_NONE;
+    for (int i = 0; i < cell.num_clipped(); ++i) {
+        const S2ClippedShape& clipped = cell.clipped(i);
+        if (clipped.num_edges() > 0) {
+            return CELL_MIXED;
+        }
+        if (clipped.contains_center() && r == CELL_NONE) {
+            r = clipped.shape_id();
+        }
+    }
 
     return r;
 }
+
+// meters to the nearest polygon edge, DBL_MAX for an empty index
+double Polygons::distance(double lng, double lat) const {
+    S2ClosestEdgeQuery query(index());
+    query.mutable_options()->set_include_interiors(false);
+    S2ClosestEdgeQuery::PointTarget target(S2Point(S2LatLng::FromDegrees(lat, lng)));
+    S1ChordAngle d = query.GetDistance(&target);
+    if (d == S1ChordAngle::Infinity()) {
+        return DBL_MAX;
+    }
+
+    return S2Earth::ToMeters(d);
+}
+
+const S2Polygon* Polygons::s2polygon(int index) const {
+    if (encodedIndex_ || index < 0 || index >= polygonsIndex_->num_shape_ids()) {
+        return nullptr;
+    }
+    auto poly_shape = dynamic_cast<const S2Polygon::Shape*>(polygonsIndex_->shape(index));
+    return poly_shape ? poly_shape->polygon() : nullptr;
+}
+
+// square meters, -1 when the shape is not an S2Polygon
+double Polygons::area(int index) const {
+    auto polygon = s2polygon(index);
+    if (!polygon) {
+        return -1;
+    }
+    return S2Earth::SteradiansToSquareMeters(polygon->GetArea());
+}
+
+// shape ids of `other` intersecting the covering of the shape, a superset of
+// the shapes intersecting the shape itself
+std::unique_ptr<std::vector<int32_t>> Polygons::intersecting(int index, 
+    const Polygons& other) const {
+    auto r = std::make_unique<std::vector<int32_t>>();
+    auto polygon = s2polygon(index);
+    if (!polygon) {
+        return r;
+    }
+
+    S2RegionCoverer::Options options;
+    options.set_max_cells(16);
+    S2RegionCoverer coverer(options);
+    S2CellUnion covering = coverer.GetCovering(*polygon);
+
+    std::set<int32_t> ids;
+    auto region = MakeS2ShapeIndexRegion(other.index());
+    for (const S2CellId& id : covering) {
+        region.VisitIntersectingShapes(S2Cell(id), [&ids](const S2Shape* shape, bool) {
+            ids.insert(shape->id());
+            return true;
+        });
+    }
+    r->assign(ids.begin(), ids.end());
+    return r;
+}
+
+std::unique_ptr<Overlay> Polygons::intersection(int index, const Polygons& other, 
+    int other_index) const {
+    auto a = s2polygon(index);
+    auto b = other.s2polygon(other_index);
+    if (!a || !b) {
+        return std::make_unique<Overlay>();
+    }
+
+    S2Polygon result;
+    result.set_s2debug_override(S2Debug::DISABLE);
+    result.InitToIntersection(*a, *b);
+    return to_overlay(result);
+}
+
+// the part of the shape not covered by the shapes of `other`
+std::unique_ptr<Overlay> Polygons::uncovered(int index, const Polygons& other) const {
+    auto polygon = s2polygon(index);
+    if (!polygon) {
+        return std::make_unique<Overlay>();
+    }
+
+    std::vector<std::unique_ptr<S2Polygon>> covers;
+    auto ids = intersecting(index, other);
+    for (auto id : *ids) {
+        auto cover = other.s2polygon(id);
+        if (cover) {
+            auto copy = std::make_unique<S2Polygon>();
+            copy->Copy(*cover);
+            covers.push_back(std::move(copy));
+        }
+    }
+
+    S2Polygon result;
+    result.set_s2debug_override(S2Debug::DISABLE);
+    if (covers.empty()) {
+        result.Copy(*polygon);
+    } else {
+        auto cover = S2Polygon::DestructiveUnion(std::move(covers));
+        result.InitToDifference(*polygon, *cover);
+    }
+    return to_overlay(result);
+}
+
+Coverage::Coverage() : polygons_(std::make_unique<std::vector<std::unique_ptr<S2Polygon>>>()) {}
+
+void Coverage::add(const Polygons& polygons) {
+    for (int i = 0; i < polygons.index()->num_shape_ids(); ++i) {
+        auto polygon = polygons.s2polygon(i);
+        if (polygon) {
+            auto copy = std::make_unique<S2Polygon>();
+            copy->Copy(*polygon);
+            polygons_->push_back(std::move(copy));
+        }
+    }
+}
+
+size_t Coverage::size() const {
+    return polygons_->size();
+}
+
+std::unique_ptr<Overlay> Coverage::gaps() const {
+    if (polygons_->empty()) {
+        return std::make_unique<Overlay>();
+    }
+    std::vector<std::unique_ptr<S2Polygon>> copies;
+    for (const auto& polygon : *polygons_) {
+        auto copy = std::make_unique<S2Polygon>();
+        copy->Copy(*polygon);
+        copies.push_back(std::move(copy));
+    }
+    auto merged = S2Polygon::DestructiveUnion(std::move(copies));
+
+    // holes keep the union on their left, inverted they enclose the gap
+    std::vector<std::unique_ptr<S2Loop>> shells;
+    for (int i = 0; i < merged->num_loops(); ++i) {
+        const S2Loop* loop = merged->loop(i);
+        if (loop->is_hole()) {
+            auto shell = loop->Clone();
+            shell->Invert();
+            shells.push_back(std::move(shell));
+        }
+    }
+    S2Polygon result;
+    result.set_s2debug_override(S2Debug::DISABLE);
+    result.InitNested(std::move(shells));
+    return to_overlay(result);
+}
@@ -9,13 +9,28 @@
 
 #include "s2/s2polygon.h"
 #include "s2/mutable_s2shape_index.h"
+#include "s2/encoded_s2shape_index.h"
 
 #pragma GCC diagnostic pop
 
 #include <float.h>
+#include <stdint.h>
+#include <string>
 #include <vector>
 #include "error_codes.h"
 
+// cell_shape results for cells that do not resolve to a single shape
+constexpr int CELL_NONE = -1;
+constexpr int CELL_MIXED = -2;
+
+uint64_t cell_id(double lng, double lat, int level);
+
+// log lines of this library and S2 as "severity\tsource\tmessage", drained
+// into tracing by Rust
+void init_logging();
+std::string next_log();
+void push_log(const char* severity, const char* source, const std::string& message);
+
 class LngLat {
 public:
     LngLat() : lng_(DBL_MAX), lat_(DBL_MAX) {}
@@ -75,12 +90,23 @@ public:
                 for (size_t i = 0; i < lnglats.lnglats_->size() - 1; i++) {
                     auto& lnglat = (*lnglats.lnglats_)[i];
                     auto& lnglat1 = (*lnglats.lnglats_)[i+1];
-                    printf("%d %3.7f,%3.7f %3.7f,%3.7f\n", 
+                    char line[128];
+                    snprintf(line, sizeof(line), "invalid loop edge %zu %3.7f,%3.7f %3.7f,%3.7f", 
                         i, lnglat.lng_, lnglat.lat_, lnglat1.lng_, lnglat1.lat_);
+                    push_log("DEBUG", "polygon.h", line);
                 }
             }
 
-            return ErrorCode::FAILURE;
+            S2Error error;
+            loop_->FindValidationError(&error);
+            switch (error.code()) {
+            case S2Error::LOOP_SELF_INTERSECTION:
+                return ErrorCode::CROSSING_EDGES;
+            case S2Error::DUPLICATE_VERTICES:
+                return ErrorCode::DUPLICATE_VERTICES;
+            default:
+                return ErrorCode::FAILURE;
+            }
         }
 
         if (outer && loop_->GetCurvature() > 0) {
@@ -125,15 +151,92 @@ public:
     }
 };
 
+// area in square meters and loops of an overlay of polygons, the loops are
+// flattened into lnglats with the vertex count and area of every loop
+class Overlay {
+public:
+    Overlay() : area_(0), lnglats_(std::make_unique<std::vector<LngLat>>()),
+        loop_sizes_(std::make_unique<std::vector<int32_t>>()),
+        loop_areas_(std::make_unique<std::vector<double>>()) {}
+
+    double area() const {
+        return area_;
+    }
+
+    std::unique_ptr<std::vector<LngLat>> lnglats() const {
+        return std::make_unique<std::vector<LngLat>>(*lnglats_);
+    }
+
+    std::unique_ptr<std::vector<int32_t>> loop_sizes() const {
+        return std::make_unique<std::vector<int32_t>>(*loop_sizes_);
+    }
+
+    // negative for the holes
+    std::unique_ptr<std::vector<double>> loop_areas() const {
+        return std::make_unique<std::vector<double>>(*loop_areas_);
+    }
+
+    double area_;
+    std::unique_ptr<std::vector<LngLat>> lnglats_;
+    std::unique_ptr<std::vector<int32_t>> loop_sizes_;
+    std::unique_ptr<std::vector<double>> loop_areas_;
+};
+
+// interior covering cell of a shape as a leaf cell id range
+struct CoveringCell {
+    uint64_t min;
+    uint64_t max;
+    int shape;
+};
+
 class Polygons {
 public:
     Polygons();
     ~Polygons();
 
     ErrorCode add(Polygon polgon);
+    void build_covering(int max_cells, int max_level);
+    bool compact();
+    bool is_compact() const;
+    size_t covering_size() const;
+    size_t space_used() const;
     int search(double lng, double lat) const;
+    int search_exact(double lng, double lat) const;
     std::unique_ptr<SearchResult> search_polygon(double lng, double lat) const;
+    std::unique_ptr<SearchResult> polygon(int index) const;
+    int cell_shape(uint64_t cell_id) const;
+    double distance(double lng, double lat) const;
+
+    // overlay queries, empty or negative results in compact mode
+    double area(int index) const;
+    std::unique_ptr<std::vector<int32_t>> intersecting(int index, const Polygons& other) const;
+    std::unique_ptr<Overlay> intersection(int index, const Polygons& other, int other_index) const;
+    std::unique_ptr<Overlay> uncovered(int index, const Polygons& other) const;
 
 protected:
+    friend class Coverage;
+
+    const S2ShapeIndex* index() const;
+    const S2Polygon* s2polygon(int index) const;
+
     std::unique_ptr<MutableS2ShapeIndex> polygonsIndex_; // for rust, autocxx must use pointer!
-};
\ No newline at end of file
+    // compact mode, lax polygons encoded into a single buffer
+    std::unique_ptr<std::string> encoded_;
+    std::unique_ptr<EncodedS2ShapeIndex> encodedIndex_;
+    std::unique_ptr<std::vector<CoveringCell>> covering_;
+};
+
+// union of the shapes of several Polygons, e.g. the parts of a layer, for the
+// gaps the union leaves inside itself
+class Coverage {
+public:
+    Coverage();
+
+    void add(const Polygons& polygons);
+    size_t size() const;
+    // every hole of the union as a shell, islands inside a hole are ignored
+    std::unique_ptr<Overlay> gaps() const;
+
+protected:
+    std::unique_ptr<std::vector<std::unique_ptr<S2Polygon>>> polygons_; // pointer for autocxx
+};
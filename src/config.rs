@@ -1,17 +1,198 @@
+//! Service configuration, `Config.toml` layered with `TARANTULA_*`
+//! environment variables and `--set key=value` overrides.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use error_chain::bail;
 use std::collections::HashMap;
+use std::path::Path;
 use serde::Deserialize;
 
+const ENV_PREFIX: &str = "TARANTULA_";
+
 #[derive(Deserialize, Clone)]
 pub struct Config {
     pub search: Search,
     pub rest: Rest,
     pub grpc: Grpc,
+    #[serde(default)]
+    pub telemetry: Option<Telemetry>,
+    #[serde(default)]
+    pub log: Option<Log>,
+    #[serde(default)]
+    pub validate: Option<Validate>,
+    #[serde(default)]
+    pub versions: Option<Versions>,
+    /// seconds to drain in-flight requests on shutdown
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout_secs: u64,
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+impl Config {
+    /// Reads `path`, then applies the `TARANTULA_*` environment and `sets`, 
+    /// later layers win.
+    pub fn load(path: &Path, sets: &[String]) -> Result<Config> {
+        let toml_string = std::fs::read_to_string(path)
+            .chain_err(|| format!("failed to read config file {}", path.display()))?;
+        Config::layered(&toml_string, std::env::vars(), sets)
+            .chain_err(|| format!("invalid config {}", path.display()))
+    }
+
+    /// `toml_string` with the `TARANTULA_*` variables of `env` and the
+    /// `key=value` entries of `sets` applied in order.
+    pub fn layered(toml_string: &str, env: impl IntoIterator<Item = (String, String)>, 
+        sets: &[String]) -> Result<Config> {
+        let mut value: toml::Value = toml::from_str(toml_string)
+            .chain_err(|| "failed to parse toml")?;
+        // overrides of keys missing from the file that parsed as something
+        // other than a string, e.g. a numeric-looking `rest.admin_token`
+        let mut guessed = Vec::new();
+        for (key, raw) in env_overrides(env) {
+            if override_guesses(&value, &key, &raw) {
+                guessed.push((key.clone(), raw.clone()));
+            }
+            apply_override(&mut value, &key, &raw)
+                .chain_err(|| format!("environment {}{}", ENV_PREFIX, key.to_uppercase()))?;
+        }
+        for set in sets {
+            let Some((key, raw)) = set.split_once('=') else {
+                bail!("--set {} is not key=value", set);
+            };
+            let (key, raw) = (key.trim(), raw.trim());
+            if override_guesses(&value, key, raw) {
+                guessed.push((key.to_string(), raw.to_string()));
+            }
+            apply_override(&mut value, key, raw).chain_err(|| format!("--set {}", set))?;
+        }
+        match value.clone().try_into::<Config>() {
+            Ok(config) => Ok(config),
+            Err(e) if !guessed.is_empty() => {
+                // retry with the guessed overrides kept as the strings given
+                for (key, raw) in &guessed {
+                    insert(&mut value, key, toml::Value::String(raw.clone()))?;
+                }
+                value.try_into::<Config>().or(Err(e)).chain_err(|| "failed to parse config")
+            }
+            Err(e) => Err(e).chain_err(|| "failed to parse config"),
+        }
+    }
+
+    /// Problems that would fail or silently skip parts of the load, empty
+    /// when the config is consistent with the files under `shapefile.path`.
+    pub fn check(&self) -> Vec<String> {
+        let search = &self.search;
+        let mut problems = vec![];
+
+        search.hierarchies
+            .iter()
+            .chain(search.district_par.iter())
+            .chain(search.district_par_any.iter())
+            .filter(|name| !search.shapefile.attributes.contains_key(*name))
+            .for_each(|name| problems.push(format!("layer {} has no attributes entry", name)));
+
+        if !Path::new(&search.shapefile.path).is_dir() {
+            problems.push(format!("shapefile path {} is not a directory", search.shapefile.path));
+            return problems;
+        }
+
+        let districts = if search.discover {
+            match crate::discover::districts(search) {
+                Ok(districts) => districts,
+                Err(e) => {
+                    problems.push(format!("failed to discover districts: {}", e));
+                    return problems;
+                }
+            }
+        } else {
+            search.districts.clone()
+        };
+        if districts.is_empty() {
+            problems.push("no districts configured or discovered".to_string());
+        }
+
+        districts.iter().for_each(|district| {
+            match crate::discover::district_files(search, district) {
+                Ok(files) => files.missing.iter().for_each(|name| {
+                    problems.push(format!("district {} has no {} shapefile", district, name));
+                }),
+                Err(e) => problems.push(format!("district {}: {}", district, e)),
+            }
+        });
+        problems
+    }
+}
+
+/// `(key, value)` overrides of the `TARANTULA_*` variables, `__` separates
+/// the tables, e.g. `TARANTULA_REST__PORT` is `rest.port`.
+pub fn env_overrides(env: impl IntoIterator<Item = (String, String)>) -> Vec<(String, String)> {
+    let mut overrides: Vec<(String, String)> = env
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            Some((key.to_lowercase().replace("__", "."), value))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
+
+/// Sets the dotted `key` to `raw` parsed as a TOML value, or as a string
+/// when it is not one or the key already holds a string, creating the
+/// missing tables.
+pub fn apply_override(value: &mut toml::Value, key: &str, raw: &str) -> Result<()> {
+    let parsed = match lookup(value, key) {
+        Some(toml::Value::String(_)) => toml::Value::String(raw.to_string()),
+        _ => parse_raw(raw),
+    };
+    insert(value, key, parsed)
+}
+
+fn parse_raw(raw: &str) -> toml::Value {
+    toml::from_str::<toml::Table>(&format!("v = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| toml::Value::String(raw.to_string()))
+}
+
+fn lookup<'a>(value: &'a toml::Value, key: &str) -> Option<&'a toml::Value> {
+    key.split('.').try_fold(value, |value, part| value.get(part))
+}
+
+/// Whether the type of `raw` for `key` is only a guess: the key is missing
+/// from `value` and `raw` does not parse as a string.
+fn override_guesses(value: &toml::Value, key: &str, raw: &str) -> bool {
+    lookup(value, key).is_none() && !parse_raw(raw).is_str()
+}
+
+fn insert(value: &mut toml::Value, key: &str, parsed: toml::Value) -> Result<()> {
+    let mut path: Vec<&str> = key.split('.').collect();
+    let Some(last) = path.pop().filter(|last| !last.is_empty()) else {
+        bail!("empty key {}", key);
+    };
+    let mut table = value.as_table_mut().chain_err(|| "config is not a table")?;
+    for part in path {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .chain_err(|| format!("{} of {} is not a table", part, key))?;
+    }
+    table.insert(last.to_string(), parsed);
+    Ok(())
 }
 
 #[derive(Deserialize, Clone, Debug)]
 pub struct Attribute {
     pub level: i32,
     pub names: Vec<String>,
+    #[serde(default)]
+    pub code: Option<String>,
 }
 
 #[derive(Deserialize, Clone, Debug)]
@@ -23,18 +204,72 @@ pub struct Shapefile {
 #[derive(Deserialize, Clone, Debug)]
 pub struct Search {
     pub shapefile: Shapefile,
+    #[serde(default)]
     pub districts: Vec<String>,
+    #[serde(default)]
+    pub discover: bool,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
     pub hierarchies: Vec<String>,
     pub district_par: Vec<String>,
     pub district_par_any: Vec<String>,
     pub debug: bool,
     pub debug_name: String,
+    #[serde(default)]
+    pub parcel_rank: bool,
+    #[serde(default)]
+    pub cache: Option<Cache>,
+    #[serde(default)]
+    pub covering: Option<Covering>,
+    #[serde(default)]
+    pub derive_parents: bool,
+    #[serde(default)]
+    pub check_codes: bool,
+    #[serde(default)]
+    pub border_distance: f64,
+    #[serde(default)]
+    pub lazy: Option<LazyLoad>,
+    #[serde(default)]
+    pub compact: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Cache {
+    pub level: i32,
+    pub capacity: usize,
+    #[serde(default = "default_cache_shards")]
+    pub shards: usize,
+}
+
+fn default_cache_shards() -> usize {
+    16
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LazyLoad {
+    /// evict least recently used districts above this, 0 is unlimited
+    #[serde(default)]
+    pub memory_budget_mb: usize,
+    /// answer without lower levels while the district loads in background
+    #[serde(default)]
+    pub partial: bool,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Covering {
+    pub max_cells: i32,
+    pub max_level: i32,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct Rest {
     pub port: u16,
     pub host: String,
+    /// bearer token of the `/admin` routes, which are disabled without it
+    #[serde(default)]
+    pub admin_token: Option<String>,
 }
 
 #[derive(Deserialize, Clone)]
@@ -42,3 +277,91 @@ pub struct Grpc {
     pub port: u16,
     pub host: String,
 }
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Telemetry {
+    /// OTLP gRPC endpoint of the collector, e.g. http://localhost:4317
+    pub otlp_endpoint: String,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    #[serde(default = "default_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+fn default_service_name() -> String {
+    "tarantula".to_string()
+}
+
+fn default_sample_ratio() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Log {
+    /// tracing filter directive, e.g. "info" or "info,tarantula_s2::search=debug",
+    /// RUST_LOG overrides it
+    #[serde(default = "default_log_level")]
+    pub level: String,
+    #[serde(default)]
+    pub json: bool,
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+/// Most problems of each kind over the dataset before `validate` fails,
+/// `None` is unlimited.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Validate {
+    pub duplicate_vertices: Option<usize>,
+    pub crossing_edges: Option<usize>,
+    pub curvature: Option<usize>,
+    pub empty_rings: Option<usize>,
+    pub empty_attributes: Option<usize>,
+    pub invalid_loops: Option<usize>,
+}
+
+/// Older datasets served next to `search.shapefile.path`, picked per query
+/// with `version=` or `as_of=`.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Versions {
+    #[serde(default)]
+    pub snapshots: Vec<Snapshot>,
+    /// load a snapshot on its first query instead of at startup
+    #[serde(default)]
+    pub lazy: bool,
+    /// snapshots kept loaded, the least recently queried is unloaded, 0 is unlimited
+    #[serde(default)]
+    pub max_loaded: usize,
+    /// snapshots released this many days before the current dataset are not
+    /// served, 0 keeps all
+    #[serde(default)]
+    pub max_age_days: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub name: String,
+    /// converted shapefiles laid out like `search.shapefile.path`
+    pub path: String,
+    /// release date, YYYYMMDD or YYYY-MM-DD, the newest date in the file
+    /// names by default
+    #[serde(default)]
+    pub as_of: Option<String>,
+}
+
+impl Default for Validate {
+    // the loader drops duplicate vertices and empty rings on its own
+    fn default() -> Self {
+        Validate {
+            duplicate_vertices: None,
+            crossing_edges: Some(0),
+            curvature: Some(0),
+            empty_rings: None,
+            empty_attributes: Some(0),
+            invalid_loops: Some(0),
+        }
+    }
+}
@@ -0,0 +1,513 @@
+//! Changes between two releases, for the `diff` command.
+//!
+//! The features of every layer are matched by code within a district. Codes
+//! only in the new release are added and codes only in the old one removed. A
+//! matched feature is renamed when its name changed, and its geometry changed
+//! when the symmetric difference of the two polygons exceeds the area
+//! threshold. A feature whose polygon fails to load in either release is
+//! counted as invalid rather than added or removed. The changes are written as
+//! a GeoJSON changeset, and a sample of points can be looked up in both
+//! releases to count the answers the new release would change.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::config::{Attribute, Search as SearchConfig};
+use crate::discover::{layer_files, LayerFile};
+use crate::ffi;
+use crate::search::{Info, Search};
+use crate::utils::shape::load_features;
+use crate::validate::geometry;
+use autocxx::prelude::*;
+use core::pin::Pin;
+use geojson::{feature::Id, Feature, FeatureCollection, Geometry, JsonObject, Value};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// Changed points kept as examples in the report.
+pub const SAMPLES: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub status: Status,
+    pub layer: String,
+    pub district: String,
+    pub level: i32,
+    pub code: String,
+    pub old_name: Option<String>,
+    pub new_name: Option<String>,
+    pub old_area_m2: Option<f64>,
+    pub new_area_m2: Option<f64>,
+    /// area of the symmetric difference of the old and new polygons
+    pub changed_area_m2: f64,
+    pub renamed: bool,
+    pub geometry_changed: bool,
+    /// the new polygon, or the old one of a removed feature
+    #[serde(skip)]
+    pub geometry: Option<Value>,
+}
+
+impl Change {
+    /// New area minus old area, the area of an added or removed feature
+    /// counted from zero.
+    pub fn area_delta_m2(&self) -> f64 {
+        self.new_area_m2.unwrap_or_default() - self.old_area_m2.unwrap_or_default()
+    }
+
+    /// Codes are unique within a layer, so the id stays the same across
+    /// changesets of the same feature.
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.layer, self.code)
+    }
+
+    pub fn to_feature(&self) -> Feature {
+        let mut properties = JsonObject::new();
+        properties.insert("change".to_string(), match self.status {
+            Status::Added => "added",
+            Status::Removed => "removed",
+            Status::Changed => "changed",
+        }.into());
+        properties.insert("layer".to_string(), self.layer.clone().into());
+        properties.insert("district".to_string(), self.district.clone().into());
+        properties.insert("level".to_string(), self.level.into());
+        properties.insert("code".to_string(), self.code.clone().into());
+        properties.insert("old_name".to_string(), self.old_name.clone().into());
+        properties.insert("new_name".to_string(), self.new_name.clone().into());
+        properties.insert("old_area_m2".to_string(), self.old_area_m2.into());
+        properties.insert("new_area_m2".to_string(), self.new_area_m2.into());
+        properties.insert("area_delta_m2".to_string(), self.area_delta_m2().into());
+        properties.insert("changed_area_m2".to_string(), self.changed_area_m2.into());
+        properties.insert("renamed".to_string(), self.renamed.into());
+        properties.insert("geometry_changed".to_string(), self.geometry_changed.into());
+        Feature {
+            bbox: None,
+            geometry: self.geometry.clone().map(Geometry::new),
+            id: Some(Id::String(self.id())),
+            properties: Some(properties),
+            foreign_members: None,
+        }
+    }
+}
+
+/// Area of the symmetric difference of two polygons from their areas and
+/// the area of their intersection, and whether it is above `min_area_m2`.
+pub fn geometry_changed(old_area_m2: f64, new_area_m2: f64, intersection_m2: f64,
+    min_area_m2: f64) -> (f64, bool) {
+    let changed = (old_area_m2 + new_area_m2 - 2.0 * intersection_m2).max(0.0);
+    (changed, changed > min_area_m2)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerCounts {
+    pub layer: String,
+    pub level: i32,
+    pub old_features: usize,
+    pub new_features: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub renamed: usize,
+    pub geometry_changed: usize,
+    pub added_area_m2: f64,
+    pub removed_area_m2: f64,
+    /// sum of the area deltas of the features in both releases
+    pub changed_area_delta_m2: f64,
+    /// features without a code or with a code seen before in the same file set
+    pub skipped: usize,
+    /// features without a valid outer ring in either release, left out of
+    /// the matching
+    pub invalid: usize,
+}
+
+/// Level, code and name of a hit, what a client gets back for a point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Answer {
+    pub level: i32,
+    pub code: String,
+    pub name: String,
+}
+
+impl From<Info> for Answer {
+    fn from(info: Info) -> Self {
+        Answer { level: info.level, code: info.code, name: info.name }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PointChange {
+    pub lon: f64,
+    pub lat: f64,
+    pub old: Vec<Answer>,
+    pub new: Vec<Answer>,
+}
+
+/// Levels at which two answers differ, a level missing from one side
+/// included.
+pub fn differing_levels(old: &[Answer], new: &[Answer]) -> Vec<i32> {
+    let levels: BTreeSet<i32> = old.iter().chain(new.iter()).map(|answer| answer.level).collect();
+    levels
+        .into_iter()
+        .filter(|level| {
+            let at = |answers: &[Answer]| -> Vec<(String, String)> { answers
+                .iter()
+                .filter(|answer| answer.level == *level)
+                .map(|answer| (answer.code.clone(), answer.name.clone()))
+                .collect() };
+            at(old) != at(new)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PointImpact {
+    pub points: usize,
+    pub changed: usize,
+    /// points the lookup failed on in either release
+    pub failed: usize,
+    /// changed points by level, a point counts at every level that differs
+    pub levels: BTreeMap<i32, usize>,
+    /// the first changed points, up to `SAMPLES`
+    pub samples: Vec<PointChange>,
+}
+
+impl PointImpact {
+    pub fn add(&mut self, lon: f64, lat: f64, old: Vec<Answer>, new: Vec<Answer>) {
+        self.points += 1;
+        let levels = differing_levels(&old, &new);
+        if levels.is_empty() {
+            return;
+        }
+        self.changed += 1;
+        levels.into_iter().for_each(|level| *self.levels.entry(level).or_default() += 1);
+        if self.samples.len() < SAMPLES {
+            self.samples.push(PointChange { lon, lat, old, new });
+        }
+    }
+
+    pub fn changed_ratio(&self) -> f64 {
+        if self.points == 0 {
+            return 0.0;
+        }
+        self.changed as f64 / self.points as f64
+    }
+}
+
+/// Looks `points` up in both releases on the rayon pool.
+pub fn point_impact(old: &Search, new: &Search, points: &[(f64, f64)]) -> PointImpact {
+    let answers = |search: &Search, lon: f64, lat: f64| -> std::result::Result<Vec<Answer>, String> {
+        let mut answers: Vec<Answer> = search
+            .search(lon, lat)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(Answer::from)
+            .collect();
+        answers.sort_by_key(|answer| answer.level);
+        Ok(answers)
+    };
+    let looked_up: Vec<_> = points
+        .par_iter()
+        .map(|(lon, lat)| (*lon, *lat, answers(old, *lon, *lat), answers(new, *lon, *lat)))
+        .collect();
+    let mut impact = PointImpact::default();
+    for (lon, lat, old, new) in looked_up {
+        match (old, new) {
+            (Ok(old), Ok(new)) => impact.add(lon, lat, old, new),
+            (Err(e), _) | (_, Err(e)) => {
+                tracing::debug!(lon, lat, "diff lookup failed: {}", e);
+                impact.points += 1;
+                impact.failed += 1;
+            }
+        }
+    }
+    impact
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub old: String,
+    pub new: String,
+    pub min_area_m2: f64,
+    pub layers: Vec<LayerCounts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<PointImpact>,
+    /// shapefiles that could not be read
+    pub errors: Vec<String>,
+    #[serde(skip)]
+    pub changes: Vec<Change>,
+}
+
+impl Report {
+    /// Adds the feature counts of a layer of a district and its changes.
+    pub fn add(&mut self, layer: &str, level: i32, old_features: usize, new_features: usize,
+        skipped: usize, invalid: usize, changes: Vec<Change>) {
+        let index = match self.layers.iter().position(|counts| counts.layer == layer) {
+            Some(index) => index,
+            None => {
+                self.layers.push(LayerCounts { layer: layer.to_string(), level, ..Default::default() });
+                self.layers.len() - 1
+            }
+        };
+        let counts = &mut self.layers[index];
+        counts.old_features += old_features;
+        counts.new_features += new_features;
+        counts.skipped += skipped;
+        counts.invalid += invalid;
+        for change in changes.iter() {
+            match change.status {
+                Status::Added => {
+                    counts.added += 1;
+                    counts.added_area_m2 += change.area_delta_m2();
+                }
+                Status::Removed => {
+                    counts.removed += 1;
+                    counts.removed_area_m2 -= change.area_delta_m2();
+                }
+                Status::Changed => {
+                    counts.renamed += change.renamed as usize;
+                    counts.geometry_changed += change.geometry_changed as usize;
+                    counts.changed_area_delta_m2 += change.area_delta_m2();
+                }
+            }
+        }
+        self.changes.extend(changes);
+        self.layers.sort_by(|a, b| a.level.cmp(&b.level).then_with(|| a.layer.cmp(&b.layer)));
+    }
+
+    /// One row per layer, tab separated, and the point impact after it.
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "layer\tlevel\told\tnew\tadded\tremoved\trenamed\tgeometry_changed\t\
+            added_area_m2\tremoved_area_m2\tchanged_area_delta_m2\tskipped\tinvalid")?;
+        for counts in self.layers.iter() {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.1}\t{:.1}\t{:.1}\t{}\t{}",
+                counts.layer, counts.level, counts.old_features, counts.new_features,
+                counts.added, counts.removed, counts.renamed, counts.geometry_changed,
+                counts.added_area_m2, counts.removed_area_m2, counts.changed_area_delta_m2,
+                counts.skipped, counts.invalid)?;
+        }
+        if let Some(impact) = self.points.as_ref() {
+            writeln!(out)?;
+            writeln!(out, "points\tchanged\tratio\tfailed")?;
+            writeln!(out, "{}\t{}\t{:.4}\t{}", impact.points, impact.changed,
+                impact.changed_ratio(), impact.failed)?;
+            for (level, changed) in impact.levels.iter() {
+                writeln!(out, "level {}\t{}", level, changed)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_geojson(&self, out: &mut impl Write) -> Result<()> {
+        let collection = FeatureCollection {
+            bbox: None,
+            features: self.changes.iter().map(|change| change.to_feature()).collect(),
+            foreign_members: None,
+        };
+        serde_json::to_writer(out, &collection).chain_err(|| "write geojson")
+    }
+}
+
+// a feature of a release, `index` is its shape in `Release::parts[part]`
+struct Loaded {
+    part: usize,
+    index: i32,
+    name: String,
+    area_m2: f64,
+    geometry: Value,
+}
+
+// the files of a district layer of a release, features by code
+#[derive(Default)]
+struct Release {
+    parts: Vec<Pin<Box<ffi::Polygons>>>,
+    features: BTreeMap<String, Loaded>,
+    // codes of the features without a valid polygon
+    invalid: BTreeSet<String>,
+    skipped: usize,
+}
+
+/// Compares every layer of every district of `old` and `new`, directories
+/// laid out like `search.shapefile.path`. The files of a district layer are
+/// loaded from both releases together and dropped before the next one.
+pub fn diff(config: &SearchConfig, old: &str, new: &str, min_area_m2: f64) -> Result<Report> {
+    let files = |path: &str| -> Result<Vec<LayerFile>> {
+        let mut config = config.clone();
+        config.shapefile.path = path.to_string();
+        layer_files(&config).chain_err(|| format!("failed to list the shapefiles of {}", path))
+    };
+    let mut groups: BTreeMap<(String, String), (Vec<LayerFile>, Vec<LayerFile>)> = BTreeMap::new();
+    files(old)?.into_iter().for_each(|file| groups
+        .entry((file.district.clone(), file.layer.clone()))
+        .or_default()
+        .0
+        .push(file));
+    files(new)?.into_iter().for_each(|file| groups
+        .entry((file.district.clone(), file.layer.clone()))
+        .or_default()
+        .1
+        .push(file));
+
+    let mut report = Report {
+        old: old.to_string(),
+        new: new.to_string(),
+        min_area_m2,
+        ..Default::default()
+    };
+    for ((district, layer), (old_files, new_files)) in groups {
+        let Some(attribute) = config.shapefile.attributes.get(&layer) else {
+            report.errors.push(format!("layer {} has no attributes entry", layer));
+            continue;
+        };
+        let _span = tracing::info_span!("diff", %district, %layer).entered();
+        let (old_release, new_release) = rayon::join(
+            || load_release(&old_files, attribute),
+            || load_release(&new_files, attribute));
+        let (old_release, mut errors) = old_release;
+        let (new_release, new_errors) = new_release;
+        errors.extend(new_errors);
+        report.errors.extend(errors);
+
+        let change = |status: Status, code: &str| Change {
+            status,
+            layer: layer.clone(),
+            district: district.clone(),
+            level: attribute.level,
+            code: code.to_string(),
+            old_name: None,
+            new_name: None,
+            old_area_m2: None,
+            new_area_m2: None,
+            changed_area_m2: 0.0,
+            renamed: false,
+            geometry_changed: false,
+            geometry: None,
+        };
+        // a feature invalid in one release is neither added nor removed
+        let codes: BTreeSet<&String> = old_release.features.keys()
+            .chain(new_release.features.keys())
+            .filter(|code| !old_release.invalid.contains(*code)
+                && !new_release.invalid.contains(*code))
+            .collect();
+        let changes: Vec<Change> = codes
+            .into_par_iter()
+            .filter_map(|code| match (old_release.features.get(code), new_release.features.get(code)) {
+                (Some(before), Some(after)) => {
+                    let intersection = old_release.parts[before.part]
+                        .intersection(c_int(before.index), &new_release.parts[after.part],
+                            c_int(after.index))
+                        .area();
+                    let (changed_area_m2, reshaped) = geometry_changed(before.area_m2,
+                        after.area_m2, intersection, min_area_m2);
+                    let renamed = before.name != after.name;
+                    if !renamed && !reshaped {
+                        return None;
+                    }
+                    Some(Change {
+                        old_name: Some(before.name.clone()),
+                        new_name: Some(after.name.clone()),
+                        old_area_m2: Some(before.area_m2),
+                        new_area_m2: Some(after.area_m2),
+                        changed_area_m2,
+                        renamed,
+                        geometry_changed: reshaped,
+                        geometry: Some(after.geometry.clone()),
+                        ..change(Status::Changed, code)
+                    })
+                }
+                (None, Some(after)) => Some(Change {
+                    new_name: Some(after.name.clone()),
+                    new_area_m2: Some(after.area_m2),
+                    changed_area_m2: after.area_m2,
+                    geometry: Some(after.geometry.clone()),
+                    ..change(Status::Added, code)
+                }),
+                (Some(before), None) => Some(Change {
+                    old_name: Some(before.name.clone()),
+                    old_area_m2: Some(before.area_m2),
+                    changed_area_m2: before.area_m2,
+                    geometry: Some(before.geometry.clone()),
+                    ..change(Status::Removed, code)
+                }),
+                (None, None) => None,
+            })
+            .collect();
+
+        tracing::info!(%district, %layer, old = old_release.features.len(),
+            new = new_release.features.len(), changes = changes.len(), "diff");
+        let invalid = old_release.invalid.union(&new_release.invalid).count();
+        report.add(&layer, attribute.level, old_release.features.len(),
+            new_release.features.len(), old_release.skipped + new_release.skipped, invalid,
+            changes);
+    }
+    Ok(report)
+}
+
+fn load_release(files: &[LayerFile], attribute: &Attribute) -> (Release, Vec<String>) {
+    let loaded: Vec<std::result::Result<Part, String>> = files
+        .par_iter()
+        .map(|file| load_part(&file.path, attribute))
+        .collect();
+    let mut release = Release::default();
+    let mut errors = vec![];
+    for part in loaded {
+        let (polys, features) = match part {
+            Ok(part) => part,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        let index = release.parts.len();
+        release.parts.push(polys);
+        for (code, feature) in features {
+            if code.is_empty() || release.features.contains_key(&code)
+                || release.invalid.contains(&code) {
+                release.skipped += 1;
+                continue;
+            }
+            match feature {
+                Some(feature) => {
+                    release.features.insert(code, Loaded { part: index, ..feature });
+                }
+                None => {
+                    release.invalid.insert(code);
+                }
+            }
+        }
+    }
+    (release, errors)
+}
+
+// a shapefile loaded into an index, with the code of every shape, `None`
+// for the shapes without a valid polygon
+type Part = (Pin<Box<ffi::Polygons>>, Vec<(String, Option<Loaded>)>);
+
+fn load_part(path: &str, attribute: &Attribute) -> std::result::Result<Part, String> {
+    let mut polys = ffi::Polygons::new().within_box();
+    let features = load_features(path, attribute, Some(polys.as_mut()))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let features = features
+        .into_iter()
+        .enumerate()
+        .map(|(shape, feature)| {
+            let loaded = feature.valid().then(|| Loaded {
+                part: 0,
+                index: shape as i32,
+                name: feature.name.clone(),
+                area_m2: polys.area(c_int(shape as i32)),
+                geometry: geometry(&feature.shape),
+            });
+            (feature.code, loaded)
+        })
+        .collect();
+    Ok((polys, features))
+}
@@ -0,0 +1,146 @@
+//! Discovery of district folders under `shapefile.path`.
+//!
+//! Every subdirectory holding at least one of the configured layers is a
+//! district, so adding a district only needs its folder dropped in.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::config::Search as SearchConfig;
+use std::path::Path;
+
+/// Layer files of one district folder.
+#[derive(Debug, Clone, Default)]
+pub struct DistrictFiles {
+    pub district: String,
+    pub layers: Vec<String>,
+    pub parts: Vec<(String, usize)>,
+    pub missing: Vec<String>,
+}
+
+impl DistrictFiles {
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty() && self.parts.iter().all(|(_, count)| *count == 0)
+    }
+}
+
+pub fn district_files(config: &SearchConfig, district: &str) -> Result<DistrictFiles> {
+    let dir = Path::new(&config.shapefile.path).join(district);
+    let mut files = DistrictFiles {
+        district: district.to_string(),
+        ..Default::default()
+    };
+
+    config.hierarchies
+        .iter()
+        .chain(config.district_par.iter())
+        .for_each(|name| {
+            if dir.join(format!("{}.shp", name)).exists() {
+                files.layers.push(name.to_string());
+            } else {
+                files.missing.push(name.to_string());
+            }
+        });
+
+    for name in config.district_par_any.iter() {
+        let pattern = format!("{}/{}*.shp", dir.display(), name);
+        let count = glob::glob(&pattern)
+            .chain_err(|| format!("failed to read glob pattern {}", pattern))?
+            .filter_map(|entry| entry.ok())
+            .count();
+        if count == 0 {
+            files.missing.push(name.to_string());
+        }
+        files.parts.push((name.to_string(), count));
+    }
+
+    Ok(files)
+}
+
+/// A shapefile of a configured layer.
+#[derive(Debug, Clone)]
+pub struct LayerFile {
+    pub layer: String,
+    pub district: String,
+    pub path: String,
+}
+
+/// Shapefiles of every configured layer and district, parts sorted by name.
+/// Missing files are left to `check-config`.
+pub fn layer_files(config: &SearchConfig) -> Result<Vec<LayerFile>> {
+    let districts = if config.discover {
+        districts(config).chain_err(|| "failed to discover districts")?
+    } else {
+        config.districts.clone()
+    };
+    let mut files = vec![];
+    for district in districts.iter() {
+        let dir = Path::new(&config.shapefile.path).join(district);
+        config.hierarchies
+            .iter()
+            .chain(config.district_par.iter())
+            .for_each(|name| {
+                let path = dir.join(format!("{}.shp", name));
+                if path.exists() {
+                    files.push(LayerFile {
+                        layer: name.to_string(),
+                        district: district.to_string(),
+                        path: path.display().to_string(),
+                    });
+                }
+            });
+        for name in config.district_par_any.iter() {
+            let pattern = format!("{}/{}*.shp", dir.display(), name);
+            let mut paths: Vec<String> = glob::glob(&pattern)
+                .chain_err(|| format!("failed to read glob pattern {}", pattern))?
+                .filter_map(|entry| entry.ok())
+                .map(|path| path.display().to_string())
+                .collect();
+            paths.sort();
+            files.extend(paths.into_iter().map(|path| LayerFile {
+                layer: name.to_string(),
+                district: district.to_string(),
+                path,
+            }));
+        }
+    }
+    Ok(files)
+}
+
+/// Districts found under `shapefile.path`, filtered by `include` and `exclude`.
+pub fn districts(config: &SearchConfig) -> Result<Vec<String>> {
+    let entries = std::fs::read_dir(&config.shapefile.path)
+        .chain_err(|| format!("failed to read {}", config.shapefile.path))?;
+
+    let mut districts = vec![];
+    for entry in entries {
+        let entry = entry.chain_err(|| "dir entry")?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let Some(district) = entry.file_name().to_str().map(|s| s.to_string()) else {
+            continue;
+        };
+        if !config.include.is_empty() && !config.include.contains(&district) {
+            tracing::info!(%district, "discover skip, not included");
+            continue;
+        }
+        if config.exclude.contains(&district) {
+            tracing::info!(%district, "discover skip, excluded");
+            continue;
+        }
+
+        let files = district_files(config, &district)?;
+        if files.is_empty() {
+            tracing::info!(%district, "discover skip, no layer files");
+            continue;
+        }
+        tracing::info!(%district, layers = ?files.layers, parts = ?files.parts, 
+            missing = ?files.missing, "discover");
+        districts.push(district);
+    }
+
+    districts.sort();
+    Ok(districts)
+}
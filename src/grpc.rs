@@ -1,11 +1,20 @@
 
 use tonic::{transport::Server, Response, Status};
 use grpc::service_server::{Service, ServiceServer};
-use grpc::{TarantulaReq, TarantulaReply};
+use grpc::{TarantulaReq, TarantulaReply, LoadStatusReq, LoadStatusReply, DatasetReq, DatasetReply};
+use grpc::{VersionsReq, VersionsReply};
+use tonic_health::ServingStatus;
+use std::future::Future;
 use std::net::ToSocketAddrs;
+use tokio_stream::wrappers::TcpListenerStream;
 use tower_http::trace::{TraceLayer, DefaultMakeSpan, DefaultOnResponse};
 use tracing::Level;
-use crate::search::GLOBAL_SEARCH;
+use crate::search::{Search, GLOBAL_SEARCH};
+use crate::versions::{self, ErrorKind as VersionErrorKind};
+use std::sync::Arc;
+use crate::metrics::METRICS;
+use std::time::Instant;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 pub mod grpc {
     tonic::include_proto!("grpc"); 
@@ -18,42 +27,231 @@ pub struct GrpcService {}
 impl Service for GrpcService {
     async fn tarantula(&self, request: tonic::Request<TarantulaReq>) 
         -> Result<Response<TarantulaReply>, Status> { 
-        let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        let start = Instant::now();
+        let span = tracing::info_span!("Tarantula", 
+            lon = request.get_ref().lon, 
+            lat = request.get_ref().lat);
+        span.set_parent(crate::telemetry::metadata_context(request.metadata()));
+        let result = match pick_version(request.get_ref()).await {
+            Ok(picked) => span.in_scope(|| self.tarantula_reply(request.get_ref(), picked)),
+            Err(status) => Err(status),
+        };
+        observe("Tarantula", start, &result);
+        result
+    }
+
+    async fn load_status(&self, request: tonic::Request<LoadStatusReq>) 
+        -> Result<Response<LoadStatusReply>, Status> { 
+        let start = Instant::now();
+        let span = tracing::info_span!("LoadStatus");
+        span.set_parent(crate::telemetry::metadata_context(request.metadata()));
+        let result = span.in_scope(|| self.load_status_reply().map(versioned));
+        observe("LoadStatus", start, &result);
+        result
+    }
+
+    async fn dataset(&self, request: tonic::Request<DatasetReq>) 
+        -> Result<Response<DatasetReply>, Status> { 
+        let start = Instant::now();
+        let span = tracing::info_span!("Dataset");
+        span.set_parent(crate::telemetry::metadata_context(request.metadata()));
+        let result = span.in_scope(|| self.dataset_reply().map(versioned));
+        observe("Dataset", start, &result);
+        result
+    }
+
+    async fn versions(&self, request: tonic::Request<VersionsReq>) 
+        -> Result<Response<VersionsReply>, Status> { 
+        let start = Instant::now();
+        let span = tracing::info_span!("Versions");
+        span.set_parent(crate::telemetry::metadata_context(request.metadata()));
+        let result = span.in_scope(|| Ok(versioned(self.versions_reply())));
+        observe("Versions", start, &result);
+        result
+    }
+}
+
+// the snapshot asked for with version or as_of, a lazy load runs off the runtime
+async fn pick_version(request: &TarantulaReq) -> Result<Option<Arc<Search>>, Status> {
+    if request.version.is_empty() && request.as_of.is_empty() {
+        return Ok(None);
+    }
+    let (version, as_of) = (request.version.clone(), request.as_of.clone());
+    tokio::task::spawn_blocking(move || versions::pick(Some(&version), Some(&as_of)))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| match e.kind() {
+            VersionErrorKind::NotFound(_) => Status::not_found(e.to_string()),
+            VersionErrorKind::Loading(_) => Status::unavailable(e.to_string()),
+            VersionErrorKind::Failed(_) => Status::internal(e.to_string()),
+            _ => Status::invalid_argument(e.to_string()),
+        })
+}
+
+// clients cache against the version of the dataset that answered
+fn versioned<T>(reply: T) -> Response<T> {
+    versioned_by(reply, unsafe { GLOBAL_SEARCH.as_ref() })
+}
+
+fn versioned_by<T>(reply: T, search: Option<&Search>) -> Response<T> {
+    let mut response = Response::new(reply);
+    if let Some(search) = search {
+        if let Ok(value) = search.dataset_version().parse() {
+            response.metadata_mut().insert(crate::rest::DATASET_VERSION, value);
+        }
+    }
+    response
+}
+
+impl GrpcService {
+    fn tarantula_reply(&self, request: &TarantulaReq, picked: Option<Arc<Search>>) 
+        -> Result<Response<TarantulaReply>, Status> {
+        let current = unsafe { &GLOBAL_SEARCH.as_ref() }
             .ok_or_else(|| Status::internal("search not initialized"))?;
-        let results 
-            = search.search(request.get_ref().lon, request.get_ref().lat);
+        let search: &Search = picked.as_deref().unwrap_or(current);
+        let results = search.search(request.lon, request.lat);
         match results {
             Ok(res) => {
-                let reply = TarantulaReply {
+                Ok(versioned_by(TarantulaReply {
                     infos: res
                         .into_iter()
                         .map(|info| grpc::Info {
                             district: info.district,
                             level: info.level,
                             name: info.name,
+                            fallback: info.fallback,
+                            code: info.code,
+                            eng_name: info.eng_name,
                         })
                         .collect(), 
-                };
-                return Ok(Response::new(reply));
+                    loading_levels: search.loading_levels(),
+                }, Some(search)))
             }
             Err(e) => {
-                return Err(Status::internal(format!("search error: {}", e)));
+                Err(Status::internal(format!("search error: {}", e)))
             }
         }
     }
+
+    fn load_status_reply(&self) -> Result<LoadStatusReply, Status> {
+        let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+            .ok_or_else(|| Status::unavailable("search not initialized"))?;
+        let status = search.load_status();
+        Ok(LoadStatusReply {
+            loaded: status.loaded,
+            partial: status.partial,
+            error: status.error.unwrap_or_default(),
+            polygons: status.polygons as u64,
+            layers: status.layers.into_iter().map(layer_status).collect(),
+        })
+    }
+
+    fn versions_reply(&self) -> VersionsReply {
+        let current = unsafe { GLOBAL_SEARCH.as_ref() };
+        VersionsReply {
+            versions: versions::list(current)
+                .into_iter()
+                .map(|version| grpc::Version {
+                    name: version.name,
+                    as_of: version.as_of.unwrap_or_default(),
+                    path: version.path,
+                    current: version.current,
+                    loaded: version.loaded,
+                    version: version.version.unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    fn dataset_reply(&self) -> Result<DatasetReply, Status> {
+        let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+            .ok_or_else(|| Status::unavailable("search not initialized"))?;
+        let manifest = search.manifest()
+            .ok_or_else(|| Status::unavailable("dataset still loading"))?;
+        Ok(DatasetReply {
+            version: manifest.version,
+            releases: manifest.releases,
+            loaded_at: manifest.loaded_at,
+            load_secs: manifest.load_secs,
+            polygons: manifest.polygons as u64,
+            files: manifest.files
+                .into_iter()
+                .map(|file| grpc::DatasetFile {
+                    name: file.name,
+                    layer: file.layer,
+                    district: file.district,
+                    release: file.release.unwrap_or_default(),
+                    size: file.size,
+                    modified: file.modified,
+                    sha256: file.sha256,
+                    polygons: file.polygons.unwrap_or_default() as u64,
+                    rejected: file.rejected.unwrap_or_default() as u64,
+                })
+                .collect(),
+            layers: manifest.layers.into_iter().map(layer_status).collect(),
+        })
+    }
+}
+
+fn layer_status(layer: crate::search::LayerStatus) -> grpc::LayerStatus {
+    grpc::LayerStatus {
+        layer: layer.layer,
+        level: layer.level,
+        polygons: layer.polygons as u64,
+        rejected: layer.rejected as u64,
+        districts: layer.districts as u64,
+        failed: layer.failed as u64,
+    }
+}
+
+fn observe<T>(endpoint: &str, start: Instant, result: &Result<Response<T>, Status>) {
+    let status = match result {
+        Ok(_) => "Ok".to_string(),
+        Err(status) => format!("{:?}", status.code()),
+    };
+    METRICS.observe_request("grpc", endpoint, &status, start.elapsed());
+}
+
+// grpc.health.v1 follows the load, serving once the dataset is fully loaded
+async fn report_health(mut reporter: tonic_health::server::HealthReporter) {
+    let service_name = <ServiceServer<GrpcService> as tonic::server::NamedService>::NAME;
+    let mut last = None;
+    loop {
+        let status = match unsafe { &GLOBAL_SEARCH.as_ref() } {
+            Some(search) if search.load_status().ready(crate::progress::Gate::Full) 
+                => ServingStatus::Serving,
+            _ => ServingStatus::NotServing,
+        };
+        if last != Some(status) {
+            tracing::info!(%status, "grpc health");
+            reporter.set_service_status("", status).await;
+            reporter.set_service_status(service_name, status).await;
+            last = Some(status);
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
 }
 
-pub async fn start_server() {
+/// Binds the listener before the index loads, so a port clash fails fast.
+pub async fn bind() -> std::io::Result<tokio::net::TcpListener> {
     let config = crate::GLOBAL_CONFIG.grpc.clone();
     let addr = (config.host, config.port)
-        .to_socket_addrs()
-        .expect("invalid host or port")
+        .to_socket_addrs()?
         .next()
-        .expect("unable to resolve address");
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, 
+            "unable to resolve address"))?;
+    tokio::net::TcpListener::bind(addr).await
+}
 
+/// Serves until `shutdown` resolves, then drains the in-flight calls.
+pub async fn serve(listener: tokio::net::TcpListener, 
+    shutdown: impl Future<Output = ()>) -> Result<(), String> {
     let service = GrpcService::default();
+    let (reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(report_health(reporter));
 
-    println!("grpc server listening on {}", addr);
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tracing::info!(%addr, "grpc server listening");
 
     let trace_layer = TraceLayer::new_for_grpc()
         .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
@@ -61,8 +259,9 @@ pub async fn start_server() {
 
     Server::builder()
         .layer(trace_layer)
+        .add_service(health_service)
         .add_service(ServiceServer::new(service))
-        .serve(addr)
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
         .await
-        .unwrap()
-}
\ No newline at end of file
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,140 @@
+//! Consistency of the admin levels and the parcels of the loaded index.
+//!
+//! Every region is overlaid with the regions of the nearest level above it
+//! that it intersects. It should lie inside exactly one of them and its code
+//! should start with the code of that parent, and every parent should be
+//! covered by the nearest level below it. Area fractions up to `tolerance`
+//! are slivers of the source data and not reported.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Issue {
+    /// part of the region is outside every parent
+    Outside,
+    /// the region lies in more than one parent
+    Straddling,
+    /// the code does not start with the code of the dominant parent
+    CodeMismatch,
+    /// part of the parent is not covered by the level below
+    Gap,
+}
+
+/// A parent intersecting a region and the fraction of the region inside it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Parent {
+    pub level: i32,
+    pub code: String,
+    pub name: String,
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub issue: Issue,
+    pub district: String,
+    pub level: i32,
+    pub code: String,
+    pub name: String,
+    pub area_m2: f64,
+    /// outside or uncovered fraction, or the fraction in the dominant parent
+    pub fraction: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<Parent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct LevelCounts {
+    pub level: i32,
+    pub regions: usize,
+    pub outside: usize,
+    pub straddling: usize,
+    pub code_mismatch: usize,
+    pub gap: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub levels: Vec<LevelCounts>,
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    /// Adds the `regions` checked at `level` and their findings.
+    pub fn add(&mut self, level: i32, regions: usize, findings: Vec<Finding>) {
+        let index = match self.levels.iter().position(|counts| counts.level == level) {
+            Some(index) => index,
+            None => {
+                self.levels.push(LevelCounts { level, ..Default::default() });
+                self.levels.sort_by_key(|counts| counts.level);
+                self.levels.iter().position(|counts| counts.level == level).unwrap_or_default()
+            }
+        };
+        let counts = &mut self.levels[index];
+        counts.regions += regions;
+        findings.iter().for_each(|finding| match finding.issue {
+            Issue::Outside => counts.outside += 1,
+            Issue::Straddling => counts.straddling += 1,
+            Issue::CodeMismatch => counts.code_mismatch += 1,
+            Issue::Gap => counts.gap += 1,
+        });
+        self.findings.extend(findings);
+    }
+
+    /// One row per level and its issue counts, tab separated.
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "level\tregions\toutside\tstraddling\tcode_mismatch\tgap")?;
+        for counts in self.levels.iter() {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}", counts.level, counts.regions,
+                counts.outside, counts.straddling, counts.code_mismatch, counts.gap)?;
+        }
+        Ok(())
+    }
+
+    /// One JSON line per finding.
+    pub fn write_findings(&self, out: &mut impl Write) -> Result<()> {
+        for finding in self.findings.iter() {
+            serde_json::to_writer(&mut *out, finding).chain_err(|| "write findings")?;
+            writeln!(out).chain_err(|| "write findings")?;
+        }
+        Ok(())
+    }
+}
+
+/// Issues of a region with the given `parents` and the fraction reported
+/// with each, `code` is the code of the region.
+pub fn classify(code: &str, parents: &[Parent], tolerance: f64) -> Vec<(Issue, f64)> {
+    let mut issues = vec![];
+    let inside: f64 = parents.iter().map(|parent| parent.fraction).sum::<f64>().min(1.0);
+    if 1.0 - inside > tolerance {
+        issues.push((Issue::Outside, 1.0 - inside));
+    }
+
+    let dominant = parents.iter().max_by(|a, b| a.fraction.total_cmp(&b.fraction));
+    let significant = parents.iter().filter(|parent| parent.fraction > tolerance).count();
+    if let Some(parent) = dominant {
+        if significant > 1 {
+            issues.push((Issue::Straddling, parent.fraction));
+        }
+        if !code.is_empty() && !parent.code.is_empty() && !code.starts_with(&parent.code) {
+            issues.push((Issue::CodeMismatch, parent.fraction));
+        }
+    }
+    issues
+}
+
+/// Uncovered fraction of a parent of `area` with `covered` of it under the
+/// level below, `None` up to `tolerance`.
+pub fn gap(area: f64, covered: f64, tolerance: f64) -> Option<f64> {
+    if area <= 0.0 {
+        return None;
+    }
+    let fraction = ((area - covered) / area).max(0.0);
+    (fraction > tolerance).then_some(fraction)
+}
@@ -0,0 +1,41 @@
+//! String interning for polygon attributes.
+//!
+//! District codes and many names repeat across the features of a layer, the
+//! compact storage mode keeps a single shared copy of each.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+pub struct Interner {
+    strings: Mutex<HashSet<Arc<str>>>,
+}
+
+impl Interner {
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let Ok(mut strings) = self.strings.lock() else {
+            return Arc::from(s);
+        };
+        if let Some(interned) = strings.get(s) {
+            return interned.clone();
+        }
+        let interned: Arc<str> = Arc::from(s);
+        strings.insert(interned.clone());
+        interned
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.lock().map(|strings| strings.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.strings
+            .lock()
+            .map(|strings| strings.iter().map(|s| s.len()).sum())
+            .unwrap_or(0)
+    }
+}
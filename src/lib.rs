@@ -21,6 +21,11 @@ include_cpp! {
     generate!("LngLats")
     generate!("Loop")
     generate!("SearchResult")
+    generate!("Overlay")
+    generate!("Coverage")
+    generate!("cell_id")
+    generate!("init_logging")
+    generate!("next_log")
 }
 
 #[cfg(docsrs)]
@@ -32,8 +37,24 @@ pub mod ffi {
         pub fn new() -> Self { Self }
         pub fn within_box(self) -> Self { self }
         pub fn add(&mut self, _value: impl std::any::Any) {}
+        pub fn build_covering(&mut self, _max_cells: autocxx::c_int, _max_level: autocxx::c_int) {}
+        pub fn covering_size(&self) -> usize { 0 }
+        pub fn compact(&mut self) -> bool { true }
+        pub fn is_compact(&self) -> bool { false }
+        pub fn space_used(&self) -> usize { 0 }
         pub fn search(&self, _lon: f64, _lat: f64) -> i32 { -1 }
+        pub fn search_exact(&self, _lon: f64, _lat: f64) -> i32 { -1 }
         pub fn search_polygon(&self, _lon: f64, _lat: f64) -> SearchResult { SearchResult::default() }
+        pub fn polygon(&self, _index: autocxx::c_int) -> SearchResult { SearchResult::default() }
+        pub fn cell_shape(&self, _cell_id: u64) -> i32 { -2 }
+        pub fn distance(&self, _lon: f64, _lat: f64) -> f64 { f64::MAX }
+        pub fn area(&self, _index: autocxx::c_int) -> f64 { -1.0 }
+        pub fn intersecting(&self, _index: autocxx::c_int, _other: &Polygons) -> Vec<i32> { vec![] }
+        pub fn intersection(&self, _index: autocxx::c_int, _other: &Polygons, 
+            _other_index: autocxx::c_int) -> Overlay { Overlay::default() }
+        pub fn uncovered(&self, _index: autocxx::c_int, _other: &Polygons) -> Overlay { 
+            Overlay::default() 
+        }
     }
 
     #[derive(Default)]
@@ -73,6 +94,10 @@ pub mod ffi {
         pub fn init(&mut self, _lnglats: LngLats, _outer: bool, _debug: bool) -> i32 { 4 }
     }
 
+    pub fn cell_id(_lng: f64, _lat: f64, _level: autocxx::c_int) -> u64 { 0 }
+    pub fn init_logging() {}
+    pub fn next_log() -> String { String::new() }
+
     #[derive(Default, Clone)]
     pub struct SearchResult {
         index: i32,
@@ -83,10 +108,53 @@ pub mod ffi {
         pub fn index(&self) -> i32 { self.index }
         pub fn lnglats(&self) -> &[LngLat] { &self.lnglats }
     }
+
+    #[derive(Default)]
+    pub struct Overlay {
+        area: f64,
+        lnglats: Vec<LngLat>,
+        loop_sizes: Vec<i32>,
+        loop_areas: Vec<f64>,
+    }
+
+    impl Overlay {
+        pub fn area(&self) -> f64 { self.area }
+        pub fn lnglats(&self) -> &[LngLat] { &self.lnglats }
+        pub fn loop_sizes(&self) -> &[i32] { &self.loop_sizes }
+        pub fn loop_areas(&self) -> &[f64] { &self.loop_areas }
+    }
+
+    #[derive(Default)]
+    pub struct Coverage;
+
+    impl Coverage {
+        pub fn new() -> Self { Self }
+        pub fn within_box(self) -> Self { self }
+        pub fn add(&mut self, _polygons: &Polygons) {}
+        pub fn size(&self) -> usize { 0 }
+        pub fn gaps(&self) -> Overlay { Overlay::default() }
+    }
 }
 
 pub mod config;
 pub mod search;
+pub mod lookup;
+pub mod autocomplete;
+pub mod cache;
+pub mod intern;
+pub mod progress;
+pub mod metrics;
+pub mod telemetry;
+pub mod logging;
+pub mod discover;
+pub mod manifest;
+pub mod versions;
+pub mod query;
+pub mod annotate;
+pub mod validate;
+pub mod hierarchy;
+pub mod overlap;
+pub mod diff;
 pub mod rest;
 
 #[cfg(not(docsrs))]
@@ -94,42 +162,173 @@ pub mod grpc;
 
 #[cfg(docsrs)]
 pub mod grpc {
-    pub async fn start_server() {}
+    pub async fn bind() -> std::io::Result<tokio::net::TcpListener> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+    pub async fn serve(_listener: tokio::net::TcpListener, 
+        _shutdown: impl std::future::Future<Output = ()>) -> Result<(), String> {
+        Ok(())
+    }
 }
 pub mod utils;
 
 pub use search::{Info, Search};
 pub use config::Config;
 
-use std::fs;
-use once_cell::sync::Lazy;
-use toml;
+use once_cell::sync::{Lazy, OnceCell};
+
+static CONFIG: OnceCell<config::Config> = OnceCell::new();
 
+/// Config of the services, set by the CLI or read from `./Config.toml` and
+/// the `TARANTULA_*` environment on first use.
 pub static GLOBAL_CONFIG: Lazy<config::Config> = Lazy::new(|| {
-    let config_file_path = "Config.toml";
-    let toml_string = fs::read_to_string(config_file_path)
-        .expect("failed to read config file");
-    toml::from_str(&toml_string).expect("failed to parse toml")
+    CONFIG.get().cloned().unwrap_or_else(|| {
+        config::Config::load(std::path::Path::new("Config.toml"), &[])
+            .expect("failed to load config")
+    })
 });
 
-pub async fn run() {
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use error_chain::bail;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Uses `config` instead of `./Config.toml`, must be called before anything
+/// reads `GLOBAL_CONFIG`.
+pub fn set_config(config: config::Config) -> Result<()> {
+    if Lazy::get(&GLOBAL_CONFIG).is_some() || CONFIG.set(config).is_err() {
+        bail!("config already set");
+    }
+    Ok(())
+}
+
+/// Starts both servers and runs until SIGTERM/SIGINT, an error if a listener
+/// cannot bind, a server stops on its own or the drain times out.
+pub async fn run() -> Result<()> {
+    logging::init(&GLOBAL_CONFIG);
+
+    let rest_listener = rest::bind().chain_err(|| "failed to bind rest listener")?;
+    let grpc_listener = grpc::bind().await.chain_err(|| "failed to bind grpc listener")?;
+
     search::initialize_global_search();
+    if let Some(versions) = GLOBAL_CONFIG.versions.clone() {
+        versions::initialize(versions, GLOBAL_CONFIG.search.clone());
+    }
+
+    let partial = GLOBAL_CONFIG.search.lazy.as_ref().map(|lazy| lazy.partial);
+    if partial == Some(true) {
+        std::thread::spawn(|| {
+            if let Some(search) = unsafe { search::GLOBAL_SEARCH.as_ref() } {
+                search.serve_pending();
+            }
+        });
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let mut rest_server = tokio::spawn(
+        rest::serve(rest_listener, wait_shutdown(shutdown_rx.clone())));
+    let mut grpc_server = tokio::spawn(
+        grpc::serve(grpc_listener, wait_shutdown(shutdown_rx)));
+
+    let mut failed = None;
+    let (mut rest_done, mut grpc_done) = (false, false);
+    tokio::select! {
+        _ = terminate() => tracing::info!("shutdown signal received, draining"),
+        result = &mut rest_server => {
+            rest_done = true;
+            failed = Some(server_error("rest", result));
+        }
+        result = &mut grpc_server => {
+            grpc_done = true;
+            failed = Some(server_error("grpc", result));
+        }
+    }
+    if let Some(error) = &failed {
+        tracing::error!(%error, "server stopped, shutting down");
+    }
+    let _ = shutdown_tx.send(true);
 
-    let rest_server = tokio::spawn(async {
-        rest::start_server().await;
-    });
+    let drain = async {
+        let mut errors = vec![];
+        if !rest_done {
+            if let Err(e) = (&mut rest_server).await.map_err(|e| e.to_string()).and_then(|r| r) {
+                errors.push(format!("rest server: {}", e));
+            }
+        }
+        if !grpc_done {
+            if let Err(e) = (&mut grpc_server).await.map_err(|e| e.to_string()).and_then(|r| r) {
+                errors.push(format!("grpc server: {}", e));
+            }
+        }
+        errors
+    };
+    let timeout = Duration::from_secs(GLOBAL_CONFIG.shutdown_timeout_secs);
+    match tokio::time::timeout(timeout, drain).await {
+        Ok(errors) => errors.into_iter().for_each(|error| {
+            tracing::error!(%error, "server failed while draining");
+            failed.get_or_insert(error);
+        }),
+        Err(_) => {
+            tracing::warn!(?timeout, "drain timed out, dropping in-flight requests");
+            failed.get_or_insert(format!("drain timed out after {:?}", timeout));
+        }
+    }
 
-    let grpc_server = tokio::spawn(async {
-        grpc::start_server().await;
-    });
+    telemetry::shutdown();
+    if let Some(error) = failed {
+        bail!(error);
+    }
+    tracing::info!("shutdown complete");
+    Ok(())
+}
 
-    let (rest_result, grpc_result) = tokio::join!(rest_server, grpc_server);
+/// Why a server task ended before shutdown was requested.
+pub fn server_error(name: &str, 
+    result: std::result::Result<std::result::Result<(), String>, tokio::task::JoinError>) 
+    -> String {
+    match result {
+        Ok(Ok(())) => format!("{} server stopped unexpectedly", name),
+        Ok(Err(e)) => format!("{} server failed: {}", name, e),
+        Err(e) => format!("{} server panicked: {}", name, e),
+    }
+}
 
-    if let Err(e) = rest_result {
-        eprintln!("error in rest server: {:?}", e);
+async fn wait_shutdown(mut rx: watch::Receiver<bool>) {
+    while !*rx.borrow() {
+        if rx.changed().await.is_err() {
+            return;
+        }
     }
+}
+
+async fn terminate() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "failed to listen for ctrl-c");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let sigterm = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let sigterm = std::future::pending::<()>();
 
-    if let Err(e) = grpc_result {
-        eprintln!("error in grpc server: {:?}", e);
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = sigterm => {},
     }
 }
@@ -0,0 +1,63 @@
+//! Tracing subscriber of the services.
+//!
+//! Installed once from `run` with the `[log]` level and text or JSON lines
+//! output. Lines logged by the C++ side and by S2 are buffered natively and
+//! drained into the same stream under the `s2` target.
+
+use crate::config::Config;
+use crate::ffi;
+use std::time::Duration;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+pub fn init(config: &Config) {
+    let (level, json) = config.log
+        .as_ref()
+        .map(|log| (log.level.as_str(), log.json))
+        .unwrap_or(("info", false));
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(level))
+        .unwrap_or_else(|_| EnvFilter::new("info"));
+
+    let result = tracing_subscriber::registry()
+        .with(filter)
+        .with(json.then(|| tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)))
+        .with((!json).then(tracing_subscriber::fmt::layer))
+        .with(crate::telemetry::layer(config.telemetry.as_ref()))
+        .try_init();
+    if let Err(e) = result {
+        eprintln!("failed to init tracing: {}", e);
+        return;
+    }
+
+    ffi::init_logging();
+    std::thread::spawn(|| loop {
+        drain_native();
+        std::thread::sleep(Duration::from_millis(500));
+    });
+}
+
+/// Emits the buffered C++ and S2 log lines, the loader calls it after every
+/// shapefile so the lines land in the span of the file.
+pub fn drain_native() {
+    loop {
+        let line = ffi::next_log().to_string();
+        if line.is_empty() {
+            return;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let severity = fields.next().unwrap_or_default();
+        let source = fields.next().unwrap_or_default();
+        let message = fields.next().unwrap_or_default();
+        match severity {
+            "FATAL" | "ERROR" => tracing::error!(target: "s2", source, "{}", message),
+            "WARNING" => tracing::warn!(target: "s2", source, "{}", message),
+            "DEBUG" => tracing::debug!(target: "s2", source, "{}", message),
+            _ => tracing::info!(target: "s2", source, "{}", message),
+        }
+    }
+}
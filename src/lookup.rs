@@ -0,0 +1,217 @@
+//! Forward lookup of regions by name.
+//!
+//! The index keeps the Korean and English names of the administrative layers
+//! loaded by `Search` and answers exact, prefix and fuzzy queries. A query may
+//! carry parent regions separated by `>` ("세종 > 조치원읍") which are matched
+//! against the code prefix of every candidate.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Layer {
+    Hierarchy(usize),
+    DistrictPar(String, usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub code: String,
+    pub level: i32,
+    pub name: String,
+    pub eng_name: String,
+    pub district: String,
+    pub centroid: (f64, f64),
+    pub layer: Layer,
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchType {
+    Exact,
+    Prefix,
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegionMatch {
+    pub code: String,
+    pub level: i32,
+    pub name: String,
+    pub eng_name: String,
+    pub district: String,
+    pub centroid: (f64, f64),
+    pub parents: Vec<String>,
+    pub match_type: MatchType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Vec<(f64, f64)>>,
+}
+
+#[derive(Default)]
+pub struct NameIndex {
+    regions: Vec<Region>,
+    keys: Vec<(String, usize)>,
+    codes: HashMap<String, usize>,
+    code_lengths: BTreeSet<usize>,
+}
+
+pub fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+pub fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost)
+                .min(prev[j + 1] + 1)
+                .min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
+impl NameIndex {
+    pub fn new(regions: Vec<Region>) -> Self {
+        let mut keys = vec![];
+        let mut codes = HashMap::new();
+        let mut code_lengths = BTreeSet::new();
+        regions.iter().enumerate().for_each(|(idx, region)| {
+            [&region.name, &region.eng_name]
+                .iter()
+                .map(|name| normalize(name))
+                .filter(|key| !key.is_empty())
+                .for_each(|key| keys.push((key, idx)));
+            if !region.code.is_empty() {
+                codes.entry(region.code.clone()).or_insert(idx);
+                code_lengths.insert(region.code.len());
+            }
+        });
+        keys.sort();
+        keys.dedup();
+
+        Self { regions, keys, codes, code_lengths }
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn region(&self, idx: usize) -> Option<&Region> {
+        self.regions.get(idx)
+    }
+
+    pub fn by_code(&self, code: &str) -> Option<&Region> {
+        self.codes.get(code).map(|idx| &self.regions[*idx])
+    }
+
+    /// Regions whose code is a prefix of `region.code`, top down.
+    pub fn parents(&self, region: &Region) -> Vec<&Region> {
+        self.parents_of(&region.code)
+    }
+
+    /// Regions whose code is a prefix of `code`, top down.
+    pub fn parents_of(&self, code: &str) -> Vec<&Region> {
+        self.code_lengths
+            .iter()
+            .filter(|len| **len < code.len())
+            .filter_map(|len| code.get(..*len))
+            .filter_map(|prefix| self.by_code(prefix))
+            .collect()
+    }
+
+    /// Exact and prefix matches of a single name, the best match per region.
+    fn find(&self, key: &str) -> HashMap<usize, MatchType> {
+        let mut found = HashMap::new();
+        let start = self.keys.partition_point(|(k, _)| k.as_str() < key);
+        self.keys[start..]
+            .iter()
+            .take_while(|(k, _)| k.starts_with(key))
+            .for_each(|(k, idx)| {
+                let match_type = if k == key { MatchType::Exact } else { MatchType::Prefix };
+                let entry = found.entry(*idx).or_insert(match_type);
+                *entry = (*entry).min(match_type);
+            });
+        found
+    }
+
+    fn find_fuzzy(&self, key: &str) -> HashMap<usize, MatchType> {
+        let key: Vec<char> = key.chars().collect();
+        let max_distance = (key.len() / 4).max(1);
+        self.keys
+            .iter()
+            .filter(|(k, _)| {
+                let k: Vec<char> = k.chars().collect();
+                k.len().abs_diff(key.len()) <= max_distance
+                    && levenshtein(&key, &k) <= max_distance
+            })
+            .map(|(_, idx)| (*idx, MatchType::Fuzzy))
+            .collect()
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Vec<(&Region, MatchType)> {
+        let mut segments: Vec<String> = query
+            .split('>')
+            .map(normalize)
+            .filter(|s| !s.is_empty())
+            .collect();
+        let Some(target) = segments.pop() else {
+            return vec![];
+        };
+
+        let mut found = self.find(&target);
+        if found.is_empty() {
+            found = self.find_fuzzy(&target);
+        }
+
+        let parent_codes: Vec<HashSet<&str>> = segments
+            .iter()
+            .map(|segment| {
+                let mut parents = self.find(segment);
+                if parents.is_empty() {
+                    parents = self.find_fuzzy(segment);
+                }
+                parents.keys()
+                    .map(|idx| self.regions[*idx].code.as_str())
+                    .collect()
+            })
+            .collect();
+
+        let mut matches: Vec<(&Region, MatchType)> = found
+            .into_iter()
+            .map(|(idx, match_type)| (&self.regions[idx], match_type))
+            .filter(|(region, _)| {
+                parent_codes.iter().all(|codes| {
+                    codes.iter().any(|code| {
+                        code.len() < region.code.len() && region.code.starts_with(code)
+                    })
+                })
+            })
+            .collect();
+
+        matches.sort_by(|a, b| {
+            a.1.cmp(&b.1)
+                .then(a.0.level.cmp(&b.0.level))
+                .then(a.0.name.len().cmp(&b.0.name.len()))
+                .then(a.0.code.cmp(&b.0.code))
+        });
+        matches.truncate(limit);
+        matches
+    }
+}
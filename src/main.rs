@@ -1,11 +1,391 @@
-
 //! Tarantula search service entry point.
 //!
-//! This binary builds the search index and starts the REST and gRPC servers.
+//! `serve` builds the search index and starts the REST and gRPC servers,
+//! `check-config` validates the config against the shapefiles, `query`
+//! prints the hierarchy of points, `annotate` appends the region codes to
+//! a point file, `validate` reports the polygons the loader would reject,
+//! `check-hierarchy` the regions outside their parents, `overlaps` the
+//! overlaps and gaps within a layer and `diff` the changes between two
+//! releases, all without starting the servers.
+
+use clap::{Args, Parser, Subcommand};
+use std::io::BufRead;
+use std::path::PathBuf;
+use tarantula_s2::annotate::{self, FileFormat};
+use tarantula_s2::query::{self, Format, Printer};
+use tarantula_s2::{run, set_config, Config, Search};
+
+#[derive(Parser)]
+#[command(name = "tarantula", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Load the index and serve the REST and gRPC apis
+    Serve(ConfigArgs),
+    /// Check that every layer has an attributes entry and its shapefiles exist
+    CheckConfig(ConfigArgs),
+    /// Print the hierarchy at a point, or at every `lon,lat` line of stdin
+    Query(QueryArgs),
+    /// Append sido/sigungu/emd/li/parcel code and name columns to a point file
+    Annotate(AnnotateArgs),
+    /// Report the polygons and attributes the loader would reject or repair
+    Validate(ValidateArgs),
+    /// Report regions outside or straddling their parents and parents with gaps
+    CheckHierarchy(CheckHierarchyArgs),
+    /// Report overlapping features and gaps within every layer
+    Overlaps(OverlapsArgs),
+    /// Report the features added, removed, renamed or reshaped between two releases
+    Diff(DiffArgs),
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    #[arg(long, requires = "lat", allow_negative_numbers = true)]
+    lon: Option<f64>,
+    #[arg(long, requires = "lon", allow_negative_numbers = true)]
+    lat: Option<f64>,
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+    /// Load this directory of converted shapefiles instead of `search.shapefile.path`
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<String>,
+}
 
-use tarantula_s2::run;
+#[derive(Args)]
+struct ValidateArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Write the offending features with their problems to this GeoJSON file
+    #[arg(long, value_name = "FILE")]
+    geojson: Option<PathBuf>,
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<String>,
+}
+
+#[derive(Args)]
+struct CheckHierarchyArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Area fraction up to which a region outside its parent or a gap is ignored
+    #[arg(long, default_value_t = 0.01)]
+    tolerance: f64,
+    /// Write the findings to this file, one JSON object per line
+    #[arg(long, value_name = "FILE")]
+    findings: Option<PathBuf>,
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<String>,
+}
+
+#[derive(Args)]
+struct OverlapsArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Overlaps and gaps up to this many square meters are ignored
+    #[arg(long, default_value_t = 1.0)]
+    min_area: f64,
+    /// Write the overlaps and gaps with their ids and source features to this GeoJSON file
+    #[arg(long, value_name = "FILE")]
+    geojson: Option<PathBuf>,
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<String>,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Directory of the current release, laid out like `search.shapefile.path`
+    old: String,
+    /// Directory of the new release
+    new: String,
+    /// Geometry changes up to this many square meters are ignored
+    #[arg(long, default_value_t = 1.0)]
+    min_area: f64,
+    /// Write the changed features with their old and new names and areas to this GeoJSON file
+    #[arg(long, value_name = "FILE")]
+    geojson: Option<PathBuf>,
+    /// Look up the `lon,lat` lines of this file in both releases and count the changed answers
+    #[arg(long, value_name = "FILE")]
+    points: Option<PathBuf>,
+    /// Print the report as JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Args)]
+struct AnnotateArgs {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Input file, `-` is stdin for csv and ndjson
+    input: PathBuf,
+    /// Output file, `-` is stdout for csv and ndjson
+    #[arg(long, short)]
+    output: PathBuf,
+    /// Format of the input, from the extension by default
+    #[arg(long, value_enum)]
+    input_format: Option<FileFormat>,
+    /// Format of the output, from the extension by default
+    #[arg(long, value_enum)]
+    output_format: Option<FileFormat>,
+    #[arg(long, default_value = "lon")]
+    lon_column: String,
+    #[arg(long, default_value = "lat")]
+    lat_column: String,
+    /// Rows read, looked up and written at a time, bounds the memory
+    #[arg(long, default_value_t = 10_000)]
+    batch_size: usize,
+    #[arg(long, value_name = "DIR")]
+    snapshot: Option<String>,
+}
+
+#[derive(Args, Default)]
+struct ConfigArgs {
+    /// Config file, overridden by TARANTULA_* variables, e.g. TARANTULA_REST__PORT
+    #[arg(long, short, default_value = "Config.toml")]
+    config: PathBuf,
+    /// Override a config value, e.g. --set search.debug=true
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<String>,
+}
+
+impl ConfigArgs {
+    fn load(&self) -> Config {
+        Config::load(&self.config, &self.set).unwrap_or_else(|e| exit(e))
+    }
+}
+
+fn exit(error: impl std::fmt::Display) -> ! {
+    eprintln!("tarantula: {}", error);
+    std::process::exit(1);
+}
 
 #[tokio::main]
 async fn main() {
-    run().await;
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Serve(args)) => serve(args).await,
+        Some(Command::CheckConfig(args)) => check_config(args),
+        Some(Command::Query(args)) => query(args),
+        Some(Command::Annotate(args)) => annotate(args),
+        Some(Command::Validate(args)) => validate(args),
+        Some(Command::CheckHierarchy(args)) => check_hierarchy(args),
+        Some(Command::Overlaps(args)) => overlaps(args),
+        Some(Command::Diff(args)) => diff(args),
+        None => serve(ConfigArgs { config: "Config.toml".into(), ..Default::default() }).await,
+    }
+}
+
+async fn serve(args: ConfigArgs) {
+    if let Err(e) = set_config(args.load()) {
+        exit(e);
+    }
+    if let Err(e) = run().await {
+        exit(e);
+    }
+}
+
+fn check_config(args: ConfigArgs) {
+    let config = args.load();
+    let problems = config.check();
+    if problems.is_empty() {
+        println!("{}: ok", args.config.display());
+        return;
+    }
+    problems.iter().for_each(|problem| println!("{}: {}", args.config.display(), problem));
+    exit(format!("{} problems", problems.len()));
+}
+
+fn load_search(args: &ConfigArgs, snapshot: Option<&str>) -> Search {
+    load_search_with(args, snapshot, |_| {})
+}
+
+fn load_search_with(args: &ConfigArgs, snapshot: Option<&str>,
+    adjust: impl FnOnce(&mut tarantula_s2::config::Search)) -> Search {
+    let mut config = args.load().search;
+    adjust(&mut config);
+    if let Some(snapshot) = snapshot {
+        config.shapefile.path = snapshot.to_string();
+    }
+    // every level up front, lazy loading only pays off in a long running server
+    config.lazy = None;
+    let mut search = Search::new(config).unwrap_or_else(|e| exit(e));
+    search.load().unwrap_or_else(|e| exit(format!("failed to load search: {}", e)));
+    search
+}
+
+fn query(args: QueryArgs) {
+    let search = load_search(&args.config, args.snapshot.as_deref());
+    let mut printer = Printer::new(args.format, std::io::stdout().lock());
+    let mut print = |lon: f64, lat: f64| {
+        query::query(&search, lon, lat)
+            .and_then(|result| printer.print(&result))
+            .unwrap_or_else(|e| exit(e));
+    };
+
+    if let (Some(lon), Some(lat)) = (args.lon, args.lat) {
+        print(lon, lat);
+    } else {
+        for (number, line) in std::io::stdin().lock().lines().enumerate() {
+            let line = line.unwrap_or_else(|e| exit(e));
+            match query::parse_point(&line) {
+                Some(Ok((lon, lat))) => print(lon, lat),
+                Some(Err(e)) => exit(format!("line {}: {}", number + 1, e)),
+                None => {}
+            }
+        }
+    }
+    printer.finish().unwrap_or_else(|e| exit(e));
+}
+
+fn file_format(path: &std::path::Path, format: Option<FileFormat>) -> FileFormat {
+    format
+        .or_else(|| FileFormat::from_path(path))
+        .unwrap_or_else(|| exit(format!("unknown format of {}, use --input-format or --output-format",
+            path.display())))
+}
+
+fn annotate(args: AnnotateArgs) {
+    let input_format = file_format(&args.input, args.input_format);
+    let output_format = file_format(&args.output, args.output_format);
+    let search = load_search(&args.config, args.snapshot.as_deref());
+
+    let started = std::time::Instant::now();
+    let mut reader = annotate::open_reader(&args.input, input_format, args.batch_size)
+        .unwrap_or_else(|e| exit(e));
+    let writer = annotate::open_writer(&args.output, output_format)
+        .unwrap_or_else(|e| exit(e));
+    let summary = annotate::annotate(&search, reader.as_mut(), writer,
+        (&args.lon_column, &args.lat_column), args.batch_size)
+        .unwrap_or_else(|e| exit(e));
+    eprintln!("annotated {} of {} rows in {:?}, {} not found, {} invalid, {} failed",
+        summary.annotated, summary.rows, started.elapsed(),
+        summary.not_found, summary.invalid, summary.failed);
+}
+
+fn validate(args: ValidateArgs) {
+    let config = args.config.load();
+    let mut search = config.search;
+    if let Some(snapshot) = args.snapshot {
+        search.shapefile.path = snapshot;
+    }
+    let report = tarantula_s2::validate::validate(&search, args.geojson.is_some())
+        .unwrap_or_else(|e| exit(e));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_else(|e| exit(e)));
+    } else {
+        report.write_table(&mut std::io::stdout().lock()).unwrap_or_else(|e| exit(e));
+    }
+    report.errors.iter().for_each(|error| eprintln!("{}", error));
+
+    if let Some(path) = args.geojson {
+        let file = std::fs::File::create(&path)
+            .unwrap_or_else(|e| exit(format!("failed to create {}: {}", path.display(), e)));
+        report.write_geojson(&mut std::io::BufWriter::new(file)).unwrap_or_else(|e| exit(e));
+        eprintln!("{} offending features written to {}", report.features.len(), path.display());
+    }
+
+    let violations = report.violations(&config.validate.unwrap_or_default());
+    if !violations.is_empty() {
+        exit(violations.join(", "));
+    }
+}
+
+fn check_hierarchy(args: CheckHierarchyArgs) {
+    // the overlays need the polygons the compact index drops
+    let search = load_search_with(&args.config, args.snapshot.as_deref(),
+        |config| config.compact = false);
+    let report = search.check_hierarchy(args.tolerance).unwrap_or_else(|e| exit(e));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_else(|e| exit(e)));
+    } else {
+        report.write_table(&mut std::io::stdout().lock()).unwrap_or_else(|e| exit(e));
+    }
+
+    if let Some(path) = args.findings {
+        let file = std::fs::File::create(&path)
+            .unwrap_or_else(|e| exit(format!("failed to create {}: {}", path.display(), e)));
+        report.write_findings(&mut std::io::BufWriter::new(file)).unwrap_or_else(|e| exit(e));
+        eprintln!("{} findings written to {}", report.findings.len(), path.display());
+    }
+
+    if !report.findings.is_empty() {
+        exit(format!("{} hierarchy findings", report.findings.len()));
+    }
+}
+
+fn overlaps(args: OverlapsArgs) {
+    let mut search = args.config.load().search;
+    if let Some(snapshot) = args.snapshot {
+        search.shapefile.path = snapshot;
+    }
+    let report = tarantula_s2::overlap::overlaps(&search, args.min_area)
+        .unwrap_or_else(|e| exit(e));
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_else(|e| exit(e)));
+    } else {
+        report.write_table(&mut std::io::stdout().lock()).unwrap_or_else(|e| exit(e));
+    }
+    report.errors.iter().for_each(|error| eprintln!("{}", error));
+
+    if let Some(path) = args.geojson {
+        let file = std::fs::File::create(&path)
+            .unwrap_or_else(|e| exit(format!("failed to create {}: {}", path.display(), e)));
+        report.write_geojson(&mut std::io::BufWriter::new(file)).unwrap_or_else(|e| exit(e));
+        eprintln!("{} overlaps and gaps written to {}", report.problems.len(), path.display());
+    }
+}
+
+fn diff(args: DiffArgs) {
+    let config = args.config.load().search;
+    let mut report = tarantula_s2::diff::diff(&config, &args.old, &args.new, args.min_area)
+        .unwrap_or_else(|e| exit(e));
+
+    if let Some(path) = args.points.as_ref() {
+        let file = std::fs::File::open(path)
+            .unwrap_or_else(|e| exit(format!("failed to open {}: {}", path.display(), e)));
+        let mut points = vec![];
+        for (number, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.unwrap_or_else(|e| exit(e));
+            match query::parse_point(&line) {
+                Some(Ok(point)) => points.push(point),
+                Some(Err(e)) => exit(format!("{}:{}: {}", path.display(), number + 1, e)),
+                None => {}
+            }
+        }
+        let old = load_search(&args.config, Some(args.old.as_str()));
+        let new = load_search(&args.config, Some(args.new.as_str()));
+        report.points = Some(tarantula_s2::diff::point_impact(&old, &new, &points));
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_else(|e| exit(e)));
+    } else {
+        report.write_table(&mut std::io::stdout().lock()).unwrap_or_else(|e| exit(e));
+    }
+    report.errors.iter().for_each(|error| eprintln!("{}", error));
+
+    if let Some(path) = args.geojson {
+        let file = std::fs::File::create(&path)
+            .unwrap_or_else(|e| exit(format!("failed to create {}: {}", path.display(), e)));
+        report.write_geojson(&mut std::io::BufWriter::new(file)).unwrap_or_else(|e| exit(e));
+        eprintln!("{} changes written to {}", report.changes.len(), path.display());
+    }
 }
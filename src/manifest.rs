@@ -0,0 +1,174 @@
+//! Manifest of the dataset an instance serves.
+//!
+//! Every file of the configured shapefiles, sidecars included, is listed
+//! with its size and SHA-256 when the load finishes. The version combines
+//! the newest release date in the file names with a hash of the file
+//! hashes, so it changes with any file and clients can cache against it.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::discover::LayerFile;
+use crate::search::LayerStatus;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Sidecars hashed with every `.shp`, when present.
+const SIDECARS: [&str; 5] = ["shp", "shx", "dbf", "prj", "cpg"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// path relative to `shapefile.path`
+    pub name: String,
+    pub layer: String,
+    pub district: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub release: Option<String>,
+    pub size: u64,
+    /// modification time, unix seconds
+    pub modified: u64,
+    pub sha256: String,
+    /// polygons indexed and rejected, on the loaded `.shp` entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub polygons: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: String,
+    /// release dates of the file names, oldest first
+    pub releases: Vec<String>,
+    /// end of the load, unix seconds
+    pub loaded_at: u64,
+    pub load_secs: f64,
+    pub polygons: usize,
+    pub files: Vec<FileEntry>,
+    pub layers: Vec<LayerStatus>,
+}
+
+impl Manifest {
+    pub fn new(mut files: Vec<FileEntry>, loaded_at: u64, load_secs: f64) -> Self {
+        files.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut releases: Vec<String> = files.iter().filter_map(|file| file.release.clone()).collect();
+        releases.sort();
+        releases.dedup();
+        Manifest {
+            version: version(&files),
+            releases,
+            loaded_at,
+            load_secs,
+            files,
+            ..Default::default()
+        }
+    }
+
+    /// Swaps the entries of `layers` of `district` for `entries` after a reload.
+    pub fn replace_district(&mut self, district: &str, layers: &[String], entries: Vec<FileEntry>) {
+        let mut files: Vec<FileEntry> = self.files
+            .drain(..)
+            .filter(|file| file.district != district || !layers.contains(&file.layer))
+            .collect();
+        files.extend(entries);
+        *self = Manifest::new(files, self.loaded_at, self.load_secs);
+    }
+}
+
+/// Newest release date and the first 12 hex digits of a hash over the
+/// names and hashes of `files`, independent of their order.
+pub fn version(files: &[FileEntry]) -> String {
+    let mut keys: Vec<(&str, &str)> = files
+        .iter()
+        .map(|file| (file.name.as_str(), file.sha256.as_str()))
+        .collect();
+    keys.sort();
+    let mut hasher = Sha256::new();
+    keys.iter().for_each(|(name, sha256)| {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(sha256.as_bytes());
+        hasher.update([b'\n']);
+    });
+    let digest = format!("{:x}", hasher.finalize());
+    let release = files
+        .iter()
+        .filter_map(|file| file.release.as_deref())
+        .max()
+        .unwrap_or("unknown");
+    format!("{}-{}", release, &digest[..12])
+}
+
+/// Seconds since the unix epoch.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Release date of a file name like `AL_D002_36_20250504` or its split
+/// parts `AL_D002_36_20250504_part3`, an 8 digit field of the stem.
+pub fn release_date(path: &str) -> Option<String> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    stem.split('_')
+        .rev()
+        .find(|field| field.len() == 8 && field.chars().all(|c| c.is_ascii_digit()))
+        .map(|date| date.to_string())
+}
+
+/// Entries of `files` and their sidecars, hashed on the rayon pool. Names
+/// are relative to `root`.
+pub fn file_entries(root: &str, files: &[LayerFile]) -> Result<Vec<FileEntry>> {
+    let paths: Vec<(&LayerFile, std::path::PathBuf)> = files
+        .iter()
+        .flat_map(|file| SIDECARS
+            .iter()
+            .map(move |extension| (file, Path::new(&file.path).with_extension(extension))))
+        .filter(|(_, path)| path.exists())
+        .collect();
+    paths
+        .par_iter()
+        .map(|(file, path)| {
+            let metadata = std::fs::metadata(path)
+                .chain_err(|| format!("failed to stat {}", path.display()))?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            let name = path.strip_prefix(root).unwrap_or(path).display().to_string();
+            Ok(FileEntry {
+                release: release_date(&name),
+                name,
+                layer: file.layer.clone(),
+                district: file.district.clone(),
+                size: metadata.len(),
+                modified,
+                sha256: sha256(path)?,
+                polygons: None,
+                rejected: None,
+            })
+        })
+        .collect()
+}
+
+fn sha256(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)
+        .chain_err(|| format!("failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buffer).chain_err(|| format!("failed to read {}", path.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
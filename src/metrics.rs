@@ -0,0 +1,176 @@
+//! Prometheus metrics of the services, the search and the load.
+//!
+//! Counters and histograms are updated as requests are served, the gauges
+//! describing the loaded index are refreshed on every scrape from the
+//! footprint `Search` keeps as layers load and unload.
+
+use crate::search::Search;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::time::Duration;
+
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+// seconds, probes of a single layer take microseconds
+const PROBE_BUCKETS: [f64; 12] = [
+    0.000_001, 0.000_002, 0.000_005, 0.000_01, 0.000_02, 0.000_05,
+    0.000_1, 0.000_2, 0.000_5, 0.001, 0.005, 0.01,
+];
+
+pub struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_seconds: HistogramVec,
+    levels: IntCounterVec,
+    probe_seconds: HistogramVec,
+    polygons_loaded: IntCounterVec,
+    polygons_rejected: IntCounterVec,
+    layer_polygons: IntGaugeVec,
+    index_bytes: IntGaugeVec,
+    dataset: IntGaugeVec,
+    load_seconds: IntGauge,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("tarantula".to_string()), None)
+            .expect("metrics registry");
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "requests by api, endpoint and status"),
+            &["api", "endpoint", "status"],
+        ).expect("requests metric");
+        let request_seconds = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "request latency by api and endpoint"),
+            &["api", "endpoint"],
+        ).expect("request latency metric");
+        let levels = IntCounterVec::new(
+            Opts::new("level_results_total", "searches answered (hit) or not (miss) per level"),
+            &["level", "result"],
+        ).expect("level results metric");
+        let probe_seconds = HistogramVec::new(
+            HistogramOpts::new("probe_duration_seconds", "point probe latency of a layer per level")
+                .buckets(PROBE_BUCKETS.to_vec()),
+            &["level"],
+        ).expect("probe latency metric");
+        let polygons_loaded = IntCounterVec::new(
+            Opts::new("polygons_loaded_total", "polygons added to the index per level"),
+            &["level"],
+        ).expect("polygons loaded metric");
+        let polygons_rejected = IntCounterVec::new(
+            Opts::new("polygons_rejected_total", "polygons or rings dropped at load per level and error code"),
+            &["level", "code"],
+        ).expect("polygons rejected metric");
+        let layer_polygons = IntGaugeVec::new(
+            Opts::new("layer_polygons", "polygons indexed per layer"),
+            &["layer", "level"],
+        ).expect("layer polygons metric");
+        let index_bytes = IntGaugeVec::new(
+            Opts::new("index_bytes", "approximate memory of the loaded layers"),
+            &["kind"],
+        ).expect("index bytes metric");
+        let dataset = IntGaugeVec::new(
+            Opts::new("dataset_info", "version of the loaded dataset"),
+            &["version"],
+        ).expect("dataset metric");
+        let load_seconds = IntGauge::new("load_duration_seconds", "duration of the last full load")
+            .expect("load duration metric");
+
+        registry.register(Box::new(requests.clone())).expect("register requests");
+        registry.register(Box::new(request_seconds.clone())).expect("register request latency");
+        registry.register(Box::new(levels.clone())).expect("register level results");
+        registry.register(Box::new(probe_seconds.clone())).expect("register probe latency");
+        registry.register(Box::new(polygons_loaded.clone())).expect("register polygons loaded");
+        registry.register(Box::new(polygons_rejected.clone())).expect("register polygons rejected");
+        registry.register(Box::new(layer_polygons.clone())).expect("register layer polygons");
+        registry.register(Box::new(index_bytes.clone())).expect("register index bytes");
+        registry.register(Box::new(dataset.clone())).expect("register dataset");
+        registry.register(Box::new(load_seconds.clone())).expect("register load duration");
+
+        Self {
+            registry,
+            requests,
+            request_seconds,
+            levels,
+            probe_seconds,
+            polygons_loaded,
+            polygons_rejected,
+            layer_polygons,
+            index_bytes,
+            dataset,
+            load_seconds,
+        }
+    }
+
+    pub fn observe_request(&self, api: &str, endpoint: &str, status: &str, elapsed: Duration) {
+        self.requests.with_label_values(&[api, endpoint, status]).inc();
+        self.request_seconds
+            .with_label_values(&[api, endpoint])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Hit for every level in `found`, miss for the other `levels`.
+    pub fn observe_levels(&self, levels: &[i32], found: &[i32]) {
+        levels.iter().for_each(|level| {
+            let result = if found.contains(level) { "hit" } else { "miss" };
+            self.levels.with_label_values(&[&level.to_string(), result]).inc();
+        });
+    }
+
+    pub fn observe_probe(&self, level: i32, elapsed: Duration) {
+        self.probe_seconds
+            .with_label_values(&[&level.to_string()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_polygons(&self, level: i32, loaded: usize, rejected: &[(&str, usize)]) {
+        let level = level.to_string();
+        self.polygons_loaded.with_label_values(&[&level]).inc_by(loaded as u64);
+        rejected.iter().for_each(|(code, count)| {
+            self.polygons_rejected.with_label_values(&[&level, code]).inc_by(*count as u64);
+        });
+    }
+
+    pub fn observe_load(&self, elapsed: Duration) {
+        self.load_seconds.set(elapsed.as_secs() as i64);
+    }
+
+    /// Refreshes the index gauges from `search` and renders the text format.
+    pub fn render(&self, search: Option<&Search>) -> Result<String, String> {
+        if let Some(search) = search {
+            self.layer_polygons.reset();
+            search.load_status().layers.iter().for_each(|layer| {
+                self.layer_polygons
+                    .with_label_values(&[&layer.layer, &layer.level.to_string()])
+                    .set(layer.polygons as i64);
+            });
+            let footprint = search.footprint();
+            self.index_bytes.with_label_values(&["index"]).set(footprint.index_bytes as i64);
+            self.index_bytes.with_label_values(&["info"]).set(footprint.info_bytes as i64);
+            self.dataset.reset();
+            self.dataset.with_label_values(&[&search.dataset_version()]).set(1);
+        }
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
+
+/// Label of a ring or polygon error code of `Loop::init`.
+pub fn error_code_label(code: i32) -> &'static str {
+    match code {
+        1 => "failure",
+        2 => "outer_curvature",
+        3 => "inner_curvature",
+        4 => "too_few_vertices",
+        5 => "crossing_edges",
+        6 => "duplicate_vertices",
+        _ => "load_error",
+    }
+}
@@ -0,0 +1,366 @@
+//! Overlaps and gaps within a layer, for the `overlaps` command.
+//!
+//! The polygons of a layer should tile its district, over all the parts of
+//! a split layer like `AL_D002_`. Two features overlapping by more than the
+//! area threshold make the point lookup ambiguous, and the holes the union
+//! of the layer leaves inside itself are gaps. Both are exported as GeoJSON
+//! features with ids that stay the same across releases, carrying the file
+//! and feature index `shape_edit` opens.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::config::{Attribute, Search as SearchConfig};
+use crate::discover::{layer_files, LayerFile};
+use crate::ffi;
+use crate::utils::shape::load_features;
+use autocxx::prelude::*;
+use core::pin::Pin;
+use geojson::{feature::Id, Feature, FeatureCollection, Geometry, JsonObject, Value};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Overlap,
+    Gap,
+}
+
+/// A feature of a shapefile, `feature` is the record index `shape_edit` shows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Source {
+    pub file: String,
+    pub feature: usize,
+    pub code: String,
+    pub name: String,
+}
+
+/// An outer ring and its holes.
+pub type Rings = Vec<Vec<(f64, f64)>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Problem {
+    pub kind: Kind,
+    pub layer: String,
+    pub district: String,
+    pub level: i32,
+    pub area_m2: f64,
+    /// the two overlapping features, empty for a gap
+    pub sources: Vec<Source>,
+    /// polygons of the overlap or the gap, each an outer ring and its holes
+    #[serde(skip)]
+    pub polygons: Vec<Rings>,
+}
+
+impl Problem {
+    /// Stable across releases: the codes of the overlapping features, or the
+    /// file stem and feature index without codes, and the rounded center of
+    /// a gap.
+    pub fn id(&self) -> String {
+        match self.kind {
+            Kind::Overlap => {
+                let mut keys: Vec<String> = self.sources
+                    .iter()
+                    .map(|source| if source.code.is_empty() {
+                        let stem = std::path::Path::new(&source.file)
+                            .file_stem()
+                            .and_then(|stem| stem.to_str())
+                            .unwrap_or_default();
+                        format!("{}#{}", stem, source.feature)
+                    } else {
+                        source.code.clone()
+                    })
+                    .collect();
+                keys.sort();
+                format!("overlap:{}:{}", self.layer, keys.join(":"))
+            }
+            Kind::Gap => {
+                let (lng, lat) = center(&self.polygons);
+                format!("gap:{}:{}:{:.5},{:.5}", self.layer, self.district, lng, lat)
+            }
+        }
+    }
+
+    pub fn to_feature(&self) -> Feature {
+        let mut properties = JsonObject::new();
+        properties.insert("kind".to_string(), match self.kind {
+            Kind::Overlap => "overlap",
+            Kind::Gap => "gap",
+        }.into());
+        properties.insert("layer".to_string(), self.layer.clone().into());
+        properties.insert("district".to_string(), self.district.clone().into());
+        properties.insert("level".to_string(), self.level.into());
+        properties.insert("area_m2".to_string(), self.area_m2.into());
+        for (i, source) in self.sources.iter().enumerate() {
+            let key = |name: &str| format!("{}_{}", name, if i == 0 { "a" } else { "b" });
+            properties.insert(key("file"), source.file.clone().into());
+            properties.insert(key("feature"), source.feature.into());
+            properties.insert(key("code"), source.code.clone().into());
+            properties.insert(key("name"), source.name.clone().into());
+        }
+        let polygons = self.polygons
+            .iter()
+            .map(|polygon| polygon
+                .iter()
+                .map(|ring| ring.iter().map(|(lng, lat)| vec![*lng, *lat]).collect())
+                .collect())
+            .collect();
+        Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(Value::MultiPolygon(polygons))),
+            id: Some(Id::String(self.id())),
+            properties: Some(properties),
+            foreign_members: None,
+        }
+    }
+}
+
+// mean of the outer ring vertices
+fn center(polygons: &[Rings]) -> (f64, f64) {
+    let points: Vec<&(f64, f64)> = polygons
+        .iter()
+        .filter_map(|polygon| polygon.first())
+        .flat_map(|ring| ring.iter().skip(1))
+        .collect();
+    if points.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = points.len() as f64;
+    (points.iter().map(|p| p.0).sum::<f64>() / n, points.iter().map(|p| p.1).sum::<f64>() / n)
+}
+
+/// Splits the flattened loops of an overlay into polygons and their areas,
+/// a loop with a negative area is a hole of the polygon before it. Rings are
+/// closed and loops of fewer than 3 vertices dropped.
+pub fn polygons(lnglats: &[(f64, f64)], loop_sizes: &[i32], loop_areas: &[f64])
+    -> Vec<(f64, Rings)> {
+    let mut polygons: Vec<(f64, Rings)> = vec![];
+    let mut start = 0;
+    for (size, area) in loop_sizes.iter().zip(loop_areas) {
+        let end = (start + (*size).max(0) as usize).min(lnglats.len());
+        let mut ring = lnglats[start..end].to_vec();
+        start = end;
+        if ring.len() < 3 {
+            continue;
+        }
+        ring.push(ring[0]);
+        match polygons.last_mut() {
+            Some((total, rings)) if *area < 0.0 => {
+                *total += area;
+                rings.push(ring);
+            }
+            _ => polygons.push((*area, vec![ring])),
+        }
+    }
+    polygons
+}
+
+fn overlay_polygons(overlay: &ffi::Overlay) -> Vec<(f64, Rings)> {
+    let lnglats: Vec<(f64, f64)> = overlay.lnglats().iter().map(|ll| (ll.lng(), ll.lat())).collect();
+    let sizes: Vec<i32> = overlay.loop_sizes().iter().copied().collect();
+    let areas: Vec<f64> = overlay.loop_areas().iter().copied().collect();
+    polygons(&lnglats, &sizes, &areas)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LayerCounts {
+    pub layer: String,
+    pub level: i32,
+    pub files: usize,
+    pub features: usize,
+    pub overlaps: usize,
+    pub overlap_area_m2: f64,
+    pub gaps: usize,
+    pub gap_area_m2: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub min_area_m2: f64,
+    pub layers: Vec<LayerCounts>,
+    /// shapefiles that could not be read
+    pub errors: Vec<String>,
+    #[serde(skip)]
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    /// Adds a layer of a district with `files` and `features` and its problems.
+    pub fn add(&mut self, layer: &str, level: i32, files: usize, features: usize,
+        problems: Vec<Problem>) {
+        let index = match self.layers.iter().position(|counts| counts.layer == layer) {
+            Some(index) => index,
+            None => {
+                self.layers.push(LayerCounts { layer: layer.to_string(), level, ..Default::default() });
+                self.layers.len() - 1
+            }
+        };
+        let counts = &mut self.layers[index];
+        counts.files += files;
+        counts.features += features;
+        for problem in problems.iter() {
+            match problem.kind {
+                Kind::Overlap => {
+                    counts.overlaps += 1;
+                    counts.overlap_area_m2 += problem.area_m2;
+                }
+                Kind::Gap => {
+                    counts.gaps += 1;
+                    counts.gap_area_m2 += problem.area_m2;
+                }
+            }
+        }
+        self.problems.extend(problems);
+        self.layers.sort_by(|a, b| a.level.cmp(&b.level).then_with(|| a.layer.cmp(&b.layer)));
+    }
+
+    /// One row per layer, tab separated.
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "layer\tlevel\tfiles\tfeatures\toverlaps\toverlap_area_m2\tgaps\tgap_area_m2")?;
+        for counts in self.layers.iter() {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{:.1}\t{}\t{:.1}", counts.layer, counts.level,
+                counts.files, counts.features, counts.overlaps, counts.overlap_area_m2,
+                counts.gaps, counts.gap_area_m2)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_geojson(&self, out: &mut impl Write) -> Result<()> {
+        let collection = FeatureCollection {
+            bbox: None,
+            features: self.problems.iter().map(|problem| problem.to_feature()).collect(),
+            foreign_members: None,
+        };
+        serde_json::to_writer(out, &collection).chain_err(|| "write geojson")
+    }
+}
+
+// a shapefile loaded into an index, with the record of every shape
+struct Part {
+    polys: Pin<Box<ffi::Polygons>>,
+    sources: Vec<Source>,
+}
+
+/// Finds the overlaps and gaps above `min_area_m2` in every layer of every
+/// district of `config`. The files of a district layer are loaded together
+/// and dropped before the next one.
+pub fn overlaps(config: &SearchConfig, min_area_m2: f64) -> Result<Report> {
+    let files = layer_files(config).chain_err(|| "failed to list the shapefiles")?;
+    let mut groups: BTreeMap<(String, String), Vec<&LayerFile>> = BTreeMap::new();
+    files.iter().for_each(|file| groups
+        .entry((file.district.clone(), file.layer.clone()))
+        .or_default()
+        .push(file));
+
+    let mut report = Report { min_area_m2, ..Default::default() };
+    for ((district, layer), files) in groups {
+        let Some(attribute) = config.shapefile.attributes.get(&layer) else {
+            report.errors.push(format!("layer {} has no attributes entry", layer));
+            continue;
+        };
+        let _span = tracing::info_span!("overlaps", %district, %layer).entered();
+        let loaded: Vec<std::result::Result<Part, String>> = files
+            .par_iter()
+            .map(|file| load_part(&file.path, attribute))
+            .collect();
+        let mut parts = vec![];
+        for part in loaded {
+            match part {
+                Ok(part) => parts.push(part),
+                Err(e) => report.errors.push(e),
+            }
+        }
+
+        let shapes: Vec<(usize, usize)> = parts
+            .iter()
+            .enumerate()
+            .flat_map(|(a, part)| (0..part.sources.len()).map(move |i| (a, i)))
+            .collect();
+        let mut problems: Vec<Problem> = shapes
+            .par_iter()
+            .flat_map_iter(|(a, i)| overlaps_of(&parts, *a, *i, min_area_m2))
+            .map(|(area_m2, sources, polygons)| Problem {
+                kind: Kind::Overlap,
+                layer: layer.clone(),
+                district: district.clone(),
+                level: attribute.level,
+                area_m2,
+                sources,
+                polygons,
+            })
+            .collect();
+
+        let mut coverage = ffi::Coverage::new().within_box();
+        parts.iter().for_each(|part| coverage.as_mut().add(&part.polys));
+        overlay_polygons(&coverage.gaps())
+            .into_iter()
+            .filter(|(area, _)| *area > min_area_m2)
+            .for_each(|(area_m2, polygon)| problems.push(Problem {
+                kind: Kind::Gap,
+                layer: layer.clone(),
+                district: district.clone(),
+                level: attribute.level,
+                area_m2,
+                sources: vec![],
+                polygons: vec![polygon],
+            }));
+
+        tracing::info!(%district, %layer, features = shapes.len(), problems = problems.len(),
+            "overlaps");
+        report.add(&layer, attribute.level, parts.len(), shapes.len(), problems);
+    }
+    Ok(report)
+}
+
+fn load_part(path: &str, attribute: &Attribute) -> std::result::Result<Part, String> {
+    let mut polys = ffi::Polygons::new().within_box();
+    // rejected polygons are reported by `validate`
+    let features = load_features(path, attribute, Some(polys.as_mut()))
+        .map_err(|e| format!("{}: {}", path, e))?;
+    let sources = features
+        .into_iter()
+        .map(|feature| Source {
+            file: path.to_string(),
+            feature: feature.index,
+            code: feature.code,
+            name: feature.name,
+        })
+        .collect();
+    Ok(Part { polys, sources })
+}
+
+type Overlap = (f64, Vec<Source>, Vec<Rings>);
+
+// overlaps of shape `i` of part `a` with the shapes after it, each pair once
+fn overlaps_of(parts: &[Part], a: usize, i: usize, min_area_m2: f64) -> Vec<Overlap> {
+    let part = &parts[a];
+    let mut found = vec![];
+    for (b, other) in parts.iter().enumerate().skip(a) {
+        let candidates: Vec<i32> = part.polys.intersecting(c_int(i as i32), &other.polys)
+            .iter()
+            .copied()
+            .collect();
+        for j in candidates {
+            if b == a && j as usize <= i {
+                continue;
+            }
+            let overlay = part.polys.intersection(c_int(i as i32), &other.polys, c_int(j));
+            if overlay.area() <= min_area_m2 {
+                continue;
+            }
+            let Some(source) = other.sources.get(j as usize) else {
+                continue;
+            };
+            let polygons = overlay_polygons(&overlay)
+                .into_iter()
+                .map(|(_, polygon)| polygon)
+                .collect();
+            found.push((overlay.area(), vec![part.sources[i].clone(), source.clone()], polygons));
+        }
+    }
+    found
+}
@@ -0,0 +1,179 @@
+//! Loading progress of the layers, served while the data is still loading.
+//!
+//! Layers are recorded per district as they finish. The partial gate opens
+//! once every layer of the upper levels is done, the full gate once the whole
+//! load including the name index has finished.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Gate {
+    Partial,
+    #[default]
+    Full,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerProgress {
+    pub layer: String,
+    pub level: i32,
+    pub gate: Gate,
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Readiness {
+    pub partial: bool,
+    pub full: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub layers: Vec<LayerProgress>,
+}
+
+impl Readiness {
+    /// A failed load never opens a gate.
+    pub fn ready(&self, gate: Gate) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        match gate {
+            Gate::Partial => self.partial,
+            Gate::Full => self.full,
+        }
+    }
+}
+
+struct LayerState {
+    layer: String,
+    level: i32,
+    gate: Gate,
+    total: usize,
+    loaded: HashSet<String>,
+    failed: HashSet<String>,
+}
+
+impl LayerState {
+    fn done(&self) -> bool {
+        self.loaded.len() + self.failed.len() >= self.total
+    }
+}
+
+#[derive(Default)]
+pub struct Progress {
+    layers: RwLock<Vec<LayerState>>,
+    started: AtomicBool,
+    finished: AtomicBool,
+    error: RwLock<Option<String>>,
+}
+
+impl Progress {
+    /// Starts tracking `(layer, level, gate, districts)`, forgetting the previous load.
+    pub fn start(&self, layers: Vec<(String, i32, Gate, usize)>) {
+        if let Ok(mut states) = self.layers.write() {
+            *states = layers
+                .into_iter()
+                .map(|(layer, level, gate, total)| LayerState {
+                    layer,
+                    level,
+                    gate,
+                    total,
+                    loaded: HashSet::new(),
+                    failed: HashSet::new(),
+                })
+                .collect();
+        }
+        if let Ok(mut error) = self.error.write() {
+            *error = None;
+        }
+        self.finished.store(false, Ordering::Release);
+        self.started.store(true, Ordering::Release);
+    }
+
+    /// Records a layer of a district, a district is counted once per layer.
+    pub fn record(&self, layer: &str, district: &str, ok: bool) {
+        if self.is_finished() {
+            return;
+        }
+        let Ok(mut states) = self.layers.write() else {
+            return;
+        };
+        if let Some(state) = states.iter_mut().find(|state| state.layer == layer) {
+            state.loaded.remove(district);
+            state.failed.remove(district);
+            if ok {
+                state.loaded.insert(district.to_string());
+            } else {
+                state.failed.insert(district.to_string());
+            }
+        }
+    }
+
+    pub fn finish(&self) {
+        self.finished.store(true, Ordering::Release);
+    }
+
+    /// Ends the load with an error, the layers loaded so far keep serving.
+    pub fn fail(&self, error: &str) {
+        if let Ok(mut state) = self.error.write() {
+            *state = Some(error.to_string());
+        }
+        self.finish();
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.read().ok().and_then(|error| error.clone())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Acquire)
+    }
+
+    /// Levels with a layer still loading, empty once the load has finished.
+    pub fn loading_levels(&self) -> Vec<i32> {
+        if self.is_finished() {
+            return vec![];
+        }
+        let mut levels: Vec<i32> = self.layers
+            .read()
+            .map(|states| states
+                .iter()
+                .filter(|state| !state.done())
+                .map(|state| state.level)
+                .collect())
+            .unwrap_or_default();
+        levels.sort();
+        levels.dedup();
+        levels
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let layers: Vec<LayerProgress> = self.layers
+            .read()
+            .map(|states| states
+                .iter()
+                .map(|state| LayerProgress {
+                    layer: state.layer.clone(),
+                    level: state.level,
+                    gate: state.gate,
+                    total: state.total,
+                    loaded: state.loaded.len(),
+                    failed: state.failed.len(),
+                    done: state.done(),
+                })
+                .collect())
+            .unwrap_or_default();
+        let full = self.is_finished();
+        let partial = full || (self.started.load(Ordering::Acquire) && layers
+            .iter()
+            .filter(|layer| layer.gate == Gate::Partial)
+            .all(|layer| layer.done));
+        Readiness { partial, full, error: self.error(), layers }
+    }
+}
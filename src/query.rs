@@ -0,0 +1,172 @@
+//! Point queries of the `query` command.
+//!
+//! Points come from the flags or stdin, one `lon,lat` (or `lon lat`) per
+//! line, and the hierarchy of every point is printed as JSON lines, a table
+//! or a GeoJSON feature collection of the points and the hit polygons.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::search::{Info, Search};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Json,
+    Table,
+    Geojson,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    pub level: i32,
+    pub code: String,
+    pub name: String,
+    pub eng_name: String,
+    pub district: String,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
+    #[serde(skip)]
+    pub lnglats: Vec<(f64, f64)>,
+}
+
+impl From<Info> for Hit {
+    fn from(info: Info) -> Self {
+        Hit {
+            level: info.level,
+            code: info.code,
+            name: info.name,
+            eng_name: info.eng_name,
+            district: info.district,
+            fallback: info.fallback,
+            lnglats: info.lnglats,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PointResult {
+    pub lon: f64,
+    pub lat: f64,
+    pub hits: Vec<Hit>,
+}
+
+/// Hierarchy at `(lon, lat)`, from the top level down.
+pub fn query(search: &Search, lon: f64, lat: f64) -> Result<PointResult> {
+    let mut hits: Vec<Hit> = search
+        .search(lon, lat)
+        .map_err(|e| format!("search {},{}: {}", lon, lat, e))?
+        .into_iter()
+        .map(Hit::from)
+        .collect();
+    hits.sort_by_key(|hit| hit.level);
+    Ok(PointResult { lon, lat, hits })
+}
+
+/// `lon,lat` or `lon lat`, `None` for a blank or `#` comment line.
+pub fn parse_point(line: &str) -> Option<Result<(f64, f64)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let fields: Vec<&str> = line
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|field| !field.is_empty())
+        .collect();
+    let point = match fields.as_slice() {
+        [lon, lat] => lon.parse::<f64>().ok().zip(lat.parse::<f64>().ok()),
+        _ => None,
+    };
+    Some(point.ok_or_else(|| format!("invalid point {:?}, expected lon,lat", line).into()))
+}
+
+/// Writes the results as they come, except GeoJSON which is written by `finish`.
+pub struct Printer<W: Write> {
+    format: Format,
+    out: W,
+    header: bool,
+    features: Vec<Feature>,
+}
+
+impl<W: Write> Printer<W> {
+    pub fn new(format: Format, out: W) -> Self {
+        Printer { format, out, header: false, features: vec![] }
+    }
+
+    pub fn print(&mut self, result: &PointResult) -> Result<()> {
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, result).chain_err(|| "write json")?;
+                writeln!(self.out).chain_err(|| "write json")?;
+            }
+            Format::Table => {
+                if !self.header {
+                    writeln!(self.out, "lon\tlat\tlevel\tcode\tname\teng_name\tdistrict")
+                        .chain_err(|| "write table")?;
+                    self.header = true;
+                }
+                if result.hits.is_empty() {
+                    writeln!(self.out, "{}\t{}\t-\t-\t-\t-\t-", result.lon, result.lat)
+                        .chain_err(|| "write table")?;
+                }
+                for hit in result.hits.iter() {
+                    writeln!(self.out, "{}\t{}\t{}\t{}\t{}\t{}\t{}", result.lon, result.lat,
+                        hit.level, hit.code, hit.name, hit.eng_name, hit.district)
+                        .chain_err(|| "write table")?;
+                }
+            }
+            Format::Geojson => self.features.extend(features(result)),
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if self.format == Format::Geojson {
+            let collection = FeatureCollection {
+                bbox: None,
+                features: std::mem::take(&mut self.features),
+                foreign_members: None,
+            };
+            serde_json::to_writer(&mut self.out, &collection).chain_err(|| "write geojson")?;
+            writeln!(self.out).chain_err(|| "write geojson")?;
+        }
+        self.out.flush().chain_err(|| "flush")
+    }
+}
+
+fn feature(geometry: Value, properties: JsonObject) -> Feature {
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(geometry)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+/// The query point and a polygon per hit with its outer ring.
+pub fn features(result: &PointResult) -> Vec<Feature> {
+    let mut properties = JsonObject::new();
+    properties.insert("levels".to_string(),
+        result.hits.iter().map(|hit| hit.level).collect::<Vec<_>>().into());
+    let mut features = vec![feature(Value::Point(vec![result.lon, result.lat]), properties)];
+
+    result.hits.iter().filter(|hit| hit.lnglats.len() >= 3).for_each(|hit| {
+        let mut ring: Vec<Vec<f64>> = hit.lnglats.iter().map(|(lng, lat)| vec![*lng, *lat]).collect();
+        if ring.first() != ring.last() {
+            ring.push(ring[0].clone());
+        }
+        let mut properties = match serde_json::to_value(hit) {
+            Ok(serde_json::Value::Object(properties)) => properties,
+            _ => JsonObject::new(),
+        };
+        properties.insert("lon".to_string(), result.lon.into());
+        properties.insert("lat".to_string(), result.lat.into());
+        features.push(feature(Value::Polygon(vec![ring]), properties));
+    });
+    features
+}
@@ -1,57 +1,372 @@
 use axum::{
-    routing::get,
+    routing::{delete, get, post},
     Router,
-    http::StatusCode,
-    extract::Query,
+    http::{HeaderMap, HeaderValue, Request, StatusCode},
+    extract::{MatchedPath, Path, Query},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     Json
 };
+use std::future::Future;
 use std::net::ToSocketAddrs;
-use tower_http::trace::{TraceLayer, DefaultMakeSpan, DefaultOnResponse};
+use tower_http::trace::{TraceLayer, DefaultOnResponse};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing::Level;
 use tower::ServiceBuilder;
-use crate::search::GLOBAL_SEARCH;
+use crate::search::{Search, GLOBAL_SEARCH};
+use crate::versions::{self, ErrorKind as VersionErrorKind};
+use crate::progress::Gate;
+use crate::metrics::METRICS;
+use std::time::Instant;
 use serde::Deserialize;
 use serde_json::json;
 
+/// Response header with the version of the dataset manifest.
+pub const DATASET_VERSION: &str = "x-dataset-version";
+
 #[derive(Deserialize)]
 struct LonLatParams {
     lon: f64,
     lat: f64,
+    /// snapshot name, see `versions`
+    version: Option<String>,
+    /// newest dataset released on or before this date
+    as_of: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RegionSearchParams {
+    q: String,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    geometry: bool,
+}
+
+#[derive(Deserialize)]
+struct AutocompleteParams {
+    q: String,
+    #[serde(default = "default_limit")]
+    limit: usize,
+}
+
+#[derive(Deserialize)]
+struct ReadinessParams {
+    #[serde(default)]
+    gate: Gate,
+}
+
+#[derive(Deserialize)]
+struct LoadDistrictParams {
+    path: Option<String>,
+}
+
+fn default_limit() -> usize {
+    10
 }
 
-pub async fn start_server() {
+/// Binds the listener before the index loads, so a port clash fails fast.
+pub fn bind() -> std::io::Result<std::net::TcpListener> {
     let config = crate::GLOBAL_CONFIG.rest.clone();
-    tracing_subscriber::fmt::init();
+    let addr = (config.host, config.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, 
+            "unable to resolve address"))?;
+    std::net::TcpListener::bind(addr)
+}
+
+/// Serves until `shutdown` resolves, then drains the in-flight requests.
+pub async fn serve(listener: std::net::TcpListener, 
+    shutdown: impl Future<Output = ()>) -> Result<(), String> {
     let trace_layer = TraceLayer::new_for_http()
-        .make_span_with(DefaultMakeSpan::new().level(Level::INFO)) 
+        .make_span_with(|request: &Request<axum::body::Body>| {
+            let span = tracing::info_span!("request",
+                method = %request.method(),
+                uri = %request.uri());
+            span.set_parent(crate::telemetry::header_context(request.headers()));
+            span
+        }) 
         .on_response(DefaultOnResponse::new().level(Level::INFO)); 
 
+    let admin = Router::new()
+        .route("/admin/districts", get(admin_districts))
+        .route("/admin/districts/:district", delete(admin_unload_district))
+        .route("/admin/districts/:district/load", post(admin_load_district))
+        .route_layer(middleware::from_fn(admin_auth));
+
     let app = Router::new()
         .route("/tarantula", get(tarantula))
+        .route("/regions/search", get(regions_search))
+        .route("/regions/autocomplete", get(regions_autocomplete))
+        .route("/cache/stats", get(cache_stats))
+        .route("/lazy/stats", get(lazy_stats))
+        .route("/readiness", get(readiness))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/dataset", get(dataset))
+        .route("/versions", get(versions_list))
+        .merge(admin)
+        .route_layer(middleware::from_fn(track_metrics))
+        .layer(middleware::from_fn(dataset_version))
         .layer(ServiceBuilder::new().layer(trace_layer));
 
-    let addr = (config.host, config.port)
-        .to_socket_addrs()
-        .expect("invalid host or port")
-        .next()
-        .expect("unable to resolve address");
-    
-    println!("rest api server listening on {}", addr);
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    tracing::info!(%addr, "rest api server listening");
 
-    axum::Server::bind(&addr)
+    axum::Server::from_tcp(listener)
+        .map_err(|e| e.to_string())?
         .serve(app.into_make_service())
+        .with_graceful_shutdown(shutdown)
         .await
-        .unwrap();
+        .map_err(|e| e.to_string())
+}
+
+async fn track_metrics<B>(request: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let endpoint = request.extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    METRICS.observe_request("rest", &endpoint, response.status().as_str(), start.elapsed());
+    response
+}
+
+// the admin routes need `rest.admin_token` configured and sent as a bearer token
+async fn admin_auth<B>(request: Request<B>, next: Next<B>) 
+    -> Result<Response, (StatusCode, String)> {
+    let Some(token) = crate::GLOBAL_CONFIG.rest.admin_token.as_deref() else {
+        return Err((StatusCode::FORBIDDEN, "admin api disabled, set rest.admin_token".to_string()));
+    };
+    let authorization = request.headers()
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !admin_authorized(authorization, token) {
+        return Err((StatusCode::UNAUTHORIZED, "invalid admin token".to_string()));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Whether the `Authorization` header value carries `token` as a bearer
+/// token, compared in constant time. An empty token authorizes nothing.
+pub fn admin_authorized(authorization: Option<&str>, token: &str) -> bool {
+    let Some(given) = authorization.and_then(|value| value.strip_prefix("Bearer ")) else {
+        return false;
+    };
+    let (given, token) = (given.trim().as_bytes(), token.as_bytes());
+    !token.is_empty() && given.len() == token.len()
+        && given.iter().zip(token).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// clients cache against the version of the dataset that answered
+async fn dataset_version<B>(request: Request<B>, next: Next<B>) -> impl IntoResponse {
+    let mut response = next.run(request).await;
+    if response.headers().contains_key(DATASET_VERSION) {
+        return response;
+    }
+    if let Some(search) = unsafe { GLOBAL_SEARCH.as_ref() } {
+        if let Ok(value) = HeaderValue::from_str(&search.dataset_version()) {
+            response.headers_mut().insert(DATASET_VERSION, value);
+        }
+    }
+    response
+}
+
+async fn dataset() -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, 
+            "search not initialized".to_string()))?;
+    match search.manifest() {
+        Some(manifest) => Ok(Json(json!(manifest))),
+        None => Err((StatusCode::SERVICE_UNAVAILABLE, "dataset still loading".to_string())),
+    }
+}
+
+async fn metrics() -> Result<String, (StatusCode, String)> {
+    let search = unsafe { GLOBAL_SEARCH.as_ref() };
+    METRICS.render(search).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
 }
 
 async fn tarantula(Query(params): Query<LonLatParams>) 
+    -> Result<(HeaderMap, Json<serde_json::Value>), (StatusCode, String)> {
+    let current = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    let picked = pick_version(params.version, params.as_of).await?;
+    let search: &Search = picked.as_deref().unwrap_or(current);
+    let result = search.search(params.lon, params.lat);
+    match result {
+        Ok(res) => {
+            let mut headers = loading_headers(search.loading_levels());
+            if let Ok(value) = HeaderValue::from_str(&search.dataset_version()) {
+                headers.insert(DATASET_VERSION, value);
+            }
+            Ok((headers, Json(json!(res))))
+        }
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+// the snapshot asked for with version= or as_of=, a lazy load runs off the runtime
+async fn pick_version(version: Option<String>, as_of: Option<String>) 
+    -> Result<Option<std::sync::Arc<Search>>, (StatusCode, String)> {
+    if version.is_none() && as_of.is_none() {
+        return Ok(None);
+    }
+    tokio::task::spawn_blocking(move || versions::pick(version.as_deref(), as_of.as_deref()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| {
+            let status = match e.kind() {
+                VersionErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+                VersionErrorKind::Loading(_) => StatusCode::SERVICE_UNAVAILABLE,
+                VersionErrorKind::Failed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_REQUEST,
+            };
+            (status, e.to_string())
+        })
+}
+
+async fn versions_list() -> Json<serde_json::Value> {
+    let current = unsafe { GLOBAL_SEARCH.as_ref() };
+    Json(json!(versions::list(current)))
+}
+
+// levels still loading, the answer may miss them
+fn loading_headers(levels: Vec<i32>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if !levels.is_empty() {
+        let levels: Vec<String> = levels.iter().map(|level| level.to_string()).collect();
+        if let Ok(value) = HeaderValue::from_str(&levels.join(",")) {
+            headers.insert("x-loading-levels", value);
+        }
+    }
+    headers
+}
+
+async fn readiness(Query(params): Query<ReadinessParams>) 
+    -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, 
+            "search not initialized".to_string()))?;
+    let readiness = search.readiness();
+    let status = if readiness.ready(params.gate) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((status, Json(json!(readiness))))
+}
+
+async fn healthz() 
+    -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, 
+            "search not initialized".to_string()))?;
+    let status = search.load_status();
+    let code = if status.healthy() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((code, Json(json!(status))))
+}
+
+async fn readyz(Query(params): Query<ReadinessParams>) 
+    -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::SERVICE_UNAVAILABLE, 
+            "search not initialized".to_string()))?;
+    let status = search.load_status();
+    let code = if status.ready(params.gate) {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((code, Json(json!(status))))
+}
+
+async fn regions_search(Query(params): Query<RegionSearchParams>) 
     -> Result<Json<serde_json::Value>, (StatusCode, String)> {
     let search = unsafe { &GLOBAL_SEARCH.as_ref() }
         .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
             "search not initialized".to_string()))?;
-    let result = search.search(params.lon, params.lat);
+    let result = search.search_regions(&params.q, params.limit, params.geometry);
+    match result {
+        Ok(res) => Ok(Json(json!(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn regions_autocomplete(Query(params): Query<AutocompleteParams>) 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    let result = search.autocomplete(&params.q, params.limit);
     match result {
         Ok(res) => Ok(Json(json!(res))),
         Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
     }
 }
+
+async fn cache_stats() 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    match search.cache_stats() {
+        Some(stats) => Ok(Json(json!(stats))),
+        None => Err((StatusCode::NOT_FOUND, "cache disabled".to_string())),
+    }
+}
+
+async fn lazy_stats() 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    Ok(Json(json!(search.lazy_stats())))
+}
+
+async fn admin_districts() 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    match search.districts() {
+        Ok(res) => Ok(Json(json!(res))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn admin_unload_district(Path(district): Path<String>) 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    match search.unload_district(&district) {
+        Ok(true) => Ok(Json(json!({ "district": district, "unloaded": true }))),
+        Ok(false) => Err((StatusCode::NOT_FOUND, format!("district {} not loaded", district))),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn admin_load_district(Path(district): Path<String>, 
+    Query(params): Query<LoadDistrictParams>) 
+    -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let search = unsafe { &GLOBAL_SEARCH.as_ref() }
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, 
+            "search not initialized".to_string()))?;
+    let name = district.clone();
+    let result = tokio::task::spawn_blocking(move || 
+            search.reload_district(&name, params.path.as_deref()).map_err(|e| e.to_string()))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    match result {
+        Ok(()) => Ok(Json(json!({ "district": district, "loaded": true }))),
+        Err(e) => Err((StatusCode::BAD_REQUEST, e)),
+    }
+}
@@ -8,9 +8,17 @@ mod errors {
     error_chain::error_chain! { }
 }
 use errors::*;
+use error_chain::bail;
 use scopeguard::defer;
 use serde::{Deserialize, Serialize};
-use crate::{ffi, config::Search as SearchConfig};
+use crate::{ffi, hierarchy, config::{Attribute, Search as SearchConfig}};
+use crate::lookup::{Layer, NameIndex, Region, RegionMatch};
+use crate::autocomplete::{Autocomplete, Suggestion};
+use crate::cache::{Cache, CacheStats};
+use crate::intern::Interner;
+use crate::manifest::{self, Manifest};
+use crate::progress::{Gate, Progress, Readiness};
+use crate::metrics::{error_code_label, Metrics, METRICS};
 use autocxx::prelude::*;
 use shapefile::{record::{traits::HasXY}, Shape};
 #[path = "./utils/mod.rs"]
@@ -18,31 +26,41 @@ mod utils;
 use core::pin::Pin;
 use rayon::prelude::*;
 use stopwatch::Stopwatch;
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, RwLock};
+use std::time::Instant;
+use tracing::Span;
 pub static mut GLOBAL_SEARCH: Option<Search> = None;
 use std::sync::Mutex;
 
+/// Creates the global search and loads its layers in the background, the
+/// servers start right away and answer from the layers loaded so far.
 pub fn initialize_global_search() {
     unsafe {
         let search_config = crate::GLOBAL_CONFIG.search.clone();
         let search = Search::new(search_config.clone())
             .and_then(|mut search| {
-                match search.load() {
-                    Ok(()) => Ok(search),
-                    Err(err) => {
-                        eprintln!("warning: failed to load search data: {err}");
-                        Ok(search)
-                    }
+                if let Err(err) = search.discover() {
+                    tracing::warn!(error = %err, "failed to discover districts");
                 }
+                Ok(search)
             })
             .unwrap_or_else(|err| {
-                eprintln!("warning: failed to create search: {err}");
+                tracing::warn!(error = %err, "failed to create search");
                 Search::new(search_config).unwrap_or_else(|_| panic!("failed to create fallback search"))
             });
 
         GLOBAL_SEARCH = Some(search);
     }
+
+    std::thread::spawn(|| {
+        if let Some(search) = unsafe { GLOBAL_SEARCH.as_ref() } {
+            if let Err(err) = search.load_layers() {
+                tracing::error!(error = %err, "failed to load search data");
+            }
+        }
+    });
 }
 
 unsafe impl Send for ffi::Polygons {}
@@ -53,56 +71,318 @@ pub struct Info {
     pub district: String,
     pub level: i32,
     pub name: String,
+    #[serde(default)]
+    pub code: String,
+    #[serde(default)]
+    pub eng_name: String,
     pub lnglats: Vec<(f64, f64)>,
+    /// found in a neighbouring district near the district boundary
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct PolyInfo {
+    pub district: Arc<str>,
+    pub level: i32,
+    pub name: Arc<str>,
+    pub code: Arc<str>,
+    pub eng_name: Arc<str>,
+    pub centroid: (f64, f64),
+}
+
+impl PolyInfo {
+    fn to_info(&self, lnglats: Vec<(f64, f64)>) -> Info {
+        Info {
+            district: self.district.to_string(),
+            level: self.level,
+            name: self.name.to_string(),
+            code: self.code.to_string(),
+            eng_name: self.eng_name.to_string(),
+            lnglats,
+            fallback: false,
+        }
+    }
+}
+
+type Polys = (Pin<Box<ffi::Polygons>>, Vec<PolyInfo>);
+
+// point probe of a layer, timed per level, `parent` crosses the rayon threads
+fn probe(parent: &Span, metrics: Option<&Metrics>, polys: &Polys, lon: f64, lat: f64) -> i32 {
+    let first = polys.1.first();
+    let _span = tracing::info_span!(parent: parent, "probe",
+        level = first.map(|info| info.level),
+        district = first.map(|info| &*info.district))
+        .entered();
+    let start = Instant::now();
+    let j = i32::from(polys.0.search(lon, lat));
+    if let Some((metrics, info)) = metrics.zip(first) {
+        metrics.observe_probe(info.level, start.elapsed());
+    }
+    j
+}
+
+// approximate bytes of a layer, index and attributes
+fn polys_bytes(polys: &Polys) -> usize {
+    polys.0.space_used() + info_bytes(&polys.1)
+}
+
+// attribute bytes, strings shared by interning are counted once
+fn info_bytes(infos: &[PolyInfo]) -> usize {
+    let mut seen = std::collections::HashSet::new();
+    infos.iter()
+        .map(|info| {
+            std::mem::size_of::<PolyInfo>() + [&info.district, &info.name, &info.code, &info.eng_name]
+                .iter()
+                .filter(|s| seen.insert(Arc::as_ptr(s) as *const u8))
+                .map(|s| s.len())
+                .sum::<usize>()
+        })
+        .sum()
+}
+
+/// Polygons per level and bytes of a group of loaded layers.
+#[derive(Debug, Clone, Default)]
+pub struct Footprint {
+    pub polygons: HashMap<i32, usize>,
+    pub index_bytes: usize,
+    pub info_bytes: usize,
+}
+
+impl Footprint {
+    fn of<'a>(layers: impl Iterator<Item = &'a Polys>) -> Self {
+        let mut footprint = Footprint::default();
+        layers.for_each(|polys| {
+            if let Some(info) = polys.1.first() {
+                *footprint.polygons.entry(info.level).or_insert(0) += polys.1.len();
+            }
+            footprint.index_bytes += polys.0.space_used();
+            footprint.info_bytes += info_bytes(&polys.1);
+        });
+        footprint
+    }
+
+    fn add(&mut self, other: &Footprint) {
+        other.polygons.iter().for_each(|(level, polygons)| {
+            *self.polygons.entry(*level).or_insert(0) += polygons;
+        });
+        self.index_bytes += other.index_bytes;
+        self.info_bytes += other.info_bytes;
+    }
+}
+
+struct DistrictState {
+    path: String,
+    layers: usize,
+    bytes: usize,
+    polygons: usize,
+    last_used: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DistrictStats {
+    pub district: String,
+    pub loaded: bool,
+    pub path: String,
+    pub layers: usize,
+    pub polygons: usize,
+    pub bytes: usize,
+    pub last_used: u64,
+}
+
+#[derive(Default)]
+struct LazyCounters {
+    loads: AtomicU64,
+    evictions: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LazyStats {
+    pub loads: u64,
+    pub evictions: u64,
+    pub districts: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerMemory {
     pub district: String,
     pub level: i32,
-    pub name: String,
+    pub polygons: usize,
+    pub index_bytes: usize,
+    pub info_bytes: usize,
+    pub compact: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoryReport {
+    pub index_bytes: usize,
+    pub info_bytes: usize,
+    pub interned_strings: usize,
+    pub interned_bytes: usize,
+    pub layers: Vec<LayerMemory>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayerStatus {
+    pub layer: String,
+    pub level: i32,
+    pub polygons: usize,
+    pub rejected: usize,
+    pub districts: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadStatus {
+    pub loaded: bool,
+    pub partial: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub polygons: usize,
+    pub layers: Vec<LayerStatus>,
+}
+
+impl LoadStatus {
+    /// Alive unless the load failed.
+    pub fn healthy(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// An instance with an empty index is never ready.
+    pub fn ready(&self, gate: Gate) -> bool {
+        let open = match gate {
+            Gate::Partial => self.partial,
+            Gate::Full => self.loaded,
+        };
+        open && self.healthy() && self.polygons > 0
+    }
 }
 
 pub struct Search {
     config: SearchConfig,
-    hierarchies: Vec<(Pin<Box<ffi::Polygons>>, Vec<PolyInfo>)>,
-    district_par: RwLock<HashMap<String, Vec<(Pin<Box<ffi::Polygons>>, Vec<PolyInfo>)>>>,
-    district_par_any: RwLock<HashMap<String, Vec<(Pin<Box<ffi::Polygons>>, Vec<PolyInfo>)>>>,
+    hierarchies: RwLock<Vec<Arc<Polys>>>,
+    district_par: RwLock<HashMap<String, Vec<Polys>>>,
+    district_par_any: RwLock<HashMap<String, Vec<Polys>>>,
+    lookup: RwLock<NameIndex>,
+    autocomplete: RwLock<Autocomplete>,
+    cache: Option<Cache<Vec<Info>>>,
+    states: RwLock<HashMap<String, DistrictState>>,
+    clock: AtomicU64,
+    load_lock: Mutex<()>,
+    pending: Mutex<VecDeque<String>>,
+    pending_cv: Condvar,
+    lazy_stats: LazyCounters,
+    interner: Option<Interner>,
+    progress: Progress,
+    // loaded shapefiles, their level, indexed polygons and rejected ones,
+    // polygons with a ring left out or not loaded at all
+    files: RwLock<HashMap<String, (i32, usize, usize)>>,
+    manifest: RwLock<Option<Manifest>>,
+    // false for snapshots, which stay out of the metrics of the current dataset
+    metrics: bool,
+    // footprint of the hierarchies and of the lower layers by district,
+    // updated as layers are loaded and dropped so status and metrics
+    // never walk the index
+    hierarchy_footprint: RwLock<Footprint>,
+    footprints: RwLock<HashMap<String, Footprint>>,
+    levels: Vec<i32>,
 }
 
 impl Search{
     pub fn new(config: SearchConfig) -> Result<Self> {
+        let cache = config.cache
+            .as_ref()
+            .map(|cache| Cache::new(cache.capacity, cache.shards));
+        let interner = config.compact.then(Interner::default);
+        let mut levels: Vec<i32> = config.hierarchies
+            .iter()
+            .chain(config.district_par.iter())
+            .chain(config.district_par_any.iter())
+            .filter_map(|name| config.shapefile.attributes.get(name))
+            .map(|attribute| attribute.level)
+            .collect();
+        levels.sort();
+        levels.dedup();
         Ok(
             Self {
                 config,
-                hierarchies: vec![],
+                hierarchies: RwLock::new(vec![]),
                 district_par: RwLock::new(HashMap::new()),
                 district_par_any: RwLock::new(HashMap::new()),
+                lookup: RwLock::new(NameIndex::default()),
+                autocomplete: RwLock::new(Autocomplete::default()),
+                cache,
+                states: RwLock::new(HashMap::new()),
+                clock: AtomicU64::new(0),
+                load_lock: Mutex::new(()),
+                pending: Mutex::new(VecDeque::new()),
+                pending_cv: Condvar::new(),
+                lazy_stats: LazyCounters::default(),
+                interner,
+                progress: Progress::default(),
+                files: RwLock::new(HashMap::new()),
+                manifest: RwLock::new(None),
+                metrics: true,
+                hierarchy_footprint: RwLock::new(Footprint::default()),
+                footprints: RwLock::new(HashMap::new()),
+                levels,
             }
         )
     }
 
+    /// Keeps the loads and searches of this instance out of the metrics,
+    /// which describe the current dataset.
+    pub fn without_metrics(mut self) -> Self {
+        self.metrics = false;
+        self
+    }
+
+    fn metrics(&self) -> Option<&'static Metrics> {
+        self.metrics.then(|| &*METRICS)
+    }
+
     pub fn load(&mut self) -> Result<()> {
+        self.discover()?;
+        self.load_layers()
+    }
+
+    /// Replaces `districts` with the folders found under the shapefile path
+    /// when `discover` is set.
+    pub fn discover(&mut self) -> Result<()> {
+        if self.config.discover {
+            self.config.districts = crate::discover::districts(&self.config)
+                .map_err(|e| format!("failed to discover districts: {}", e))?;
+            tracing::info!(districts = ?self.config.districts, "discovered districts");
+        }
+        Ok(())
+    }
+
+    /// Loads every layer. Searches are answered meanwhile from the layers
+    /// loaded so far, the upper levels first and the parcels last.
+    pub fn load_layers(&self) -> Result<()> {
+        let result = self.load_all_layers();
+        if let Err(e) = &result {
+            self.progress.fail(&e.to_string());
+        }
+        result
+    }
+
+    fn load_all_layers(&self) -> Result<()> {
         let sw = Stopwatch::start_new();
         defer! {
-            println!("Search load elapsed: {:?}", sw.elapsed());
+            tracing::info!(elapsed = ?sw.elapsed(), "search load");
+            if let Some(metrics) = self.metrics() {
+                metrics.observe_load(sw.elapsed());
+            }
         }
 
-        let config = &self.config.clone();
+        let config = &self.config;
+        let _load = tracing::info_span!("load", districts = config.districts.len()).entered();
+        self.clear_cache();
+        self.start_progress();
        
-        config.districts
-            .iter()
-            .for_each(|district| {
-                if let Ok(mut district_par) 
-                    = self.district_par.write() {
-                        district_par.insert(district.to_string(), vec![]);     
-                }
-                if let Ok(mut district_par_any) 
-                    = self.district_par_any.write() {
-                        district_par_any.insert(district.to_string(), vec![]);     
-                }
-            });
-    
+        let hierarchies = tracing::info_span!("load_hierarchies").entered();
         config.districts.iter().try_for_each(|district| -> Result<()> {
             config.hierarchies.iter().try_for_each(|name| {
                 let attribute = config
@@ -113,50 +393,69 @@ impl Search{
 
                 let shapefile_path = format!("{}/{}/{}.shp", config.shapefile.path, district, name);
                 if !std::path::Path::new(&shapefile_path).exists() {
-                    println!("skip missing shapefile: {shapefile_path}");
+                    tracing::warn!(file = %shapefile_path, district, layer = %name, "skip missing shapefile");
+                    self.progress.record(name, district, true);
                     return Ok(());
                 }
 
                 let result = self.get_polygons(
                     district,
-                    attribute.level,
+                    attribute,
                     shapefile_path.as_str(),
-                    &attribute.names.as_ref(),
                     config.debug,
                     &config.debug_name,
-                )?;
+                );
+                self.progress.record(name, district, result.is_ok());
 
-                self.hierarchies.push(result);
+                self.hierarchies.write().map_err(|_| "failed to lock write")?.push(Arc::new(result?));
                 Ok(())
             })
         })?;
+        *self.hierarchy_footprint.write().map_err(|_| "failed to lock write")?
+            = Footprint::of(self.hierarchies().iter().map(|polys| &**polys));
+        self.build_lookup()?;
+        drop(hierarchies);
 
-        config.districts
-            .par_iter()
-            .try_for_each(|district| {
-                config.district_par
-                    .par_iter()
-                    .try_for_each(|name|
-                        self.load_district_par_polygons(
-                            config,
-                            district, 
-                            name,
-                            config.debug, 
-                            &config.debug_name,
-                        )
-                    )?;
-                config.district_par_any
-                    .par_iter()
-                    .try_for_each(|name|
-                        self.load_district_par_any_polygons(
-                            config,
-                            district, 
-                            name,
-                            config.debug, 
-                            &config.debug_name,
-                        )
-                    )
-            })?;
+        // lazy mode loads the lower levels of a district on first request
+        if config.lazy.is_none() {
+            let phase = tracing::info_span!("load_district_par").entered();
+            config.districts
+                .par_iter()
+                .try_for_each(|district| -> Result<()> {
+                    let _span = tracing::info_span!(parent: &*phase, "load_district", %district)
+                        .entered();
+                    let par = self.load_par_layers(config, district)?;
+                    self.district_par.write().map_err(|_| "failed to lock write")?
+                        .insert(district.to_string(), par);
+                    self.update_footprint(district)
+                })?;
+            self.build_lookup()?;
+            drop(phase);
+
+            let phase = tracing::info_span!("load_district_par_any").entered();
+            config.districts
+                .par_iter()
+                .try_for_each(|district| -> Result<()> {
+                    let _span = tracing::info_span!(parent: &*phase, "load_district", %district)
+                        .entered();
+                    let par_any = self.load_par_any_layers(config, district)?;
+                    self.district_par_any.write().map_err(|_| "failed to lock write")?
+                        .insert(district.to_string(), par_any);
+                    self.update_footprint(district)?;
+                    self.insert_state(config, district)
+                })?;
+            if config.parcel_rank {
+                self.build_lookup()?;
+            }
+            drop(phase);
+        }
+
+        if config.check_codes {
+            self.check_codes()?;
+        }
+        self.clear_cache();
+        self.progress.finish();
+        self.build_manifest(sw.elapsed().as_secs_f64());
 
         // warm up the index
         let _ = self.search(127.1, 35.1);
@@ -164,12 +463,303 @@ impl Search{
         Ok(())
     }
 
+    fn start_progress(&self) {
+        let config = &self.config;
+        let level = |name: &String| config.shapefile.attributes
+            .get(name)
+            .map(|attribute| attribute.level)
+            .unwrap_or_default();
+        let districts = config.districts.len();
+        let lower = if config.lazy.is_none() { districts } else { 0 };
+        let layers = config.hierarchies
+            .iter()
+            .map(|name| (name.to_string(), level(name), Gate::Partial, districts))
+            .chain(config.district_par
+                .iter()
+                .map(|name| (name.to_string(), level(name), Gate::Partial, lower)))
+            .chain(config.district_par_any
+                .iter()
+                .map(|name| (name.to_string(), level(name), Gate::Full, lower)))
+            .collect();
+        self.progress.start(layers);
+    }
+
+    /// Per layer loading progress and the partial and full readiness gates.
+    pub fn readiness(&self) -> Readiness {
+        self.progress.readiness()
+    }
+
+    /// Levels whose layers are still loading, a response without them may be incomplete.
+    pub fn loading_levels(&self) -> Vec<i32> {
+        self.progress.loading_levels()
+    }
+
+    /// Whether the dataset loaded and how many polygons each layer indexes.
+    pub fn load_status(&self) -> LoadStatus {
+        let readiness = self.progress.readiness();
+        let polygons = self.footprint().polygons;
+        let mut rejected: HashMap<i32, usize> = HashMap::new();
+        if let Ok(files) = self.files.read() {
+            files.values().for_each(|(level, _, count)| *rejected.entry(*level).or_insert(0) += count);
+        }
+
+        let layers: Vec<LayerStatus> = readiness.layers
+            .iter()
+            .map(|layer| LayerStatus {
+                layer: layer.layer.clone(),
+                level: layer.level,
+                polygons: polygons.get(&layer.level).copied().unwrap_or(0),
+                rejected: rejected.get(&layer.level).copied().unwrap_or(0),
+                districts: layer.loaded,
+                failed: layer.failed,
+            })
+            .collect();
+        LoadStatus {
+            loaded: readiness.full && readiness.error.is_none(),
+            partial: readiness.partial,
+            error: readiness.error,
+            polygons: layers.iter().map(|layer| layer.polygons).sum(),
+            layers,
+        }
+    }
+
+    /// Version of the manifest, "unknown" until the load finishes.
+    pub fn dataset_version(&self) -> String {
+        self.manifest
+            .read()
+            .ok()
+            .and_then(|manifest| manifest.as_ref().map(|manifest| manifest.version.clone()))
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Manifest of the dataset with the polygons indexed and rejected per
+    /// file and layer so far, `None` until the load finishes.
+    pub fn manifest(&self) -> Option<Manifest> {
+        let mut manifest = self.manifest.read().ok()?.clone()?;
+        let root = std::path::Path::new(&self.config.shapefile.path);
+        if let Ok(files) = self.files.read() {
+            let loaded: HashMap<std::path::PathBuf, (usize, usize)> = files
+                .iter()
+                .map(|(path, (_, polygons, rejected))| (path.into(), (*polygons, *rejected)))
+                .collect();
+            manifest.files.iter_mut().for_each(|file| {
+                if let Some((polygons, rejected)) = loaded.get(&root.join(&file.name)) {
+                    file.polygons = Some(*polygons);
+                    file.rejected = Some(*rejected);
+                }
+            });
+        }
+        let status = self.load_status();
+        manifest.polygons = status.polygons;
+        manifest.layers = status.layers;
+        Some(manifest)
+    }
+
+    // lists and hashes the configured shapefiles once the load is done
+    fn build_manifest(&self, load_secs: f64) {
+        let sw = Stopwatch::start_new();
+        let entries = crate::discover::layer_files(&self.config)
+            .map_err(|e| e.to_string())
+            .and_then(|files| manifest::file_entries(&self.config.shapefile.path, &files)
+                .map_err(|e| e.to_string()));
+        match entries {
+            Ok(entries) => {
+                let manifest = Manifest::new(entries, manifest::unix_now(), load_secs);
+                tracing::info!(version = %manifest.version, files = manifest.files.len(),
+                    elapsed = ?sw.elapsed(), "manifest");
+                if let Ok(mut current) = self.manifest.write() {
+                    *current = Some(manifest);
+                }
+            }
+            Err(e) => tracing::warn!(error = %e, "failed to build the manifest"),
+        }
+    }
+
+    // a reload swaps the lower layer files of the district in the manifest
+    fn update_manifest(&self, config: &SearchConfig, district: &str) {
+        let layers: Vec<String> = config.district_par
+            .iter()
+            .chain(config.district_par_any.iter())
+            .cloned()
+            .collect();
+        let mut district_config = config.clone();
+        district_config.discover = false;
+        district_config.districts = vec![district.to_string()];
+        district_config.hierarchies = vec![];
+        let entries = crate::discover::layer_files(&district_config)
+            .map_err(|e| e.to_string())
+            // named under the configured path like the rest of the manifest,
+            // `manifest` looks the polygon counts up from there
+            .and_then(|files| manifest::file_entries(&self.config.shapefile.path, &files)
+                .map_err(|e| e.to_string()));
+        match (entries, self.manifest.write()) {
+            (Ok(entries), Ok(mut current)) => {
+                if let Some(manifest) = current.as_mut() {
+                    manifest.replace_district(district, &layers, entries);
+                    tracing::info!(version = %manifest.version, district, "manifest");
+                }
+            }
+            (Err(e), _) => tracing::warn!(error = %e, district, "failed to update the manifest"),
+            (_, Err(_)) => tracing::warn!(district, "failed to lock the manifest"),
+        }
+    }
+
+    fn hierarchies(&self) -> Vec<Arc<Polys>> {
+        self.hierarchies.read().map(|hierarchies| hierarchies.clone()).unwrap_or_default()
+    }
+
+    fn build_lookup(&self) -> Result<()> {
+        let sw = Stopwatch::start_new();
+        defer! {
+            tracing::info!(elapsed = ?sw.elapsed(), "build_lookup");
+        }
+
+        let region = |layer: Layer, index: usize, info: &PolyInfo| Region {
+            code: info.code.to_string(),
+            level: info.level,
+            name: info.name.to_string(),
+            eng_name: info.eng_name.to_string(),
+            district: info.district.to_string(),
+            centroid: info.centroid,
+            layer,
+            index,
+        };
+
+        let mut regions = vec![];
+        self.hierarchies().iter().enumerate().for_each(|(i, polys)| {
+            polys.1.iter().enumerate().for_each(|(index, info)| {
+                regions.push(region(Layer::Hierarchy(i), index, info));
+            });
+        });
+
+        let district_par = self.district_par.read().map_err(|_| "failed to lock read")?;
+        district_par.iter().for_each(|(district, layers)| {
+            layers.iter().enumerate().for_each(|(i, polys)| {
+                polys.1.iter().enumerate().for_each(|(index, info)| {
+                    regions.push(region(
+                        Layer::DistrictPar(district.to_string(), i), index, info));
+                });
+            });
+        });
+
+        drop(district_par);
+
+        let parcels = if self.config.parcel_rank {
+            self.parcel_counts()?
+        } else {
+            HashMap::new()
+        };
+
+        let index = NameIndex::new(regions);
+        let autocomplete = Autocomplete::new(index.regions(), &parcels);
+        tracing::info!(regions = index.len(), "name index");
+        *self.lookup.write().map_err(|_| "failed to lock write")? = index;
+        *self.autocomplete.write().map_err(|_| "failed to lock write")? = autocomplete;
+        Ok(())
+    }
+
+    // number of parcels whose centroid lies in each administrative region
+    fn parcel_counts(&self) -> Result<HashMap<(Layer, usize), usize>> {
+        let district_par = self.district_par.read().map_err(|_| "failed to lock read")?;
+        let district_par_any 
+            = self.district_par_any.read().map_err(|_| "failed to lock read")?;
+        let hierarchies = self.hierarchies();
+        let counts = district_par_any
+            .par_iter()
+            .map(|(district, parcels)| {
+                let mut layers: Vec<(Layer, &Pin<Box<ffi::Polygons>>)> = hierarchies
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, polys)| polys.1.iter().any(|info| &*info.district == district.as_str()))
+                    .map(|(i, polys)| (Layer::Hierarchy(i), &polys.0))
+                    .collect();
+                if let Some(b) = district_par.get(district) {
+                    b.iter().enumerate().for_each(|(i, polys)| {
+                        layers.push((Layer::DistrictPar(district.to_string(), i), &polys.0));
+                    });
+                }
+
+                let mut counts = HashMap::new();
+                parcels.iter().flat_map(|polys| polys.1.iter()).for_each(|info| {
+                    layers.iter().for_each(|(layer, polys)| {
+                        let j = i32::from(polys.search(info.centroid.0, info.centroid.1));
+                        if j >= 0 {
+                            *counts.entry((layer.clone(), j as usize)).or_insert(0) += 1;
+                        }
+                    });
+                });
+                counts
+            })
+            .reduce(HashMap::new, |mut a, b| {
+                b.into_iter().for_each(|(k, v)| *a.entry(k).or_insert(0) += v);
+                a
+            });
+        Ok(counts)
+    }
+
+    pub fn autocomplete(&self, query: &str, limit: usize) -> Result<Vec<Suggestion>> {
+        let autocomplete = self.autocomplete.read().map_err(|_| "failed to lock read")?;
+        Ok(autocomplete.suggest(query, limit).into_iter().cloned().collect())
+    }
+
+    fn region_geometry(&self, region: &Region) -> Result<Vec<(f64, f64)>> {
+        let lnglats = |polys: &Pin<Box<ffi::Polygons>>| -> Vec<(f64, f64)> {
+            polys.polygon(c_int(region.index as i32))
+                .lnglats()
+                .iter()
+                .map(|ll| (ll.lng(), ll.lat()))
+                .collect()
+        };
+
+        match &region.layer {
+            Layer::Hierarchy(i) => self.hierarchies()
+                .get(*i)
+                .map(|polys| lnglats(&polys.0))
+                .chain_err(|| format!("hierarchy layer {}", i)),
+            Layer::DistrictPar(district, i) => {
+                let a = self.district_par.read().map_err(|_| "failed to lock read")?;
+                a.get(district)
+                    .and_then(|b| b.get(*i))
+                    .map(|polys| lnglats(&polys.0))
+                    .chain_err(|| format!("district par layer {} {}", district, i))
+            }
+        }
+    }
+
+    pub fn search_regions(&self, query: &str, limit: usize, geometry: bool)
+        -> Result<Vec<RegionMatch>> {
+        let lookup = self.lookup.read().map_err(|_| "failed to lock read")?;
+        lookup.search(query, limit)
+            .into_iter()
+            .map(|(region, match_type)| {
+                Ok(RegionMatch {
+                    code: region.code.clone(),
+                    level: region.level,
+                    name: region.name.clone(),
+                    eng_name: region.eng_name.clone(),
+                    district: region.district.clone(),
+                    centroid: region.centroid,
+                    parents: lookup.parents(region)
+                        .iter()
+                        .map(|parent| parent.name.clone())
+                        .collect(),
+                    match_type,
+                    geometry: if geometry {
+                        Some(self.region_geometry(region)?)
+                    } else {
+                        None
+                    },
+                })
+            })
+            .collect()
+    }
+
     fn load_district_par_any_polygons(&self, config: &SearchConfig, district: &str,
-        name: &str, debug: bool, debug_name: &str) -> Result<()> {
+        name: &str, debug: bool, debug_name: &str) -> Result<Vec<Polys>> {
         let sw = Stopwatch::start_new();
         defer!({
-            println!("load_district_par_any_polygons {} {} elapsed: {:?}", 
-                district, name, sw.elapsed());
+            tracing::info!(district, layer = name, elapsed = ?sw.elapsed(), 
+                "load_district_par_any_polygons");
         });
        
         let file_pattern = format!(
@@ -183,203 +773,939 @@ impl Search{
             .chain_err(|| "failed to read glob pattern")?
             .collect();
 
-        paths.par_iter().try_for_each(|entry| {
-                match entry {
-                    Ok(path) => {
-                        if let Some(path) = path.to_str() {
-                            if let Some(attribute) 
-                                = config.shapefile.attributes.get(name) {  
-                                if let Ok(result) = self.get_polygons(
-                                    district, 
-                                    attribute.level,
-                                    path, 
-                                    &attribute.names.as_ref(), 
-                                     config.debug, 
-                                    &config.debug_name,
-                                ) {
-                                    if let Ok(mut a) 
-                                        = self.district_par_any.write() {
-                                        if let Some(b) 
-                                            = a.get_mut(district) {
-                                            b.push(result);
-                                        }
-                                    } 
-                                }
-                            }
-                        } 
-                        Ok(())
-                    }
-                    Err(e) => {
-                        return Err(format!("match enty {:?}", e));
-                    }
-                }
-            })?;
-        Ok(())
+        let attribute = config.shapefile.attributes.get(name)
+            .chain_err(|| format!("failed to get attributes for {}", name))?;
+        let parent = Span::current();
+        // a part that fails to load fails the layer, parcels are never
+        // missing from a district reported as loaded
+        paths.par_iter().map(|entry| {
+                let _span = tracing::info_span!(parent: &parent, "load_part").entered();
+                let path = match entry {
+                    Ok(path) => path.display().to_string(),
+                    Err(e) => bail!("match enty {:?}", e),
+                };
+                self.get_polygons(district, attribute, &path, debug, debug_name)
+                    .map_err(|e| {
+                        tracing::error!(file = %path, district, layer = name, error = %e,
+                            "failed to load part");
+                        e
+                    })
+                    .chain_err(|| format!("failed to get polygons for {} {}", district, path))
+            })
+            .collect()
     }
 
     fn load_district_par_polygons(&self, config: &SearchConfig, district: &str,
-        name: &str, debug: bool, debug_name: &str) -> Result<()> {
+        name: &str, debug: bool, debug_name: &str) -> Result<Vec<Polys>> {
         let sw = Stopwatch::start_new();
         defer!({
-            println!("load_district_polygons {} {} elapsed: {:?}", 
-                district, name, sw.elapsed());
+            tracing::info!(district, layer = name, elapsed = ?sw.elapsed(), 
+                "load_district_polygons");
         });
 
         let path = format!("{}/{}/{}.shp", 
             config.shapefile.path, district, name);
+        if !std::path::Path::new(&path).exists() {
+            tracing::warn!(file = %path, district, layer = name, "skip missing shapefile");
+            return Ok(vec![]);
+        }
         let attribute = config.shapefile.attributes.get(name)
             .chain_err(|| format!("failed to get attributes for {}", name))?;
         let result = self.get_polygons(
             district, 
-            attribute.level,
+            attribute,
             path.as_str(), 
-            &attribute.names.as_ref(), 
             debug, 
             debug_name,
         ).chain_err(|| format!("failed to get polygons for {} {}", district, name))?;
 
-        let mut a 
-            = self.district_par.write().map_err(|_| "failed to lock write")?;
-        let b 
-            = a.get_mut(district).chain_err(|| 
-                format!("failed to get polys_vec {}", district))?;
-        
-        b.push(result);
+        Ok(vec![result])
+    }
+
+    fn load_par_layers(&self, config: &SearchConfig, district: &str) -> Result<Vec<Polys>> {
+        let parent = Span::current();
+        let layers = config.district_par
+            .par_iter()
+            .map(|name| {
+                let _span = tracing::info_span!(parent: &parent, "load_layer", layer = %name, %district)
+                    .entered();
+                let result = self.load_district_par_polygons(
+                    config,
+                    district, 
+                    name,
+                    config.debug, 
+                    &config.debug_name,
+                );
+                self.progress.record(name, district, result.is_ok());
+                result
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(layers.into_iter().flatten().collect())
+    }
 
+    fn load_par_any_layers(&self, config: &SearchConfig, district: &str) -> Result<Vec<Polys>> {
+        let parent = Span::current();
+        let layers = config.district_par_any
+            .par_iter()
+            .map(|name| {
+                let _span = tracing::info_span!(parent: &parent, "load_layer", layer = %name, %district)
+                    .entered();
+                let result = self.load_district_par_any_polygons(
+                    config,
+                    district, 
+                    name,
+                    config.debug, 
+                    &config.debug_name,
+                );
+                self.progress.record(name, district, result.is_ok());
+                result
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(layers.into_iter().flatten().collect())
+    }
+
+    // footprint of the lower layers of a district after they changed
+    fn update_footprint(&self, district: &str) -> Result<()> {
+        let footprint = {
+            let par = self.district_par.read().map_err(|_| "failed to lock read")?;
+            let par_any = self.district_par_any.read().map_err(|_| "failed to lock read")?;
+            Footprint::of(par.get(district).into_iter().chain(par_any.get(district)).flatten())
+        };
+        self.footprints.write().map_err(|_| "failed to lock write")?
+            .insert(district.to_string(), footprint);
         Ok(())
     }
 
-    fn get_polygons(&self, district: &str, level: i32, shapefile: &str, 
-        attributes: &Vec<String>, debug: bool, debug_name: &str)
-        -> Result<(Pin<Box<ffi::Polygons>>, Vec<PolyInfo>)> {
+    /// Polygons and bytes of every loaded layer, kept up to date by the
+    /// loads instead of measured.
+    pub fn footprint(&self) -> Footprint {
+        let mut footprint = self.hierarchy_footprint
+            .read()
+            .map(|footprint| footprint.clone())
+            .unwrap_or_default();
+        if let Ok(footprints) = self.footprints.read() {
+            footprints.values().for_each(|district| footprint.add(district));
+        }
+        footprint
+    }
+
+    // bookkeeping of a district whose lower layers were just loaded
+    fn insert_state(&self, config: &SearchConfig, district: &str) -> Result<()> {
+        let state = {
+            let par = self.district_par.read().map_err(|_| "failed to lock read")?;
+            let par_any = self.district_par_any.read().map_err(|_| "failed to lock read")?;
+            let layers: Vec<&Polys> = par.get(district)
+                .into_iter()
+                .chain(par_any.get(district))
+                .flatten()
+                .collect();
+            DistrictState {
+                path: config.shapefile.path.clone(),
+                layers: layers.len(),
+                bytes: layers.iter().map(|polys| polys_bytes(polys)).sum(),
+                polygons: layers.iter().map(|polys| polys.1.len()).sum(),
+                last_used: AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed)),
+            }
+        };
+        tracing::info!(district, polygons = state.polygons, bytes = state.bytes, "load_district");
+
+        self.states.write().map_err(|_| "failed to lock write")?
+            .insert(district.to_string(), state);
+        self.lazy_stats.loads.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Loads the `district_par` and `district_par_any` layers of a district,
+    /// replacing the layers loaded before.
+    pub fn load_district(&self, district: &str) -> Result<()> {
+        self.load_district_from(&self.config, district)
+    }
+
+    fn load_district_from(&self, config: &SearchConfig, district: &str) -> Result<()> {
+        let sw = Stopwatch::start_new();
+        defer!({
+            tracing::info!(district, elapsed = ?sw.elapsed(), "load_district");
+        });
+
+        let (par, par_any) = rayon::join(
+            || self.load_par_layers(config, district),
+            || self.load_par_any_layers(config, district),
+        );
+        let (par, par_any) = (par?, par_any?);
+
+        self.district_par.write().map_err(|_| "failed to lock write")?
+            .insert(district.to_string(), par);
+        self.district_par_any.write().map_err(|_| "failed to lock write")?
+            .insert(district.to_string(), par_any);
+        self.update_footprint(district)?;
+        self.insert_state(config, district)?;
+
+        self.evict(district)
+    }
+
+    /// Reloads a district from `path`, the configured shapefile path if `None`.
+    /// `path` has to be a directory under the configured shapefile path.
+    /// Queries of other districts keep running while the shapefiles load.
+    pub fn reload_district(&self, district: &str, path: Option<&str>) -> Result<()> {
+        if !self.config.districts.iter().any(|d| d == district) {
+            bail!("unknown district {}", district);
+        }
+
+        let mut config = self.config.clone();
+        if let Some(path) = path {
+            let root = std::fs::canonicalize(&self.config.shapefile.path)
+                .chain_err(|| format!("shapefile path {}", self.config.shapefile.path))?;
+            let canonical = std::fs::canonicalize(path)
+                .chain_err(|| format!("reload path {}", path))?;
+            if !canonical.starts_with(&root) {
+                bail!("reload path {} is outside {}", path, self.config.shapefile.path);
+            }
+            config.shapefile.path = path.to_string();
+        }
+        // the files of the previous load may be under another path
+        self.forget_files(district)?;
+        self.load_district_from(&config, district)?;
+        self.build_lookup()?;
+        self.clear_cache();
+        self.update_manifest(&config, district);
+        Ok(())
+    }
+
+    /// Unloads the `district_par` and `district_par_any` layers of a district,
+    /// false if it was not loaded.
+    pub fn unload_district(&self, district: &str) -> Result<bool> {
+        let unloaded = self.drop_district(district)?;
+        if unloaded {
+            self.build_lookup()?;
+            self.clear_cache();
+        }
+        Ok(unloaded)
+    }
+
+    fn drop_district(&self, district: &str) -> Result<bool> {
+        self.district_par.write().map_err(|_| "failed to lock write")?.remove(district);
+        self.district_par_any.write().map_err(|_| "failed to lock write")?.remove(district);
+        self.footprints.write().map_err(|_| "failed to lock write")?.remove(district);
+        self.forget_files(district)?;
+        let state = self.states.write().map_err(|_| "failed to lock write")?.remove(district);
+        Ok(state.is_some())
+    }
+
+    // drops the polygon counts of the lower layers of a district, whatever
+    // path they were loaded from
+    fn forget_files(&self, district: &str) -> Result<()> {
+        let config = &self.config;
+        let lower = |file: &str| {
+            let file = std::path::Path::new(file);
+            let in_district = file.parent()
+                .and_then(|dir| dir.file_name())
+                .is_some_and(|dir| dir == district);
+            let stem = file.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            in_district && (config.district_par.iter().any(|name| stem == name)
+                || config.district_par_any.iter().any(|name| stem.starts_with(name.as_str())))
+        };
+        self.files.write().map_err(|_| "failed to lock write")?.retain(|file, _| !lower(file));
+        Ok(())
+    }
+
+    pub fn districts(&self) -> Result<Vec<DistrictStats>> {
+        let states = self.states.read().map_err(|_| "failed to lock read")?;
+        Ok(self.config.districts
+            .iter()
+            .map(|district| match states.get(district) {
+                Some(state) => DistrictStats {
+                    district: district.to_string(),
+                    loaded: true,
+                    path: state.path.clone(),
+                    layers: state.layers,
+                    polygons: state.polygons,
+                    bytes: state.bytes,
+                    last_used: state.last_used.load(Ordering::Relaxed),
+                },
+                None => DistrictStats {
+                    district: district.to_string(),
+                    loaded: false,
+                    path: self.config.shapefile.path.clone(),
+                    layers: 0,
+                    polygons: 0,
+                    bytes: 0,
+                    last_used: 0,
+                },
+            })
+            .collect())
+    }
+
+    // least recently used districts over the memory budget, except `keep`
+    fn evict(&self, keep: &str) -> Result<()> {
+        let budget = self.config.lazy
+            .as_ref()
+            .map(|lazy| lazy.memory_budget_mb * 1024 * 1024)
+            .unwrap_or(0);
+        if budget == 0 {
+            return Ok(());
+        }
+
+        let victims = {
+            let states = self.states.read().map_err(|_| "failed to lock read")?;
+            let districts: Vec<(&str, usize, u64)> = states
+                .iter()
+                .map(|(district, state)| (district.as_str(), state.bytes,
+                    state.last_used.load(Ordering::Relaxed)))
+                .collect();
+            eviction_victims(&districts, budget, keep)
+        };
+        for victim in victims {
+            tracing::info!(district = %victim, "evict district");
+            self.drop_district(&victim)?;
+            self.lazy_stats.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    // loads the district on first use in lazy mode, false while it is not loaded.
+    // Eager mode searches whatever layers of the district are loaded so far.
+    fn ensure_district(&self, district: &str) -> bool {
+        if let Ok(states) = self.states.read() {
+            if let Some(state) = states.get(district) {
+                state.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+                return true;
+            }
+        }
+
+        let Some(lazy) = &self.config.lazy else {
+            return true;
+        };
+        if !self.config.districts.iter().any(|d| d == district) {
+            return false;
+        }
+
+        if lazy.partial {
+            if let Ok(mut pending) = self.pending.lock() {
+                if !pending.iter().any(|d| d == district) {
+                    pending.push_back(district.to_string());
+                    self.pending_cv.notify_one();
+                }
+            }
+            return false;
+        }
+
+        let Ok(_guard) = self.load_lock.lock() else {
+            return false;
+        };
+        if self.states.read().map(|states| states.contains_key(district)).unwrap_or(false) {
+            return true;
+        }
+        match self.load_district(district).and_then(|_| self.build_lookup()) {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!(district, error = %e, "failed to load district");
+                false
+            }
+        }
+    }
+
+    /// Loads the districts queued by searches in partial lazy mode, never returns.
+    pub fn serve_pending(&self) {
+        loop {
+            let district = {
+                let Ok(mut pending) = self.pending.lock() else {
+                    return;
+                };
+                while pending.is_empty() {
+                    pending = match self.pending_cv.wait(pending) {
+                        Ok(pending) => pending,
+                        Err(_) => return,
+                    };
+                }
+                pending.front().cloned()
+            };
+
+            if let Some(district) = district {
+                if let Err(e) = self.load_district(&district).and_then(|_| self.build_lookup()) {
+                    tracing::error!(%district, error = %e, "failed to load district");
+                }
+                if let Ok(mut pending) = self.pending.lock() {
+                    pending.retain(|d| *d != district);
+                }
+            }
+        }
+    }
+
+    pub fn lazy_stats(&self) -> LazyStats {
+        let (districts, bytes) = self.states
+            .read()
+            .map(|states| (states.len(), states.values().map(|state| state.bytes).sum()))
+            .unwrap_or((0, 0));
+        LazyStats {
+            loads: self.lazy_stats.loads.load(Ordering::Relaxed),
+            evictions: self.lazy_stats.evictions.load(Ordering::Relaxed),
+            districts,
+            bytes,
+        }
+    }
+
+    fn get_polygons(&self, district: &str, attribute: &Attribute, shapefile: &str, 
+        debug: bool, debug_name: &str)
+        -> Result<Polys> {
+        let _span = tracing::info_span!("get_polygons", %shapefile).entered();
+        let level = attribute.level;
+        let mut columns = attribute.names.clone();
+        columns.extend(attribute.code.iter().cloned());
         let shapes: (Vec<Shape>, Vec<Vec<String>>) 
-            = utils::shape::load_shape(shapefile, attributes)
+            = utils::shape::load_shape(shapefile, &columns)
                 .chain_err(|| format!("{}", shapefile))?;
         let mut polys = ffi::Polygons::new().within_box();
         let mut polys_infos = vec![];
+        let mut rejected = 0;
+        let mut codes: HashMap<&str, usize> = HashMap::new();
         shapes.0.iter().enumerate().for_each(|(shape_idx, shape)| {
             match shape {
                 Shape::Polygon(gp) => {
-                    let name = shapes.1[shape_idx][1].as_str();
+                    let attributes = &shapes.1[shape_idx];
+                    let name = attributes[1].as_str();
                     let polygon 
-                        = utils::shape::load_polygon(shapefile, gp, name, debug, debug_name);
+                        = utils::shape::load_polygon_codes(shapefile, gp, name, debug, debug_name);
                     match polygon {
-                        Ok(polygon) => {
+                        Ok((polygon, ring_codes)) => {
+                            ring_codes.iter().for_each(|code| {
+                                *codes.entry(error_code_label(*code)).or_insert(0) += 1;
+                            });
+                            if !ring_codes.is_empty() {
+                                rejected += 1;
+                            }
                             if debug {
-                                println!("loading {} {} {} rings {:?}", 
-                                    shapefile, name, shape_idx, gp.rings().len());
+                                tracing::debug!(file = shapefile, name, feature = shape_idx, 
+                                    rings = gp.rings().len(), "loading");
                             }
 
                             polys.as_mut().add(polygon);
                             polys_infos.push(
                                 PolyInfo {
-                                    district: district.to_string(),
+                                    district: self.intern(district),
                                     level,
-                                    name: name.to_string(),
+                                    name: self.intern(name),
+                                    code: self.intern(attributes
+                                        .get(attribute.names.len())
+                                        .unwrap_or(&attributes[0])),
+                                    eng_name: self.intern(attributes
+                                        .get(2)
+                                        .map(|s| s.as_str())
+                                        .unwrap_or_default()),
+                                    centroid: utils::shape::centroid(gp),
                                 }
                             );
                         },
                         Err(e) => {
-                            println!("loading error {} {} rings {:?}", 
-                                name, shape_idx, gp.rings().len());
+                            tracing::warn!(file = shapefile, name, feature = shape_idx, 
+                                rings = gp.rings().len(), error = %e, "loading error");
+                            rejected += 1;
+                            *codes.entry(error_code_label(-1)).or_insert(0) += 1;
                         }
                     }
                 },
                 _ => {}
             }
         });
+
+        self.files.write().map_err(|_| "failed to lock write")?
+            .insert(shapefile.to_string(), (level, polys_infos.len(), rejected));
+        let codes: Vec<(&str, usize)> = codes.into_iter().collect();
+        if let Some(metrics) = self.metrics() {
+            metrics.observe_polygons(level, polys_infos.len(), &codes);
+        }
+
+        if let Some(covering) = &self.config.covering {
+            polys.as_mut().build_covering(c_int(covering.max_cells), c_int(covering.max_level));
+            if debug {
+                tracing::debug!(file = shapefile, cells = polys.covering_size(), "covering");
+            }
+        }
+        if self.config.compact && !polys.as_mut().compact() {
+            bail!("failed to compact {}", shapefile);
+        }
+        crate::logging::drain_native();
         Ok((polys, polys_infos))
     }
 
+    fn intern(&self, s: &str) -> Arc<str> {
+        match &self.interner {
+            Some(interner) => interner.intern(s),
+            None => Arc::from(s),
+        }
+    }
+
+    /// Index and attribute bytes of every loaded layer.
+    pub fn memory_report(&self) -> Result<MemoryReport> {
+        let mut layers = vec![];
+        let mut push = |district: &str, polys: &Polys| {
+            layers.push(LayerMemory {
+                district: district.to_string(),
+                level: polys.1.first().map(|info| info.level).unwrap_or_default(),
+                polygons: polys.1.len(),
+                index_bytes: polys.0.space_used(),
+                info_bytes: info_bytes(&polys.1),
+                compact: polys.0.is_compact(),
+            });
+        };
+        self.hierarchies().iter().for_each(|polys| {
+            let district = polys.1.first().map(|info| info.district.to_string()).unwrap_or_default();
+            push(&district, polys);
+        });
+        for map in [&self.district_par, &self.district_par_any] {
+            let map = map.read().map_err(|_| "failed to lock read")?;
+            map.iter().for_each(|(district, layers)| {
+                layers.iter().for_each(|polys| push(district, polys));
+            });
+        }
+
+        Ok(MemoryReport {
+            index_bytes: layers.iter().map(|layer| layer.index_bytes).sum(),
+            info_bytes: layers.iter().map(|layer| layer.info_bytes).sum(),
+            interned_strings: self.interner.as_ref().map(|i| i.len()).unwrap_or(0),
+            interned_bytes: self.interner.as_ref().map(|i| i.bytes()).unwrap_or(0),
+            layers,
+        })
+    }
+
+    pub fn clear_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.stats())
+    }
+
     pub fn search(&self, lon: f64, lat: f64) -> Result<Vec<Info>> {
+        let _span = tracing::info_span!("search", lon, lat).entered();
+        let results = self.search_cached(lon, lat)?;
+        let found: Vec<i32> = results.iter().map(|info| info.level).collect();
+        if let Some(metrics) = self.metrics() {
+            metrics.observe_levels(&self.levels, &found);
+        }
+        Ok(results)
+    }
+
+    fn search_cached(&self, lon: f64, lat: f64) -> Result<Vec<Info>> {
+        let (Some(cache), Some(config)) = (&self.cache, &self.config.cache) else {
+            return self.search_layers(lon, lat);
+        };
+        // answers change while the layers are still loading
+        if !self.progress.is_finished() {
+            cache.skip();
+            return self.search_layers(lon, lat);
+        }
+
+        let cell = ffi::cell_id(lon, lat, c_int(config.level));
+        if let Some(results) = cache.get(cell) {
+            return Ok(results);
+        }
+
+        // a reload clearing the cache meanwhile voids the result
+        let generation = cache.generation();
+        let results = self.search_layers(lon, lat)?;
+        if self.is_exact_cell(cell, &results) {
+            cache.put(cell, results.clone(), generation);
+        } else {
+            cache.skip();
+        }
+        Ok(results)
+    }
+
+    // every probed layer resolves the whole cell to the same shape
+    fn is_exact_cell(&self, cell: u64, results: &[Info]) -> bool {
+        const CELL_MIXED: i32 = -2;
+        // neighbours are chosen by distance which varies inside the cell
+        if results.iter().any(|info| info.fallback) {
+            return false;
+        }
+        let exact = |polys: &Pin<Box<ffi::Polygons>>| i32::from(polys.cell_shape(cell)) != CELL_MIXED;
+        if !self.hierarchies().iter().all(|polys| exact(&polys.0)) {
+            return false;
+        }
+
+        let districts: Vec<&str> = results.iter().map(|info| info.district.as_str()).collect();
+        [&self.district_par, &self.district_par_any].iter().all(|layers| {
+            layers.read().map(|a| {
+                districts.iter().all(|district| {
+                    // a lazy district not loaded yet answers without lower levels
+                    a.get(*district)
+                        .map(|b| b.iter().all(|polys| exact(&polys.0)))
+                        .unwrap_or(self.config.lazy.is_none())
+                })
+            })
+            .unwrap_or(false)
+        })
+    }
+
+    fn search_layers(&self, lon: f64, lat: f64) -> Result<Vec<Info>> {
         let debug = self.config.debug;
+        let border_distance = self.config.border_distance;
         let mut results = vec![];
-        self.hierarchies.iter()
+        let parent = Span::current();
+        self.hierarchies().iter()
             .for_each(|polys| {
-                let r = polys.0.search(lon, lat);
-                let j = i32::from(r);
+                let j = probe(&parent, self.metrics(), polys, lon, lat);
                 if j >= 0 {
                     let info = &polys.1[j as usize];
                     if debug {         
-                        println!("{:?}", info);
-                    }
-                    results.push(Info {
-                        district: info.district.clone(),
-                        level: info.level,
-                        name: info.name.clone(),
-                        lnglats: vec![],
-                    });
-                    if let Ok(a) 
-                        = self.district_par.read() {
-                        if let Some(b) 
-                            = a.get(info.district.as_str()) {
-                            let infos: Vec<Info> = b.par_iter()
-                                .filter_map(|d| {
-                                let r = d.0.search(lon, lat);
-                                let j = i32::from(r);
-                                if j >= 0 {
-                                    let info = &d.1[j as usize];
-                                    if debug {         
-                                        println!("{:?}", info);
-                                    }
-                                    Some(Info {
-                                        district: info.district.clone(),
-                                        level: info.level,
-                                        name: info.name.clone(),
-                                        lnglats: vec![],
-                                    })
-                                } else {
-                                    None
-                                }
-                            }).collect();
-                            results.extend(infos);
-                        }
+                        tracing::debug!(?info, "found");
                     }
-                    if let Ok(a) 
-                        = self.district_par_any.read() {
-                        if let Some(b) 
-                            = a.get(info.district.as_str()) {
-                            let info = Mutex::new(None);   
-                            let exist = b.par_iter().any(|d| {
-                                let r = d.0.search_polygon(lon, lat);
-                                let j = i32::from(r.index());
-                                if j >= 0 {
-                                    let a = &d.1[j as usize];
-                                    if debug {         
-                                        println!("{:?}", a);
-                                    }
-                                    if let Ok(mut b) 
-                                        = info.lock() {
-                                        *b = Some(Info {
-                                            district: a.district.clone(),
-                                            level: a.level,
-                                            name: a.name.clone(),
-                                            lnglats: r.lnglats()
-                                                .iter()
-                                                .map(|ll| (ll.lng(), ll.lat()))
-                                                .collect(),
-                                        });
-                                    }
-                                    true
-                                } else {
-                                    false
-                                }
-                            });
-                            if exist {
-                                if let Ok(mut a) 
-                                    = info.lock() {
-                                    if let Some(b) = a.take() {
-                                        results.push(b.clone());
-                                    }
-                                } 
-                            }
-                        }
+                    results.push(info.to_info(vec![]));
+
+                    let mut lower = self.search_children(&info.district, info.level, lon, lat);
+                    if border_distance > 0.0 
+                        && (lower.is_empty() || polys.0.distance(lon, lat) <= border_distance) {
+                        let neighbours = self.search_neighbours(
+                            Some(&info.district), lon, lat, &lower);
+                        lower.extend(neighbours);
                     }
+                    results.extend(lower);
                 }
             }
         );
+
+        // the point fell into a gap between level 1 polygons
+        if results.is_empty() && border_distance > 0.0 {
+            results = self.search_neighbours(None, lon, lat, &[]);
+        }
         
         results.sort_by_key(|info| info.level);
         Ok(results)
     }
-}
\ No newline at end of file
+
+    // levels below the level 1 polygon of the district
+    fn search_children(&self, district: &str, level: i32, lon: f64, lat: f64) -> Vec<Info> {
+        let mut results = vec![];
+        if !self.ensure_district(district) {
+            return results;
+        }
+        let parcel = self.search_parcel(district, lon, lat);
+        if self.config.derive_parents {
+            let parents = parcel
+                .as_ref()
+                .and_then(|(_, code)| self.derive_parents(code, level));
+            if let Some(parents) = parents {
+                results.extend(parents);
+                results.extend(parcel.map(|(info, _)| info));
+                return results;
+            }
+        }
+
+        results.extend(self.search_district_par(district, lon, lat));
+        results.extend(parcel.map(|(info, _)| info));
+        results
+    }
+
+    // levels missing from `found` taken from districts within border distance
+    fn search_neighbours(&self, district: Option<&str>, lon: f64, lat: f64, found: &[Info])
+        -> Vec<Info> {
+        let mut levels: Vec<i32> = found.iter().map(|info| info.level).collect();
+        let mut results = vec![];
+        self.hierarchies().iter()
+            .filter_map(|polys| polys.1.first().map(|info| (polys, info)))
+            .filter(|(_, info)| Some(&*info.district) != district)
+            .filter(|(polys, _)| polys.0.distance(lon, lat) <= self.config.border_distance)
+            .for_each(|(_, info)| {
+                if self.config.debug {
+                    tracing::debug!(district = %info.district, lon, lat, "border fallback");
+                }
+                self.search_children(&info.district, info.level, lon, lat)
+                    .into_iter()
+                    .filter(|info| !levels.contains(&info.level))
+                    .for_each(|mut info| {
+                        levels.push(info.level);
+                        info.fallback = true;
+                        results.push(info);
+                    });
+            });
+        results
+    }
+
+    fn search_district_par(&self, district: &str, lon: f64, lat: f64) -> Vec<Info> {
+        let debug = self.config.debug;
+        if let Ok(a) 
+            = self.district_par.read() {
+            if let Some(b) 
+                = a.get(district) {
+                let parent = Span::current();
+                return b.par_iter()
+                    .filter_map(|d| {
+                    let j = probe(&parent, self.metrics(), d, lon, lat);
+                    if j >= 0 {
+                        let info = &d.1[j as usize];
+                        if debug {         
+                            tracing::debug!(?info, "found");
+                        }
+                        Some(info.to_info(vec![]))
+                    } else {
+                        None
+                    }
+                }).collect();
+            }
+        }
+        vec![]
+    }
+
+    // the parcel containing the point and its code
+    fn search_parcel(&self, district: &str, lon: f64, lat: f64) -> Option<(Info, String)> {
+        let debug = self.config.debug;
+        if let Ok(a) 
+            = self.district_par_any.read() {
+            if let Some(b) 
+                = a.get(district) {
+                let info = Mutex::new(None);   
+                let parent = Span::current();
+                let exist = b.par_iter().any(|d| {
+                    let _span = tracing::info_span!(parent: &parent, "probe_parcel",
+                        level = d.1.first().map(|info| info.level),
+                        %district)
+                        .entered();
+                    let start = Instant::now();
+                    let r = d.0.search_polygon(lon, lat);
+                    let j = i32::from(r.index());
+                    if let Some((metrics, info)) = self.metrics().zip(d.1.first()) {
+                        metrics.observe_probe(info.level, start.elapsed());
+                    }
+                    if j >= 0 {
+                        let a = &d.1[j as usize];
+                        if debug {         
+                            tracing::debug!(info = ?a, "found");
+                        }
+                        if let Ok(mut b) 
+                            = info.lock() {
+                            *b = Some((
+                                a.to_info(r.lnglats()
+                                    .iter()
+                                    .map(|ll| (ll.lng(), ll.lat()))
+                                    .collect()),
+                                a.code.to_string(),
+                            ));
+                        }
+                        true
+                    } else {
+                        false
+                    }
+                });
+                if exist {
+                    if let Ok(mut a) 
+                        = info.lock() {
+                        return a.take();
+                    } 
+                }
+            }
+        }
+        None
+    }
+
+    // regions below `level` whose code is a prefix of the parcel code
+    fn derive_parents(&self, code: &str, level: i32) -> Option<Vec<Info>> {
+        let lookup = self.lookup.read().ok()?;
+        let parents: Vec<Info> = lookup.parents_of(code)
+            .into_iter()
+            .filter(|region| region.level > level)
+            .map(|region| Info {
+                district: region.district.clone(),
+                level: region.level,
+                name: region.name.clone(),
+                code: region.code.clone(),
+                eng_name: region.eng_name.clone(),
+                lnglats: vec![],
+                fallback: false,
+            })
+            .collect();
+        if parents.is_empty() {
+            None
+        } else {
+            Some(parents)
+        }
+    }
+
+    // parcels whose code disagrees with the code of the region containing them
+    fn check_codes(&self) -> Result<()> {
+        let sw = Stopwatch::start_new();
+        defer! {
+            tracing::info!(elapsed = ?sw.elapsed(), "check_codes");
+        }
+
+        let district_par = self.district_par.read().map_err(|_| "failed to lock read")?;
+        let district_par_any 
+            = self.district_par_any.read().map_err(|_| "failed to lock read")?;
+        district_par_any.par_iter().for_each(|(district, parcels)| {
+            let Some(layers) = district_par.get(district) else {
+                return;
+            };
+            let mut mismatches = vec![0usize; layers.len()];
+            parcels.iter().flat_map(|polys| polys.1.iter()).for_each(|parcel| {
+                layers.iter().enumerate().for_each(|(i, polys)| {
+                    let j = i32::from(polys.0.search(parcel.centroid.0, parcel.centroid.1));
+                    if j < 0 {
+                        return;
+                    }
+                    let region = &polys.1[j as usize];
+                    if !region.code.is_empty() && !parcel.code.starts_with(&*region.code) {
+                        mismatches[i] += 1;
+                        if self.config.debug {
+                            tracing::warn!(%district, parcel = %parcel.code, name = %parcel.name, 
+                                region = %region.code, region_name = %region.name, "code mismatch");
+                        }
+                    }
+                });
+            });
+            layers.iter().zip(mismatches).for_each(|(polys, count)| {
+                let Some(info) = polys.1.first() else {
+                    return;
+                };
+                if count == 0 {
+                    tracing::info!(%district, level = info.level, mismatches = count, "check_codes");
+                } else {
+                    tracing::warn!(%district, level = info.level, mismatches = count, "check_codes");
+                }
+            });
+        });
+        Ok(())
+    }
+
+    /// Overlays every loaded region with the levels above and below it, see
+    /// `hierarchy` for the issues reported.
+    pub fn check_hierarchy(&self, tolerance: f64) -> Result<hierarchy::Report> {
+        let sw = Stopwatch::start_new();
+        defer! {
+            tracing::info!(elapsed = ?sw.elapsed(), "check_hierarchy");
+        }
+        if self.config.compact {
+            bail!("the hierarchy check needs the polygons, disable compact");
+        }
+
+        let hierarchies = self.hierarchies();
+        let district_par = self.district_par.read().map_err(|_| "failed to lock read")?;
+        let district_par_any
+            = self.district_par_any.read().map_err(|_| "failed to lock read")?;
+        let mut report = hierarchy::Report::default();
+        for district in self.config.districts.iter() {
+            let mut levels: BTreeMap<i32, Vec<&Polys>> = BTreeMap::new();
+            hierarchies
+                .iter()
+                .map(|polys| &**polys)
+                .chain(district_par.get(district).into_iter().flatten())
+                .chain(district_par_any.get(district).into_iter().flatten())
+                .filter(|polys| polys.1.first().is_some_and(|info| &*info.district == district))
+                .for_each(|polys| levels.entry(polys.1[0].level).or_default().push(polys));
+            let levels: Vec<(i32, Vec<&Polys>)> = levels.into_iter().collect();
+
+            for (i, (level, layers)) in levels.iter().enumerate() {
+                let regions: Vec<(&Polys, usize)> = layers
+                    .iter()
+                    .flat_map(|polys| (0..polys.1.len()).map(move |j| (*polys, j)))
+                    .collect();
+                let findings: Vec<hierarchy::Finding> = regions
+                    .par_iter()
+                    .flat_map_iter(|(polys, j)| {
+                        let mut findings = check_parents(polys, *j, &levels[..i], tolerance);
+                        findings.extend(check_gap(polys, *j, &levels[i + 1..], tolerance));
+                        findings
+                    })
+                    .collect();
+                tracing::info!(%district, level, regions = regions.len(),
+                    findings = findings.len(), "check_hierarchy");
+                report.add(*level, regions.len(), findings);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Districts to unload, least recently used first, until the `(district,
+/// bytes, last_used)` entries left fit in `budget` bytes. `keep` stays even
+/// when it alone is over the budget.
+pub fn eviction_victims(districts: &[(&str, usize, u64)], budget: usize, keep: &str)
+    -> Vec<String> {
+    let mut bytes: usize = districts.iter().map(|(_, bytes, _)| bytes).sum();
+    let mut candidates: Vec<&(&str, usize, u64)> = districts
+        .iter()
+        .filter(|(district, _, _)| *district != keep)
+        .collect();
+    candidates.sort_by_key(|(_, _, last_used)| *last_used);
+    candidates
+        .into_iter()
+        .take_while(|(_, size, _)| {
+            let over = bytes > budget;
+            bytes -= size;
+            over
+        })
+        .map(|(district, _, _)| district.to_string())
+        .collect()
+}
+
+fn finding(polys: &Polys, index: usize, issue: hierarchy::Issue, area: f64, fraction: f64,
+    parents: Vec<hierarchy::Parent>) -> hierarchy::Finding {
+    let info = &polys.1[index];
+    hierarchy::Finding {
+        issue,
+        district: info.district.to_string(),
+        level: info.level,
+        code: info.code.to_string(),
+        name: info.name.to_string(),
+        area_m2: area,
+        fraction,
+        parents,
+    }
+}
+
+// the region against the nearest level above it it overlaps, `above` is
+// ordered from the top level down
+fn check_parents(polys: &Polys, index: usize, above: &[(i32, Vec<&Polys>)], tolerance: f64)
+    -> Vec<hierarchy::Finding> {
+    let area = polys.0.area(c_int(index as i32));
+    if above.is_empty() || area <= 0.0 {
+        return vec![];
+    }
+    let parents = above
+        .iter()
+        .rev()
+        .map(|(_, layers)| layers
+            .iter()
+            .flat_map(|parent| polys.0
+                .intersecting(c_int(index as i32), &parent.0)
+                .iter()
+                .filter_map(|k| {
+                    let info = parent.1.get(*k as usize)?;
+                    let overlap = polys.0.intersection(c_int(index as i32), &parent.0, c_int(*k))
+                        .area();
+                    (overlap > 0.0).then(|| hierarchy::Parent {
+                        level: info.level,
+                        code: info.code.to_string(),
+                        name: info.name.to_string(),
+                        fraction: overlap / area,
+                    })
+                })
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>())
+        .find(|parents| !parents.is_empty())
+        .unwrap_or_default();
+    hierarchy::classify(&polys.1[index].code, &parents, tolerance)
+        .into_iter()
+        .map(|(issue, fraction)| finding(polys, index, issue, area, fraction, parents.clone()))
+        .collect()
+}
+
+// the region against the nearest level below it that covers part of it,
+// candidates of the covering only touching the region are not children
+fn check_gap(polys: &Polys, index: usize, below: &[(i32, Vec<&Polys>)], tolerance: f64)
+    -> Option<hierarchy::Finding> {
+    let area = polys.0.area(c_int(index as i32));
+    if area <= 0.0 {
+        return None;
+    }
+    below.iter().find_map(|(_, layers)| {
+        let covered: f64 = layers
+            .iter()
+            .filter(|child| !polys.0.intersecting(c_int(index as i32), &child.0).is_empty())
+            .map(|child| area - polys.0.uncovered(c_int(index as i32), &child.0).area())
+            .sum();
+        (covered > area * tolerance).then_some(covered)
+    })
+    .and_then(|covered| hierarchy::gap(area, covered, tolerance))
+    .map(|fraction| finding(polys, index, hierarchy::Issue::Gap, area, fraction, vec![]))
+}
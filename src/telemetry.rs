@@ -0,0 +1,96 @@
+//! Tracing subscriber and optional OpenTelemetry export.
+//!
+//! With `[telemetry]` configured, spans are exported over OTLP gRPC and the
+//! W3C `traceparent` of incoming REST headers and gRPC metadata becomes the
+//! parent of the request span.
+
+use crate::config::Telemetry;
+use opentelemetry::propagation::Extractor;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing_subscriber::registry::LookupSpan;
+
+/// OTLP export layer of the subscriber, must be called inside the tokio
+/// runtime. Also installs the W3C trace context propagator.
+pub fn layer<S>(config: Option<&Telemetry>) 
+    -> Option<tracing_opentelemetry::OpenTelemetryLayer<S, trace::Tracer>>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let config = config?;
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(&config.otlp_endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(trace::Sampler::ParentBased(Box::new(
+                    trace::Sampler::TraceIdRatioBased(config.sample_ratio))))
+                .with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", config.service_name.clone()),
+                ])),
+        )
+        .install_batch(runtime::Tokio);
+    match tracer {
+        Ok(tracer) => Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+        Err(e) => {
+            eprintln!("failed to install otlp exporter {}: {}", config.otlp_endpoint, e);
+            None
+        }
+    }
+}
+
+/// Flushes the spans not exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+struct HeaderExtractor<'a>(&'a axum::http::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+struct MetadataExtractor<'a>(&'a tonic::metadata::MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .map(|key| match key {
+                tonic::metadata::KeyRef::Ascii(key) => key.as_str(),
+                tonic::metadata::KeyRef::Binary(key) => key.as_str(),
+            })
+            .collect()
+    }
+}
+
+/// Trace context propagated in the `traceparent` header of a REST request.
+pub fn header_context(headers: &axum::http::HeaderMap) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    })
+}
+
+/// Trace context propagated in the `traceparent` metadata of a gRPC request.
+pub fn metadata_context(metadata: &tonic::metadata::MetadataMap) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&MetadataExtractor(metadata))
+    })
+}
@@ -5,6 +5,7 @@ mod errors {
 use errors::*;
 use error_chain::bail;
 use shapefile::dbase::FieldValue;
+use crate::config::Attribute;
 use crate::ffi;
 use autocxx::prelude::*;
 use std::pin::Pin;
@@ -13,6 +14,19 @@ pub fn load_shape<T>(file_path: &str, attributes: &Vec<String>)
     -> Result<(Vec<T>, Vec<Vec<String>>)>
 where
     T: From<Shape>,
+{
+    let (shapes, shape_attributes) = load_shape_records(file_path, attributes)?;
+    if shape_attributes.iter().any(|attributes| attributes.iter().all(|attr| attr.is_empty())) {
+        bail!("failed to get attributes");
+    }
+    Ok((shapes, shape_attributes))
+}
+
+// `load_shape` keeping the records with empty attributes
+pub fn load_shape_records<T>(file_path: &str, attributes: &[String]) 
+    -> Result<(Vec<T>, Vec<Vec<String>>)>
+where
+    T: From<Shape>,
 {
     let mut reader = 
         shapefile::Reader::from_path(file_path).chain_err(|| format!("{}", file_path))?;
@@ -46,10 +60,6 @@ where
             }
         }
 
-        if attributes.iter().all(|attr| attr.is_empty()) {
-            bail!("failed to get attributes");
-        }        
-
         shapes.push(T::from(shape));
         shape_attributes.push(attributes);
     } 
@@ -65,10 +75,73 @@ fn is_same_lnglat(lnglat1: (f64, f64), lnglat2: (f64, f64)) -> bool {
     false
 }
 
+// area weighted centroid of the largest outer ring, in lnglat degrees
+pub fn centroid(gp: &shapefile::record::Polygon) -> (f64, f64) {
+    let mut largest = 0f64;
+    let mut result = None;
+    gp.rings().iter().for_each(|ring| {
+        if let shapefile::PolygonRing::Inner(_) = ring {
+            return;
+        }
+
+        let (mut area, mut x, mut y) = (0f64, 0f64, 0f64);
+        ring.points().windows(2).for_each(|w| {
+            let cross = w[0].x() * w[1].y() - w[1].x() * w[0].y();
+            area += cross;
+            x += (w[0].x() + w[1].x()) * cross;
+            y += (w[0].y() + w[1].y()) * cross;
+        });
+
+        if area.abs() > largest {
+            largest = area.abs();
+            result = Some((x / (3f64 * area), y / (3f64 * area)));
+        }
+    });
+
+    result.unwrap_or_else(|| {
+        let points: Vec<_> = gp.rings().iter().flat_map(|ring| ring.points()).collect();
+        let n = points.len().max(1) as f64;
+        (
+            points.iter().map(|p| p.x()).sum::<f64>() / n,
+            points.iter().map(|p| p.y()).sum::<f64>() / n,
+        )
+    })
+}
+
 pub fn load_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str, debug: bool,
     debug_name: &str) 
     -> Result<Pin<Box<ffi::Polygon>>> {
+    load_polygon_codes(shapefile, gp, name, debug, debug_name).map(|(polygon, _)| polygon)
+}
+
+// also returns the error codes of the rings left out of the polygon
+pub fn load_polygon_codes(shapefile: &str, gp: &shapefile::record::Polygon, name: &str, 
+    debug: bool, debug_name: &str) 
+    -> Result<(Pin<Box<ffi::Polygon>>, Vec<i32>)> {
+    let (polygon, rings) = check_polygon(shapefile, gp, name, debug, debug_name)?;
+    let codes = rings.iter().map(|ring| ring.code).filter(|code| *code != 0).collect();
+    Ok((polygon, codes))
+}
+
+/// Outcome of a ring of `check_polygon`.
+#[derive(Debug, Clone, Default)]
+pub struct RingCheck {
+    pub index: usize,
+    pub outer: bool,
+    /// vertices passed to `Loop::init`
+    pub vertices: usize,
+    /// repeated, spike or closing vertices dropped before `Loop::init`
+    pub duplicates: usize,
+    /// `Loop::init` error code, 0 when the ring is in the polygon
+    pub code: i32,
+}
+
+// `load_polygon_codes` with the outcome of every ring
+pub fn check_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str, 
+    debug: bool, debug_name: &str) 
+    -> Result<(Pin<Box<ffi::Polygon>>, Vec<RingCheck>)> {
     let mut polygon = ffi::Polygon::new().within_box();
+    let mut checks = vec![];
     gp.rings().iter().enumerate().for_each(|(ring_index, ring)| {
         
         let mut first = (0f64, 0f64);
@@ -77,6 +150,7 @@ pub fn load_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str
         let mut lnglats = ffi::LngLats::new().within_box();
         let mut ok = false;
         let mut lnglats_filter = vec![];
+        let mut duplicates = 0;
         ring.points().iter().enumerate().for_each(|(point_idx, point)| {
             ok = false;
             let lnglat = (point.x(), point.y());
@@ -96,7 +170,11 @@ pub fn load_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str
                 }
             }
 
-            if ok && point_idx < ring.points().len() - 1 {
+            let last = point_idx == ring.points().len() - 1;
+            if !ok && !last {
+                duplicates += 1;
+            }
+            if ok && !last {
                 pprev = prev;
                 prev = (lnglat.0, lnglat.1);
                 if point_idx == 0 {
@@ -106,22 +184,19 @@ pub fn load_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str
                 let is_duplicate = lnglats_filter
                     .iter()
                     .any(|existing| is_same_lnglat(lnglat, *existing));
-                if !is_duplicate {
+                if is_duplicate {
+                    duplicates += 1;
+                } else {
                     lnglats.as_mut().add(lnglat.0, lnglat.1);
                     lnglats_filter.push(lnglat);
                     if name == debug_name {
-                        println!("{} {} {} {} {:.7},{:.7}", 
-                            shapefile, name, ring_index, lnglats.size(), lnglat.0, lnglat.1);
+                        tracing::debug!(file = shapefile, name, ring = ring_index, 
+                            point = lnglats.size(), lng = lnglat.0, lat = lnglat.1, "debug point");
                     }
                 }
             }
         });
 
-        const MIN_POINTS: usize = 3;
-        if lnglats.size() < MIN_POINTS {
-            return;
-        }
-
         let outer = match ring {
             shapefile::PolygonRing::Inner(_) => {
                 false
@@ -130,19 +205,93 @@ pub fn load_polygon(shapefile: &str, gp: &shapefile::record::Polygon, name: &str
                 true
             }            
         };
+        let mut check = RingCheck {
+            index: ring_index,
+            outer,
+            vertices: lnglats.size(),
+            duplicates,
+            code: 0,
+        };
+
+        const MIN_POINTS: usize = 3;
+        const TOO_FEW_VERTICES: i32 = 4;
+        if lnglats.size() < MIN_POINTS {
+            check.code = TOO_FEW_VERTICES;
+            checks.push(check);
+            return;
+        }
 
         let mut aloop = ffi::Loop::new().within_box();
         let error_code = aloop.as_mut().init(lnglats, outer, debug) as i32;
+        check.code = error_code;
+        checks.push(check);
         if error_code == 0 {
             polygon.as_mut().add(aloop);
-        } else if error_code != 4 {
+            return;
+        }
+        if error_code != TOO_FEW_VERTICES {
             if debug {
                 panic!("{} load_polygon {} error_code: {}", shapefile, name, error_code);
             } else {
-                println!("{} load_polygon {} error_code: {}", shapefile, name, error_code);
+                tracing::warn!(file = shapefile, name, ring = ring_index, error_code, "load_polygon");
             }
         }
     });
 
-    Ok(polygon)
+    Ok((polygon, checks))
+}
+
+/// A polygon feature of a shapefile, its code and name read the way the
+/// index reads them and its rings through `check_polygon`.
+#[derive(Debug, Clone)]
+pub struct Feature {
+    /// shape number in the file
+    pub index: usize,
+    pub code: String,
+    pub name: String,
+    pub shape: shapefile::record::Polygon,
+    pub rings: Vec<RingCheck>,
+}
+
+impl Feature {
+    /// Checks the rings of `shape`, the polygon of the accepted rings is
+    /// returned next to the feature.
+    pub fn check(path: &str, index: usize, shape: shapefile::record::Polygon, code: String,
+        name: String) -> Result<(Feature, Pin<Box<ffi::Polygon>>)> {
+        let (polygon, rings) = check_polygon(path, &shape, &name, false, "")
+            .chain_err(|| format!("{}: feature {}", path, index))?;
+        Ok((Feature { index, code, name, shape, rings }, polygon))
+    }
+
+    /// Whether an outer ring made it into the polygon, a feature without one
+    /// covers nothing.
+    pub fn valid(&self) -> bool {
+        self.rings.iter().any(|ring| ring.outer && ring.code == 0)
+    }
+}
+
+/// The polygon features of a shapefile of the layer of `attribute`.
+/// `polys` gets the polygon of every feature in order, invalid ones empty,
+/// so the features index its shapes.
+pub fn load_features(path: &str, attribute: &Attribute,
+    mut polys: Option<Pin<&mut ffi::Polygons>>) -> Result<Vec<Feature>> {
+    let mut columns = attribute.names.clone();
+    columns.extend(attribute.code.iter().cloned());
+    let (shapes, attributes) = load_shape_records::<Shape>(path, &columns)?;
+
+    let mut features = vec![];
+    for (index, (shape, values)) in shapes.into_iter().zip(attributes).enumerate() {
+        let Shape::Polygon(gp) = shape else {
+            continue;
+        };
+        // the code column is configured after the names, or is the first name
+        let code = values.get(attribute.names.len()).or(values.first()).cloned().unwrap_or_default();
+        let name = values.get(1).cloned().unwrap_or_default();
+        let (feature, polygon) = Feature::check(path, index, gp, code, name)?;
+        if let Some(polys) = polys.as_mut() {
+            polys.as_mut().add(polygon);
+        }
+        features.push(feature);
+    }
+    Ok(features)
 }
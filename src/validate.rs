@@ -0,0 +1,281 @@
+//! Geometry and attribute report of a dataset, for the `validate` command.
+//!
+//! Every shapefile of the configured layers goes through the loader pipeline,
+//! `load_features`, `check_polygon` and `Loop::init`, without building the
+//! index. Problems are counted per layer and the offending features can
+//! be written to GeoJSON with the problems in their properties.
+
+mod errors {
+    error_chain::error_chain! { }
+}
+use errors::*;
+use crate::config::{Attribute, Search as SearchConfig, Validate};
+use crate::discover::{layer_files, LayerFile};
+use crate::utils::shape::{self, load_features};
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, Value};
+use rayon::prelude::*;
+use serde::Serialize;
+use shapefile::record::traits::HasXY;
+use std::io::Write;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Counts {
+    pub features: usize,
+    pub duplicate_vertices: usize,
+    pub crossing_edges: usize,
+    pub curvature: usize,
+    pub empty_rings: usize,
+    pub empty_attributes: usize,
+    /// rings rejected by S2 for another reason
+    pub invalid_loops: usize,
+}
+
+impl Counts {
+    fn add(&mut self, other: &Counts) {
+        self.features += other.features;
+        self.duplicate_vertices += other.duplicate_vertices;
+        self.crossing_edges += other.crossing_edges;
+        self.curvature += other.curvature;
+        self.empty_rings += other.empty_rings;
+        self.empty_attributes += other.empty_attributes;
+        self.invalid_loops += other.invalid_loops;
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LayerReport {
+    pub layer: String,
+    pub level: i32,
+    pub files: usize,
+    pub failed_files: usize,
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub layers: Vec<LayerReport>,
+    pub total: Counts,
+    /// shapefiles that could not be read
+    pub errors: Vec<String>,
+    #[serde(skip)]
+    pub features: Vec<Feature>,
+}
+
+impl Report {
+    /// Totals above the thresholds, and the unreadable files.
+    pub fn violations(&self, thresholds: &Validate) -> Vec<String> {
+        let mut violations: Vec<String> = [
+            ("duplicate_vertices", self.total.duplicate_vertices, thresholds.duplicate_vertices),
+            ("crossing_edges", self.total.crossing_edges, thresholds.crossing_edges),
+            ("curvature", self.total.curvature, thresholds.curvature),
+            ("empty_rings", self.total.empty_rings, thresholds.empty_rings),
+            ("empty_attributes", self.total.empty_attributes, thresholds.empty_attributes),
+            ("invalid_loops", self.total.invalid_loops, thresholds.invalid_loops),
+        ]
+        .into_iter()
+        .filter_map(|(kind, count, threshold)| threshold
+            .filter(|threshold| count > *threshold)
+            .map(|threshold| format!("{} {} above {}", kind, count, threshold)))
+        .collect();
+        if !self.errors.is_empty() {
+            violations.push(format!("{} files failed to load", self.errors.len()));
+        }
+        violations
+    }
+
+    /// One row per layer and a total row, tab separated.
+    pub fn write_table(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "layer\tlevel\tfiles\tfailed\tfeatures\tduplicate_vertices\tcrossing_edges\t\
+            curvature\tempty_rings\tempty_attributes\tinvalid_loops")?;
+        let row = |out: &mut dyn Write, layer: &str, level: &str, files: usize, failed: usize,
+            counts: &Counts| {
+            writeln!(out, "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}", layer, level, files, failed,
+                counts.features, counts.duplicate_vertices, counts.crossing_edges,
+                counts.curvature, counts.empty_rings, counts.empty_attributes,
+                counts.invalid_loops)
+        };
+        for layer in self.layers.iter() {
+            row(out, &layer.layer, &layer.level.to_string(), layer.files, layer.failed_files,
+                &layer.counts)?;
+        }
+        let files = self.layers.iter().map(|layer| layer.files).sum();
+        row(out, "total", "-", files, self.errors.len(), &self.total)
+    }
+
+    pub fn write_geojson(&self, out: &mut impl Write) -> Result<()> {
+        let collection = FeatureCollection {
+            bbox: None,
+            features: self.features.clone(),
+            foreign_members: None,
+        };
+        serde_json::to_writer(out, &collection).chain_err(|| "write geojson")
+    }
+}
+
+struct FileReport {
+    layer: String,
+    counts: Counts,
+    error: Option<String>,
+    features: Vec<Feature>,
+}
+
+/// Runs every shapefile of `config` through the loader on the rayon pool,
+/// `features` keeps the offending features when `keep_features` is set.
+pub fn validate(config: &SearchConfig, keep_features: bool) -> Result<Report> {
+    let files = layer_files(config).chain_err(|| "failed to list the shapefiles")?;
+    let reports: Vec<FileReport> = files
+        .par_iter()
+        .map(|file| {
+            let attribute = config.shapefile.attributes.get(&file.layer);
+            match attribute {
+                Some(attribute) => validate_file(file, attribute, keep_features),
+                None => FileReport {
+                    layer: file.layer.clone(),
+                    counts: Counts::default(),
+                    error: Some(format!("{}: layer {} has no attributes entry",
+                        file.path, file.layer)),
+                    features: vec![],
+                },
+            }
+        })
+        .collect();
+
+    let mut report = Report::default();
+    for file_report in reports {
+        let index = match report.layers.iter().position(|layer| layer.layer == file_report.layer) {
+            Some(index) => index,
+            None => {
+                report.layers.push(LayerReport {
+                    layer: file_report.layer.clone(),
+                    level: config.shapefile.attributes
+                        .get(&file_report.layer)
+                        .map(|attribute| attribute.level)
+                        .unwrap_or_default(),
+                    files: 0,
+                    failed_files: 0,
+                    counts: Counts::default(),
+                });
+                report.layers.len() - 1
+            }
+        };
+        let layer = &mut report.layers[index];
+        layer.files += 1;
+        layer.counts.add(&file_report.counts);
+        report.total.add(&file_report.counts);
+        if let Some(error) = file_report.error {
+            layer.failed_files += 1;
+            report.errors.push(error);
+        }
+        report.features.extend(file_report.features);
+    }
+    report.layers.sort_by(|a, b| a.level.cmp(&b.level).then_with(|| a.layer.cmp(&b.layer)));
+    Ok(report)
+}
+
+fn validate_file(file: &LayerFile, attribute: &Attribute, keep_features: bool) -> FileReport {
+    let _span = tracing::info_span!("validate_file", path = %file.path).entered();
+    let mut report = FileReport {
+        layer: file.layer.clone(),
+        counts: Counts::default(),
+        error: None,
+        features: vec![],
+    };
+    let features = match load_features(&file.path, attribute, None) {
+        Ok(features) => features,
+        Err(e) => {
+            report.error = Some(format!("{}: {}", file.path, e));
+            return report;
+        }
+    };
+
+    features.into_iter().for_each(|feature| {
+        let (counts, problems) = check_feature(&feature);
+        report.counts.add(&counts);
+
+        if keep_features && !problems.is_empty() {
+            let mut properties = JsonObject::new();
+            properties.insert("file".to_string(), file.path.clone().into());
+            properties.insert("district".to_string(), file.district.clone().into());
+            properties.insert("layer".to_string(), file.layer.clone().into());
+            properties.insert("level".to_string(), attribute.level.into());
+            properties.insert("feature".to_string(), feature.index.into());
+            properties.insert("code".to_string(), feature.code.into());
+            properties.insert("name".to_string(), feature.name.into());
+            properties.insert("problems".to_string(), problems.into());
+            report.features.push(Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geometry(&feature.shape))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            });
+        }
+    });
+    report
+}
+
+/// Problems of a single feature, counted and described one per line.
+pub fn check_feature(feature: &shape::Feature) -> (Counts, Vec<String>) {
+    let mut counts = Counts { features: 1, ..Counts::default() };
+    let mut problems = vec![];
+    if feature.code.is_empty() || feature.name.is_empty() {
+        counts.empty_attributes += 1;
+        problems.push("empty attributes".to_string());
+    }
+    if feature.shape.rings().is_empty() {
+        counts.empty_rings += 1;
+        problems.push("no rings".to_string());
+    }
+    for ring in feature.rings.iter() {
+        if ring.duplicates > 0 {
+            counts.duplicate_vertices += ring.duplicates;
+            problems.push(format!("ring {}: {} duplicate vertices", ring.index, ring.duplicates));
+        }
+        let problem = match ring.code {
+            0 => continue,
+            2 | 3 => {
+                counts.curvature += 1;
+                "wrong curvature"
+            }
+            4 => {
+                counts.empty_rings += 1;
+                "fewer than 3 vertices"
+            }
+            5 => {
+                counts.crossing_edges += 1;
+                "crossing edges"
+            }
+            6 => {
+                counts.duplicate_vertices += 1;
+                "duplicate vertices"
+            }
+            _ => {
+                counts.invalid_loops += 1;
+                "invalid loop"
+            }
+        };
+        let kind = if ring.outer { "outer" } else { "inner" };
+        problems.push(format!("ring {}: {} {}", ring.index, kind, problem));
+    }
+    (counts, problems)
+}
+
+// rings as stored in the shapefile, each outer ring starts a polygon and
+// the inner rings after it are its holes
+pub(crate) fn geometry(gp: &shapefile::record::Polygon) -> Value {
+    let mut polygons: Vec<Vec<Vec<Vec<f64>>>> = vec![];
+    gp.rings().iter().for_each(|ring| {
+        let points: Vec<Vec<f64>> = ring.points().iter().map(|point| vec![point.x(), point.y()])
+            .collect();
+        match ring {
+            shapefile::PolygonRing::Inner(_) if !polygons.is_empty() => {
+                if let Some(polygon) = polygons.last_mut() {
+                    polygon.push(points);
+                }
+            }
+            _ => polygons.push(vec![points]),
+        }
+    });
+    Value::MultiPolygon(polygons)
+}
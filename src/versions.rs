@@ -0,0 +1,383 @@
+//! Named older datasets served next to the current one.
+//!
+//! Every snapshot of `[versions]` is a directory of converted shapefiles
+//! loaded into its own `Search`, at startup or on its first query when
+//! `lazy` is set. A query picks one by name with `version=`, or with
+//! `as_of=` the newest dataset released on or before the date, the current
+//! one included. `max_loaded` unloads the least recently queried snapshot
+//! and `max_age_days` drops the snapshots released long before the current
+//! dataset.
+
+mod errors {
+    error_chain::error_chain! {
+        errors {
+            NotFound(what: String) {
+                description("no such dataset version")
+                display("{}", what)
+            }
+            Loading(name: String) {
+                description("dataset version still loading")
+                display("version {} is still loading", name)
+            }
+            Failed(name: String) {
+                description("dataset version failed to load")
+                display("version {} failed to load", name)
+            }
+        }
+    }
+}
+pub use errors::{Error, ErrorKind};
+use errors::*;
+use error_chain::bail;
+use crate::config::{Search as SearchConfig, Snapshot, Versions as VersionsConfig};
+use crate::manifest::release_date;
+use crate::search::Search;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+pub static GLOBAL_VERSIONS: OnceCell<Versions> = OnceCell::new();
+
+/// Name of the dataset of `search.shapefile.path`.
+pub const CURRENT: &str = "current";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub as_of: Option<String>,
+    pub path: String,
+    pub current: bool,
+    pub loaded: bool,
+    /// manifest version once loaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+/// `YYYYMMDD` of a `YYYYMMDD` or `YYYY-MM-DD` date.
+pub fn parse_date(date: &str) -> Option<String> {
+    let digits: String = date.trim().chars().filter(|c| *c != '-').collect();
+    (digits.len() == 8 && days(&digits).is_some()).then_some(digits)
+}
+
+/// Days since 1970-01-01 of a `YYYYMMDD` date.
+pub fn days(date: &str) -> Option<i64> {
+    if date.len() != 8 || !date.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = date[..4].parse().ok()?;
+    let month: i64 = date[4..6].parse().ok()?;
+    let day: i64 = date[6..].parse().ok()?;
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let month_days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    if !(1..=12).contains(&month) || !(1..=month_days[month as usize - 1]).contains(&day) {
+        return None;
+    }
+    // civil calendar to days, march based years
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+/// Name of the newest of `datasets` released on or before `as_of`, both
+/// `YYYYMMDD`. Datasets without a release date are never picked.
+pub fn select<'a>(datasets: &'a [(String, Option<String>)], as_of: &str) -> Option<&'a str> {
+    datasets
+        .iter()
+        .filter_map(|(name, date)| date.as_deref().map(|date| (date, name)))
+        .filter(|(date, _)| *date <= as_of)
+        .max_by(|a, b| a.0.cmp(b.0).then_with(|| (a.1 == CURRENT).cmp(&(b.1 == CURRENT))))
+        .map(|(_, name)| name.as_str())
+}
+
+/// The snapshots released at most `max_age_days` before `current`, all of
+/// them when 0 or a date is unknown.
+pub fn retained(snapshots: Vec<Snapshot>, current: Option<&str>, max_age_days: u32)
+    -> Vec<Snapshot> {
+    let Some(current) = current.and_then(days).filter(|_| max_age_days > 0) else {
+        return snapshots;
+    };
+    snapshots
+        .into_iter()
+        .filter(|snapshot| {
+            let age = snapshot.as_of.as_deref().and_then(days).map(|date| current - date);
+            let keep = !matches!(age, Some(age) if age > max_age_days as i64);
+            if !keep {
+                tracing::info!(name = %snapshot.name, ?age, max_age_days, "version retired");
+            }
+            keep
+        })
+        .collect()
+}
+
+// newest release date in the file names of a dataset
+fn dataset_date(config: &SearchConfig) -> Option<String> {
+    crate::discover::layer_files(config)
+        .ok()?
+        .iter()
+        .filter_map(|file| release_date(&file.path))
+        .max()
+}
+
+struct Entry {
+    snapshot: Snapshot,
+    // held while loading, concurrent first queries wait for a single load
+    load: Mutex<()>,
+    search: RwLock<Option<Arc<Search>>>,
+    last_used: AtomicU64,
+}
+
+pub struct Versions {
+    config: VersionsConfig,
+    base: SearchConfig,
+    current_as_of: Option<String>,
+    entries: Vec<Entry>,
+    clock: AtomicU64,
+}
+
+impl Versions {
+    /// Resolves the release dates of the current dataset and the snapshots,
+    /// and applies `max_age_days`.
+    pub fn new(config: VersionsConfig, base: SearchConfig) -> Self {
+        let current_as_of = dataset_date(&base);
+        let snapshots: Vec<Snapshot> = config.snapshots
+            .iter()
+            .filter(|snapshot| {
+                let reserved = snapshot.name == CURRENT;
+                if reserved {
+                    tracing::warn!(path = %snapshot.path, "version name {} is reserved", CURRENT);
+                }
+                !reserved
+            })
+            .map(|snapshot| {
+                let mut snapshot = snapshot.clone();
+                snapshot.as_of = match &snapshot.as_of {
+                    Some(date) => {
+                        let parsed = parse_date(date);
+                        if parsed.is_none() {
+                            tracing::warn!(name = %snapshot.name, as_of = %date, "invalid as_of");
+                        }
+                        parsed
+                    }
+                    None => dataset_date(&snapshot_config(&base, &snapshot)),
+                };
+                snapshot
+            })
+            .collect();
+        let entries = retained(snapshots, current_as_of.as_deref(), config.max_age_days)
+            .into_iter()
+            .map(|snapshot| Entry {
+                snapshot,
+                load: Mutex::new(()),
+                search: RwLock::new(None),
+                last_used: AtomicU64::new(0),
+            })
+            .collect();
+        Versions { config, base, current_as_of, entries, clock: AtomicU64::new(1) }
+    }
+
+    /// Loads the snapshots up front unless `lazy`, newest first and at most
+    /// `max_loaded` of them.
+    pub fn preload(&self) {
+        if self.config.lazy {
+            return;
+        }
+        let mut entries: Vec<&Entry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| b.snapshot.as_of.cmp(&a.snapshot.as_of));
+        if self.config.max_loaded > 0 {
+            entries.truncate(self.config.max_loaded);
+        }
+        for entry in entries {
+            if let Err(e) = self.load(entry) {
+                tracing::error!(name = %entry.snapshot.name, error = %e, "failed to load version");
+            }
+        }
+    }
+
+    /// The snapshot a query asked for, `None` for the current dataset.
+    pub fn resolve(&self, version: Option<&str>, as_of: Option<&str>) -> Result<Option<String>> {
+        let version = version.filter(|version| !version.is_empty());
+        let as_of = as_of.filter(|as_of| !as_of.is_empty());
+        match (version, as_of) {
+            (Some(_), Some(_)) => bail!("version and as_of are exclusive"),
+            (Some(CURRENT), None) | (None, None) => Ok(None),
+            (Some(version), None) => self.entry(version).map(|entry| Some(entry.snapshot.name.clone())),
+            (None, Some(as_of)) => {
+                let date = parse_date(as_of)
+                    .ok_or_else(|| format!("invalid as_of {}, expected YYYY-MM-DD", as_of))?;
+                let mut datasets = vec![(CURRENT.to_string(), self.current_as_of.clone())];
+                datasets.extend(self.entries
+                    .iter()
+                    .map(|entry| (entry.snapshot.name.clone(), entry.snapshot.as_of.clone())));
+                match select(&datasets, &date) {
+                    Some(CURRENT) => Ok(None),
+                    Some(name) => Ok(Some(name.to_string())),
+                    None => Err(ErrorKind::NotFound(
+                        format!("no dataset released on or before {}", date)).into()),
+                }
+            }
+        }
+    }
+
+    /// Search of the snapshot `name`, loaded on first use.
+    pub fn search(&self, name: &str) -> Result<Arc<Search>> {
+        let entry = self.entry(name)?;
+        entry.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        if let Some(search) = loaded(entry) {
+            return Ok(search);
+        }
+        // answered once the preload gets there, snapshots it skipped for
+        // `max_loaded` load on demand
+        if !self.config.lazy && entry.load.try_lock().is_err() {
+            bail!(ErrorKind::Loading(name.to_string()));
+        }
+        self.load(entry)
+    }
+
+    /// The current dataset and the snapshots, newest first.
+    pub fn list(&self, current: Option<&Search>) -> Vec<VersionInfo> {
+        let mut versions = vec![VersionInfo {
+            name: CURRENT.to_string(),
+            as_of: self.current_as_of.clone(),
+            path: self.base.shapefile.path.clone(),
+            current: true,
+            loaded: current.is_some_and(|search| search.manifest().is_some()),
+            version: current.map(|search| search.dataset_version()),
+        }];
+        let mut snapshots: Vec<VersionInfo> = self.entries
+            .iter()
+            .map(|entry| {
+                let search = loaded(entry);
+                VersionInfo {
+                    name: entry.snapshot.name.clone(),
+                    as_of: entry.snapshot.as_of.clone(),
+                    path: entry.snapshot.path.clone(),
+                    current: false,
+                    loaded: search.is_some(),
+                    version: search.map(|search| search.dataset_version()),
+                }
+            })
+            .collect();
+        snapshots.sort_by(|a, b| b.as_of.cmp(&a.as_of).then_with(|| a.name.cmp(&b.name)));
+        versions.extend(snapshots);
+        versions
+    }
+
+    fn entry(&self, name: &str) -> Result<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.snapshot.name == name)
+            .ok_or_else(|| ErrorKind::NotFound(format!("unknown version {}", name)).into())
+    }
+
+    fn load(&self, entry: &Entry) -> Result<Arc<Search>> {
+        let _guard = entry.load.lock().map_err(|_| "failed to lock load")?;
+        if let Some(search) = loaded(entry) {
+            return Ok(search);
+        }
+        let name = &entry.snapshot.name;
+        let _span = tracing::info_span!("load_version", %name, path = %entry.snapshot.path)
+            .entered();
+        let mut search = Search::new(snapshot_config(&self.base, &entry.snapshot))
+            .chain_err(|| ErrorKind::Failed(name.to_string()))?
+            .without_metrics();
+        search.load().chain_err(|| ErrorKind::Failed(name.to_string()))?;
+        let search = Arc::new(search);
+        tracing::info!(%name, version = %search.dataset_version(), "version loaded");
+
+        *entry.search.write().map_err(|_| "failed to lock write")? = Some(search.clone());
+        entry.last_used.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+        self.evict(name);
+        Ok(search)
+    }
+
+    // unloads the least recently queried snapshots above `max_loaded`, in
+    // flight queries keep theirs until they finish
+    fn evict(&self, keep: &str) {
+        if self.config.max_loaded == 0 {
+            return;
+        }
+        loop {
+            let loaded_entries: Vec<&Entry> = self.entries
+                .iter()
+                .filter(|entry| loaded(entry).is_some())
+                .collect();
+            if loaded_entries.len() <= self.config.max_loaded {
+                return;
+            }
+            let Some(victim) = loaded_entries
+                .into_iter()
+                .filter(|entry| entry.snapshot.name != keep)
+                .min_by_key(|entry| entry.last_used.load(Ordering::Relaxed)) else {
+                return;
+            };
+            tracing::info!(name = %victim.snapshot.name, "version unloaded");
+            if let Ok(mut search) = victim.search.write() {
+                *search = None;
+            }
+        }
+    }
+}
+
+fn loaded(entry: &Entry) -> Option<Arc<Search>> {
+    entry.search.read().ok().and_then(|search| search.clone())
+}
+
+// the current config pointed at the snapshot, every level loaded up front
+fn snapshot_config(base: &SearchConfig, snapshot: &Snapshot) -> SearchConfig {
+    let mut config = base.clone();
+    config.shapefile.path = snapshot.path.clone();
+    config.lazy = None;
+    config
+}
+
+/// Search of the dataset picked by `version` or `as_of`, `None` for the
+/// current one. Blocks while a lazy snapshot loads.
+pub fn pick(version: Option<&str>, as_of: Option<&str>) -> Result<Option<Arc<Search>>> {
+    let Some(versions) = GLOBAL_VERSIONS.get() else {
+        return match (version.filter(|v| !v.is_empty()), as_of.filter(|a| !a.is_empty())) {
+            (None, None) | (Some(CURRENT), None) => Ok(None),
+            _ => Err(ErrorKind::NotFound("no dataset versions configured".to_string()).into()),
+        };
+    };
+    match versions.resolve(version, as_of)? {
+        Some(name) => versions.search(&name).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The current dataset and the configured snapshots, newest first.
+pub fn list(current: Option<&Search>) -> Vec<VersionInfo> {
+    match GLOBAL_VERSIONS.get() {
+        Some(versions) => versions.list(current),
+        None => vec![VersionInfo {
+            name: CURRENT.to_string(),
+            as_of: current
+                .and_then(|search| search.manifest())
+                .and_then(|manifest| manifest.releases.last().cloned()),
+            path: crate::GLOBAL_CONFIG.search.shapefile.path.clone(),
+            current: true,
+            loaded: current.is_some_and(|search| search.manifest().is_some()),
+            version: current.map(|search| search.dataset_version()),
+        }],
+    }
+}
+
+/// Sets up the `[versions]` of `config` and loads them in the background
+/// unless `lazy`.
+pub fn initialize(config: VersionsConfig, base: SearchConfig) {
+    if GLOBAL_VERSIONS.set(Versions::new(config, base)).is_err() {
+        tracing::warn!("versions already initialized");
+        return;
+    }
+    std::thread::spawn(|| {
+        if let Some(versions) = GLOBAL_VERSIONS.get() {
+            versions.preload();
+        }
+    });
+}
@@ -0,0 +1,152 @@
+use std::path::{Path, PathBuf};
+use tarantula_s2::annotate::{annotate_with, open_reader, open_writer, FileFormat};
+use tarantula_s2::Info;
+
+fn info(level: i32, code: &str, name: &str) -> Info {
+    Info {
+        district: "36000".to_string(),
+        level,
+        name: name.to_string(),
+        code: code.to_string(),
+        eng_name: String::new(),
+        lnglats: vec![],
+        fallback: false,
+    }
+}
+
+// sejong inside lon 127..128, nothing elsewhere
+fn lookup(lon: f64, lat: f64) -> Result<Vec<Info>, String> {
+    if !(127.0..128.0).contains(&lon) || !(36.0..37.0).contains(&lat) {
+        return Ok(vec![]);
+    }
+    Ok(vec![info(1, "36", "세종특별자치시"), info(3, "36110250", "조치원읍")])
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tarantula_annotate_{}_{}", std::process::id(), name))
+}
+
+fn run(input: &Path, input_format: FileFormat, output: &Path, output_format: FileFormat,
+    batch_size: usize) -> tarantula_s2::annotate::Summary {
+    let mut reader = open_reader(input, input_format, batch_size).unwrap();
+    let writer = open_writer(output, output_format).unwrap();
+    annotate_with(reader.as_mut(), writer, ("lon", "lat"), batch_size, lookup).unwrap()
+}
+
+#[test]
+fn test_csv_to_csv() {
+    let (input, output) = (temp_path("in.csv"), temp_path("out.csv"));
+    std::fs::write(&input, "id,lon,lat\n1,127.28,36.50\n2,126.9,37.5\n3,abc,36.5\n").unwrap();
+
+    // a batch smaller than the file
+    let summary = run(&input, FileFormat::Csv, &output, FileFormat::Csv, 2);
+    assert_eq!((summary.rows, summary.annotated, summary.not_found, summary.invalid), (3, 1, 1, 1));
+
+    let out = std::fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "id,lon,lat,sido_code,sido_name,sigungu_code,sigungu_name,\
+        emd_code,emd_name,li_code,li_name,parcel_code,parcel_name");
+    assert_eq!(lines[1], "1,127.28,36.50,36,세종특별자치시,,,36110250,조치원읍,,,,");
+    assert_eq!(lines[2], "2,126.9,37.5,,,,,,,,,,");
+    assert_eq!(lines.len(), 4);
+}
+
+#[test]
+fn test_ndjson_to_parquet_and_back() {
+    let (input, parquet, output) = 
+        (temp_path("in.ndjson"), temp_path("out.parquet"), temp_path("out.ndjson"));
+    std::fs::write(&input, concat!(
+        r#"{"id": 1, "lon": 127.28, "lat": 36.5}"#, "\n",
+        "\n",
+        r#"{"id": 2, "lon": "127.5", "lat": "36.6", "note": "string coordinates"}"#, "\n",
+    )).unwrap();
+
+    let summary = run(&input, FileFormat::Ndjson, &parquet, FileFormat::Parquet, 10);
+    assert_eq!((summary.rows, summary.annotated), (2, 2));
+
+    // annotation columns of the input are overwritten, not duplicated
+    let summary = run(&parquet, FileFormat::Parquet, &output, FileFormat::Ndjson, 1);
+    assert_eq!((summary.rows, summary.annotated), (2, 2));
+
+    let out = std::fs::read_to_string(&output).unwrap();
+    let rows: Vec<serde_json::Value> = out.lines().map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["id"], 1);
+    // mixed number and string coordinates are stored as text
+    assert_eq!(rows[0]["lon"], "127.28");
+    assert_eq!(rows[0]["emd_code"], "36110250");
+    assert_eq!(rows[0]["li_code"], serde_json::Value::Null);
+    assert_eq!(rows[1]["note"], "string coordinates");
+    assert_eq!(rows[0].as_object().unwrap().len(), 14);
+}
+
+#[test]
+fn test_missing_column() {
+    let (input, output) = (temp_path("missing.csv"), temp_path("missing_out.csv"));
+    std::fs::write(&input, "id,x,y\n1,127.28,36.50\n").unwrap();
+    let mut reader = open_reader(&input, FileFormat::Csv, 10).unwrap();
+    let writer = open_writer(&output, FileFormat::Csv).unwrap();
+    assert!(annotate_with(reader.as_mut(), writer, ("lon", "lat"), 10, lookup).is_err());
+}
+
+#[test]
+fn test_parquet_type_from_first_batch() {
+    let (input, output) = (temp_path("types.ndjson"), temp_path("types.parquet"));
+    std::fs::write(&input, concat!(
+        r#"{"id": 1, "lon": 127.28, "lat": 36.5}"#, "\n",
+        r#"{"id": 2.5, "lon": 127.28, "lat": 36.5}"#, "\n",
+    )).unwrap();
+    let open = |batch_size| {
+        let reader = open_reader(&input, FileFormat::Ndjson, batch_size).unwrap();
+        (reader, open_writer(&output, FileFormat::Parquet).unwrap())
+    };
+
+    // id is typed integer by the first row alone
+    let (mut reader, writer) = open(1);
+    let error = annotate_with(reader.as_mut(), writer, ("lon", "lat"), 1, lookup).unwrap_err();
+    assert!(error.to_string().contains("column id"), "{}", error);
+
+    // both rows in one batch widen it to float
+    let (mut reader, writer) = open(2);
+    annotate_with(reader.as_mut(), writer, ("lon", "lat"), 2, lookup).unwrap();
+    let back = temp_path("types_back.ndjson");
+    run(&output, FileFormat::Parquet, &back, FileFormat::Ndjson, 2);
+    let out = std::fs::read_to_string(&back).unwrap();
+    let ids: Vec<serde_json::Value> = out.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].clone())
+        .collect();
+    assert_eq!(ids, vec![serde_json::json!(1.0), serde_json::json!(2.5)]);
+}
+
+#[test]
+fn test_ndjson_key_after_first_batch() {
+    let input = temp_path("keys.ndjson");
+    std::fs::write(&input, concat!(
+        r#"{"id": 1, "lon": 127.28, "lat": 36.5}"#, "\n",
+        r#"{"id": 2, "lon": 127.28, "lat": 36.5, "note": "late"}"#, "\n",
+    )).unwrap();
+
+    for (output, format) in [(temp_path("keys.csv"), FileFormat::Csv),
+        (temp_path("keys.parquet"), FileFormat::Parquet)] {
+        let mut reader = open_reader(&input, FileFormat::Ndjson, 1).unwrap();
+        let writer = open_writer(&output, format).unwrap();
+        let error = annotate_with(reader.as_mut(), writer, ("lon", "lat"), 1, lookup).unwrap_err();
+        assert!(error.to_string().contains("column note"), "{:?} {}", format, error);
+    }
+
+    // ndjson output keeps every key of every row
+    let output = temp_path("keys_out.ndjson");
+    let summary = run(&input, FileFormat::Ndjson, &output, FileFormat::Ndjson, 1);
+    assert_eq!(summary.rows, 2);
+    let out = std::fs::read_to_string(&output).unwrap();
+    assert!(out.lines().nth(1).unwrap().contains(r#""note":"late""#));
+}
+
+#[test]
+fn test_format_from_path() {
+    assert_eq!(FileFormat::from_path("a/points.CSV".as_ref()), Some(FileFormat::Csv));
+    assert_eq!(FileFormat::from_path("points.jsonl".as_ref()), Some(FileFormat::Ndjson));
+    assert_eq!(FileFormat::from_path("points.parquet".as_ref()), Some(FileFormat::Parquet));
+    assert_eq!(FileFormat::from_path("points".as_ref()), None);
+}
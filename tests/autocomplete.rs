@@ -0,0 +1,33 @@
+mod common;
+
+use common::region;
+use std::collections::HashMap;
+use tarantula_s2::autocomplete::{to_choseong, to_jamo, Autocomplete};
+use tarantula_s2::lookup::Layer;
+
+#[test]
+fn test_autocomplete() {
+    assert_eq!(to_choseong("조치원읍"), "ㅈㅊㅇㅇ");
+    assert_eq!(to_jamo("원"), "ㅇㅜㅓㄴ");
+
+    let regions = vec![
+        region("36110250", 3, "조치원읍", "", 0),
+        region("36110310", 3, "장군면", "", 1),
+        region("36110330", 3, "전의면", "", 2),
+    ];
+    let parcels = HashMap::from([((Layer::Hierarchy(0), 1), 10), ((Layer::Hierarchy(0), 2), 20)]);
+    let autocomplete = Autocomplete::new(&regions, &parcels);
+
+    let r = autocomplete.suggest("ㅈㅊㅇ", 10);
+    assert_eq!(r[0].name, "조치원읍");
+
+    let r = autocomplete.suggest("조ㅊ", 10);
+    assert_eq!(r[0].name, "조치원읍");
+
+    let r = autocomplete.suggest("ㅈ", 10);
+    assert_eq!(r.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), 
+        vec!["전의면", "장군면", "조치원읍"]);
+
+    let r = autocomplete.suggest("조치웡", 10);
+    assert_eq!(r[0].name, "조치원읍");
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use common::Dataset;
+use tarantula_s2::cache::Cache;
+use tarantula_s2::config::Cache as CacheConfig;
+use tarantula_s2::Search;
+
+#[test]
+fn test_hits_and_misses() {
+    let cache: Cache<&str> = Cache::new(100, 4);
+    assert_eq!(cache.get(1), None);
+    cache.put(1, "a", cache.generation());
+    assert_eq!(cache.get(1), Some("a"));
+    cache.skip();
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses, stats.uncacheable, stats.entries), (1, 1, 1, 1));
+}
+
+#[test]
+fn test_eviction() {
+    let cache: Cache<u64> = Cache::new(2, 1);
+    cache.put(1, 10, cache.generation());
+    cache.put(2, 20, cache.generation());
+    // 1 is now the most recently used
+    assert_eq!(cache.get(1), Some(10));
+    cache.put(3, 30, cache.generation());
+
+    assert_eq!(cache.get(2), None);
+    assert_eq!(cache.get(1), Some(10));
+    assert_eq!(cache.get(3), Some(30));
+    assert_eq!(cache.stats().entries, 2);
+}
+
+#[test]
+fn test_stale_generation() {
+    let cache: Cache<u64> = Cache::new(10, 2);
+    cache.put(1, 10, cache.generation());
+    // a search started before the reload finishes after it
+    let generation = cache.generation();
+    cache.clear();
+    cache.put(2, 20, generation);
+    assert_eq!(cache.get(1), None);
+    assert_eq!(cache.get(2), None);
+
+    cache.put(2, 20, cache.generation());
+    assert_eq!(cache.get(2), Some(20));
+}
+
+#[test]
+fn test_exact_cells_only() {
+    let dataset = Dataset::sejong("cache");
+    let mut config = dataset.config(&["36000"]);
+    config.cache = Some(CacheConfig { level: 20, capacity: 1000, shards: 4 });
+    let mut search = Search::new(config).unwrap();
+    search.load().unwrap();
+    let stats = || search.cache_stats().unwrap();
+
+    // inside a parcel, every layer resolves the whole cell
+    let before = stats();
+    let first = search.search(127.015, 36.515).unwrap();
+    assert_eq!(stats().entries, before.entries + 1);
+    let second = search.search(127.015, 36.515).unwrap();
+    assert_eq!(stats().hits, before.hits + 1);
+    let codes = |infos: &[tarantula_s2::Info]| -> Vec<String> {
+        infos.iter().map(|info| info.code.clone()).collect()
+    };
+    assert_eq!(codes(&first), codes(&second));
+    assert_eq!(codes(&first).last().map(|code| code.as_str()), Some("3611025021100010000"));
+
+    // on the boundary between the two emd the cell is mixed
+    let before = stats();
+    search.search(127.05 + 1e-7, 36.55).unwrap();
+    assert_eq!(stats().uncacheable, before.uncacheable + 1);
+    assert_eq!(stats().entries, before.entries);
+}
@@ -0,0 +1,134 @@
+#![allow(dead_code)]
+
+use shapefile::dbase::{FieldName, FieldValue, Record, TableWriterBuilder};
+use shapefile::{Point, Polygon, PolygonRing};
+use std::path::PathBuf;
+use tarantula_s2::config::Search as SearchConfig;
+use tarantula_s2::lookup::{Layer, Region};
+
+/// A region of district 36000 in the first hierarchy layer.
+pub fn region(code: &str, level: i32, name: &str, eng_name: &str, index: usize) -> Region {
+    Region {
+        code: code.to_string(),
+        level,
+        name: name.to_string(),
+        eng_name: eng_name.to_string(),
+        district: "36000".to_string(),
+        centroid: (127.0, 36.5),
+        layer: Layer::Hierarchy(0),
+        index,
+    }
+}
+
+/// Counterclockwise corners of a square with its south west corner at `(x, y)`.
+pub fn square(x: f64, y: f64, size: f64) -> Vec<(f64, f64)> {
+    vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+}
+
+/// A polygon of a single outer ring, closed and oriented by `shapefile`.
+pub fn polygon(ring: &[(f64, f64)]) -> Polygon {
+    Polygon::new(PolygonRing::Outer(ring.iter().map(|(x, y)| Point::new(*x, *y)).collect()))
+}
+
+/// Columns of the layers of the default config, code first and name second
+/// like the loader expects.
+pub fn columns(stem: &str) -> &'static [&'static str] {
+    match stem {
+        "TL_SCCO_CTPRVN" => &["CTPRVN_CD", "CTP_KOR_NM", "CTP_ENG_NM"],
+        "TL_SCCO_SIG" => &["SIG_CD", "SIG_KOR_NM", "SIG_ENG_NM"],
+        "TL_SCCO_EMD" => &["EMD_CD", "EMD_KOR_NM", "EMD_ENG_NM"],
+        "TL_SCCO_LI" => &["LI_CD", "LI_KOR_NM", "LI_ENG_NM"],
+        _ => &["A1", "A3", "A4", "A5"],
+    }
+}
+
+/// Shapefiles under a temporary directory, laid out like `shapefile.path`,
+/// removed on drop.
+pub struct Dataset {
+    pub root: PathBuf,
+}
+
+impl Dataset {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir()
+            .join(format!("tarantula_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        Dataset { root }
+    }
+
+    /// District 36000 split into two emd with a parcel in each, without li.
+    pub fn sejong(name: &str) -> Self {
+        let dataset = Dataset::new(name);
+        dataset.write("36000", "TL_SCCO_CTPRVN", &[
+            (polygon(&square(127.0, 36.5, 0.1)), &["36", "Sejong", "Sejong-si"]),
+        ]);
+        dataset.write("36000", "TL_SCCO_SIG", &[
+            (polygon(&square(127.0, 36.5, 0.1)), &["36110", "Sejong", "Sejong-si"]),
+        ]);
+        dataset.write("36000", "TL_SCCO_EMD", &[
+            (polygon(&[(127.0, 36.5), (127.05, 36.5), (127.05, 36.6), (127.0, 36.6)]),
+                &["36110250", "Jochiwon", "Jochiwon-eup"]),
+            (polygon(&[(127.05, 36.5), (127.1, 36.5), (127.1, 36.6), (127.05, 36.6)]),
+                &["36110310", "Janggun", "Janggun-myeon"]),
+        ]);
+        dataset.write("36000", "AL_D002_36_20250504", &[
+            (polygon(&square(127.01, 36.51, 0.01)), &["3611025021100010000", "", "1-1", ""]),
+            (polygon(&square(127.06, 36.51, 0.01)), &["3611031021100020000", "", "2-1", ""]),
+        ]);
+        dataset
+    }
+
+    pub fn path(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    /// Writes `district/stem.shp` with the `columns(stem)` values of every feature.
+    pub fn write(&self, district: &str, stem: &str, features: &[(Polygon, &[&str])]) {
+        let dir = self.root.join(district);
+        std::fs::create_dir_all(&dir).unwrap();
+        let columns = columns(stem);
+        let table = columns.iter().fold(TableWriterBuilder::new(), |table, column| {
+            table.add_character_field(FieldName::try_from(*column).unwrap(), 40)
+        });
+        let mut writer = shapefile::Writer::from_path(dir.join(format!("{}.shp", stem)), table)
+            .unwrap();
+        for (polygon, values) in features {
+            let mut record = Record::default();
+            columns.iter().zip(values.iter()).for_each(|(column, value)| {
+                record.insert(column.to_string(), FieldValue::Character(Some(value.to_string())));
+            });
+            writer.write_shape_and_record(polygon, &record).unwrap();
+        }
+    }
+
+    /// The layers of `Config.toml` over this dataset, eager and without extras.
+    pub fn config(&self, districts: &[&str]) -> SearchConfig {
+        let districts: Vec<String> = districts.iter().map(|d| format!("{:?}", d)).collect();
+        toml::from_str(&format!(r#"
+            districts = [{}]
+            hierarchies = ["TL_SCCO_CTPRVN"]
+            district_par = ["TL_SCCO_SIG", "TL_SCCO_EMD", "TL_SCCO_LI"]
+            district_par_any = ["AL_D002_"]
+            debug = false
+            debug_name = ""
+
+            [shapefile]
+            path = {:?}
+
+            [shapefile.attributes]
+            "TL_SCCO_CTPRVN" = {{ level = 1, names = ["CTPRVN_CD", "CTP_KOR_NM", "CTP_ENG_NM"] }}
+            "TL_SCCO_SIG" = {{ level = 2, names = ["SIG_CD", "SIG_KOR_NM", "SIG_ENG_NM"] }}
+            "TL_SCCO_EMD" = {{ level = 3, names = ["EMD_CD", "EMD_KOR_NM", "EMD_ENG_NM"] }}
+            "TL_SCCO_LI" = {{ level = 4, names = ["LI_CD", "LI_KOR_NM", "LI_ENG_NM"] }}
+            "AL_D002_" = {{ level = 5, names = ["A3", "A4", "A5"], code = "A1" }}
+            "#, districts.join(", "), self.path()))
+            .unwrap()
+    }
+}
+
+impl Drop for Dataset {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
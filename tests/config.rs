@@ -0,0 +1,77 @@
+use tarantula_s2::config::{apply_override, env_overrides};
+use tarantula_s2::Config;
+
+fn config_toml() -> String {
+    std::fs::read_to_string("Config.toml").expect("Config.toml")
+}
+
+#[test]
+fn test_layered_overrides() {
+    let env = vec![
+        ("TARANTULA_REST__PORT".to_string(), "9090".to_string()),
+        ("TARANTULA_GRPC__HOST".to_string(), "0.0.0.0".to_string()),
+        ("PATH".to_string(), "/usr/bin".to_string()),
+    ];
+    let sets = vec![
+        "search.debug=true".to_string(),
+        "rest.port = 7070".to_string(),
+        "shutdown_timeout_secs=5".to_string(),
+    ];
+    let config = Config::layered(&config_toml(), env, &sets).unwrap();
+
+    // --set wins over the environment
+    assert_eq!(config.rest.port, 7070);
+    // not a toml value, taken as a string
+    assert_eq!(config.grpc.host, "0.0.0.0");
+    assert!(config.search.debug);
+    assert_eq!(config.shutdown_timeout_secs, 5);
+}
+
+#[test]
+fn test_string_overrides_that_look_typed() {
+    let env = vec![("TARANTULA_REST__ADMIN_TOKEN".to_string(), "123456".to_string())];
+    let sets = vec![
+        "search.debug_name=true".to_string(),
+        "search.shapefile.path=2025".to_string(),
+        "rest.port=7070".to_string(),
+    ];
+    let config = Config::layered(&config_toml(), env, &sets).unwrap();
+
+    // missing from the file, kept as given once the guess fails
+    assert_eq!(config.rest.admin_token.as_deref(), Some("123456"));
+    // strings in the file stay strings
+    assert_eq!(config.search.debug_name, "true");
+    assert_eq!(config.search.shapefile.path, "2025");
+    assert_eq!(config.rest.port, 7070);
+}
+
+#[test]
+fn test_invalid_overrides() {
+    let toml = config_toml();
+    assert!(Config::layered(&toml, vec![], &["rest.port".to_string()]).is_err());
+    assert!(Config::layered(&toml, vec![], &["rest.port=http".to_string()]).is_err());
+    assert!(Config::layered(&toml, vec![], &["rest.port.number=1".to_string()]).is_err());
+}
+
+#[test]
+fn test_env_overrides() {
+    let env = vec![
+        ("TARANTULA_SEARCH__LAZY__PARTIAL".to_string(), "true".to_string()),
+        ("TARANTULA_SHUTDOWN_TIMEOUT_SECS".to_string(), "10".to_string()),
+        ("HOME".to_string(), "/root".to_string()),
+    ];
+    assert_eq!(env_overrides(env), vec![
+        ("search.lazy.partial".to_string(), "true".to_string()),
+        ("shutdown_timeout_secs".to_string(), "10".to_string()),
+    ]);
+}
+
+#[test]
+fn test_apply_override_creates_tables() {
+    let mut value: toml::Value = toml::from_str("[search]\ndebug = false").unwrap();
+    apply_override(&mut value, "search.cache.level", "5").unwrap();
+    apply_override(&mut value, "search.districts", r#"["36000", "11000"]"#).unwrap();
+    assert_eq!(value["search"]["cache"]["level"].as_integer(), Some(5));
+    assert_eq!(value["search"]["districts"].as_array().map(|a| a.len()), Some(2));
+    assert!(apply_override(&mut value, "", "1").is_err());
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::{polygon, square, Dataset};
+use tarantula_s2::diff::{diff, differing_levels, geometry_changed, Answer, Change, PointImpact,
+    Report, Status, SAMPLES};
+
+fn answer(level: i32, code: &str, name: &str) -> Answer {
+    Answer { level, code: code.to_string(), name: name.to_string() }
+}
+
+fn change(status: Status, code: &str, old_area_m2: Option<f64>, new_area_m2: Option<f64>) -> Change {
+    Change {
+        status,
+        layer: "AL_D002_".to_string(),
+        district: "46000".to_string(),
+        level: 5,
+        code: code.to_string(),
+        old_name: old_area_m2.map(|_| "1-1".to_string()),
+        new_name: new_area_m2.map(|_| "1-2".to_string()),
+        old_area_m2,
+        new_area_m2,
+        changed_area_m2: 0.0,
+        renamed: status == Status::Changed,
+        geometry_changed: false,
+        geometry: None,
+    }
+}
+
+#[test]
+fn test_geometry_changed() {
+    assert_eq!(geometry_changed(100.0, 100.0, 100.0, 1.0), (0.0, false));
+    assert_eq!(geometry_changed(100.0, 120.0, 100.0, 1.0), (20.0, true));
+    // moved without a change of area
+    assert_eq!(geometry_changed(100.0, 100.0, 90.0, 1.0), (20.0, true));
+    assert_eq!(geometry_changed(100.0, 100.5, 100.0, 1.0), (0.5, false));
+    // rounding of the overlay never goes negative
+    assert_eq!(geometry_changed(100.0, 100.0, 100.000001, 1.0).0, 0.0);
+}
+
+#[test]
+fn test_differing_levels() {
+    let old = vec![answer(1, "46", "전라남도"), answer(3, "46130250", "가곡동"),
+        answer(5, "4613025021100010000", "1-1")];
+    assert!(differing_levels(&old, &old).is_empty());
+
+    let renamed = vec![answer(1, "46", "전라남도"), answer(3, "46130250", "가곡동"),
+        answer(5, "4613025021100010000", "1-2")];
+    assert_eq!(differing_levels(&old, &renamed), vec![5]);
+
+    let missing = vec![answer(1, "46", "전라남도"), answer(3, "46130251", "가곡동")];
+    assert_eq!(differing_levels(&old, &missing), vec![3, 5]);
+}
+
+#[test]
+fn test_point_impact() {
+    let old = vec![answer(1, "46", "전라남도"), answer(5, "a", "1-1")];
+    let new = vec![answer(1, "46", "전라남도"), answer(5, "b", "1-1")];
+    let mut impact = PointImpact::default();
+    impact.add(127.0, 35.0, old.clone(), old.clone());
+    for _ in 0..SAMPLES + 5 {
+        impact.add(127.0, 35.0, old.clone(), new.clone());
+    }
+    assert_eq!(impact.points, SAMPLES + 6);
+    assert_eq!(impact.changed, SAMPLES + 5);
+    assert_eq!(impact.levels.get(&5), Some(&(SAMPLES + 5)));
+    assert_eq!(impact.levels.get(&1), None);
+    assert_eq!(impact.samples.len(), SAMPLES);
+    assert_eq!(PointImpact::default().changed_ratio(), 0.0);
+}
+
+#[test]
+fn test_feature_properties() {
+    let added = change(Status::Added, "4613025021100030000", None, Some(40.0));
+    assert_eq!(added.area_delta_m2(), 40.0);
+    let feature = added.to_feature();
+    assert_eq!(feature.id, Some(geojson::feature::Id::String("AL_D002_:4613025021100030000".to_string())));
+    let properties = feature.properties.unwrap();
+    assert_eq!(properties["change"], "added");
+    assert_eq!(properties["new_area_m2"], 40.0);
+    assert!(properties["old_name"].is_null());
+
+    let changed = change(Status::Changed, "4613025021100010000", Some(100.0), Some(90.0));
+    let properties = changed.to_feature().properties.unwrap();
+    assert_eq!(properties["change"], "changed");
+    assert_eq!(properties["area_delta_m2"], -10.0);
+    assert_eq!(properties["renamed"], true);
+}
+
+#[test]
+fn test_report_counts() {
+    let mut report = Report::default();
+    report.add("AL_D002_", 5, 10, 11, 1, 2, vec![
+        change(Status::Added, "c", None, Some(40.0)),
+        change(Status::Added, "d", None, Some(10.0)),
+        change(Status::Removed, "e", Some(30.0), None),
+        change(Status::Changed, "a", Some(100.0), Some(90.0)),
+    ]);
+    report.add("TL_SCCO_EMD", 3, 2, 2, 0, 0, vec![]);
+    report.add("AL_D002_", 5, 5, 5, 0, 1, vec![]);
+
+    assert_eq!(report.layers.len(), 2);
+    assert_eq!(report.layers[0].layer, "TL_SCCO_EMD");
+    let parcels = &report.layers[1];
+    assert_eq!((parcels.old_features, parcels.new_features, parcels.skipped), (15, 16, 1));
+    assert_eq!(parcels.invalid, 3);
+    assert_eq!((parcels.added, parcels.added_area_m2), (2, 50.0));
+    assert_eq!((parcels.removed, parcels.removed_area_m2), (1, 30.0));
+    assert_eq!((parcels.renamed, parcels.geometry_changed), (1, 0));
+    assert_eq!(parcels.changed_area_delta_m2, -10.0);
+    assert_eq!(report.changes.len(), 4);
+}
+
+#[test]
+fn test_invalid_feature_is_not_removed() {
+    let (old, new) = (Dataset::sejong("diff_old"), Dataset::sejong("diff_new"));
+    // the janggun parcel turns into a bow tie, a new parcel is added
+    new.write("36000", "AL_D002_36_20250504", &[
+        (polygon(&square(127.01, 36.51, 0.01)), &["3611025021100010000", "", "1-1", ""]),
+        (polygon(&[(127.06, 36.51), (127.07, 36.52), (127.07, 36.51), (127.06, 36.52)]),
+            &["3611031021100020000", "", "2-1", ""]),
+        (polygon(&square(127.03, 36.51, 0.01)), &["3611025021100040000", "", "4-1", ""]),
+    ]);
+
+    let report = diff(&old.config(&["36000"]), &old.path(), &new.path(), 1.0).unwrap();
+    let parcels = report.layers.iter().find(|counts| counts.level == 5).unwrap();
+    assert_eq!((parcels.added, parcels.removed, parcels.invalid), (1, 0, 1));
+    assert_eq!((parcels.old_features, parcels.new_features), (2, 2));
+    let changes: Vec<(Status, &str)> = report.changes
+        .iter()
+        .map(|change| (change.status, change.code.as_str()))
+        .collect();
+    assert_eq!(changes, vec![(Status::Added, "3611025021100040000")]);
+
+    let emd = report.layers.iter().find(|counts| counts.level == 3).unwrap();
+    assert_eq!((emd.old_features, emd.invalid, emd.added + emd.removed), (2, 0, 0));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{polygon, square, Dataset};
+use tarantula_s2::discover::{district_files, districts};
+
+fn dataset() -> Dataset {
+    let dataset = Dataset::sejong("discover");
+    for district in ["43000", "44000"] {
+        dataset.write(district, "TL_SCCO_CTPRVN", &[
+            (polygon(&square(127.2, 36.5, 0.1)), &[&district[..2], "", ""]),
+        ]);
+    }
+    // a folder without layer files and a stray file are no districts
+    std::fs::create_dir_all(dataset.root.join("11000")).unwrap();
+    std::fs::write(dataset.root.join("README.txt"), b"").unwrap();
+    dataset
+}
+
+#[test]
+fn test_discover_districts() {
+    let dataset = dataset();
+    let mut config = dataset.config(&[]);
+    config.discover = true;
+    assert_eq!(districts(&config).unwrap(), vec!["36000", "43000", "44000"]);
+
+    config.include = vec!["36000".to_string(), "44000".to_string(), "11000".to_string()];
+    assert_eq!(districts(&config).unwrap(), vec!["36000", "44000"]);
+
+    config.exclude = vec!["44000".to_string()];
+    assert_eq!(districts(&config).unwrap(), vec!["36000"]);
+
+    config.include = vec![];
+    assert_eq!(districts(&config).unwrap(), vec!["36000", "43000"]);
+}
+
+#[test]
+fn test_district_files() {
+    let dataset = dataset();
+    let config = dataset.config(&[]);
+    let files = district_files(&config, "36000").unwrap();
+    assert_eq!(files.layers, vec!["TL_SCCO_CTPRVN", "TL_SCCO_SIG", "TL_SCCO_EMD"]);
+    assert_eq!(files.missing, vec!["TL_SCCO_LI"]);
+    assert_eq!(files.parts, vec![("AL_D002_".to_string(), 1)]);
+
+    let files = district_files(&config, "43000").unwrap();
+    assert_eq!(files.missing.len(), 4);
+    assert!(district_files(&config, "11000").unwrap().is_empty());
+}
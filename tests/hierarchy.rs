@@ -0,0 +1,130 @@
+mod common;
+
+use common::{polygon, Dataset};
+use tarantula_s2::hierarchy::{classify, gap, Finding, Issue, Parent, Report};
+use tarantula_s2::Search;
+
+fn parent(code: &str, fraction: f64) -> Parent {
+    Parent { level: 3, code: code.to_string(), name: code.to_string(), fraction }
+}
+
+fn issues(code: &str, parents: &[Parent]) -> Vec<Issue> {
+    classify(code, parents, 0.01).into_iter().map(|(issue, _)| issue).collect()
+}
+
+#[test]
+fn test_inside_one_parent() {
+    assert!(issues("4613025021", &[parent("46130250", 1.0)]).is_empty());
+    // slivers over the neighbour are within the tolerance
+    assert!(issues("4613025021", &[parent("46130250", 0.995), parent("46130253", 0.005)])
+        .is_empty());
+}
+
+#[test]
+fn test_outside_and_straddling() {
+    let found = classify("4613025021", &[parent("46130250", 0.6), parent("46130253", 0.3)], 0.01);
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].0, Issue::Outside);
+    assert!((found[0].1 - 0.1).abs() < 1e-9);
+    assert_eq!(found[1], (Issue::Straddling, 0.6));
+
+    assert_eq!(issues("4613025021", &[]), vec![Issue::Outside]);
+}
+
+#[test]
+fn test_code_mismatch_against_dominant_parent() {
+    assert_eq!(issues("4613025021", &[parent("46130253", 1.0)]), vec![Issue::CodeMismatch]);
+    assert_eq!(issues("4613025021", &[parent("46130250", 0.4), parent("46130253", 0.6)]),
+        vec![Issue::Straddling, Issue::CodeMismatch]);
+    // regions without codes are not compared
+    assert!(issues("", &[parent("46130253", 1.0)]).is_empty());
+}
+
+#[test]
+fn test_gap() {
+    assert_eq!(gap(100.0, 100.0, 0.01), None);
+    assert_eq!(gap(100.0, 99.5, 0.01), None);
+    assert_eq!(gap(100.0, 80.0, 0.01), Some(0.2));
+    assert_eq!(gap(100.0, 120.0, 0.01), None);
+    assert_eq!(gap(0.0, 0.0, 0.01), None);
+}
+
+#[test]
+fn test_report_counts_per_level() {
+    let finding = |issue: Issue, level: i32| Finding {
+        issue,
+        district: "46000".to_string(),
+        level,
+        code: "4613025021".to_string(),
+        name: "name".to_string(),
+        area_m2: 1000.0,
+        fraction: 0.5,
+        parents: vec![],
+    };
+    let mut report = Report::default();
+    report.add(4, 10, vec![finding(Issue::Outside, 4), finding(Issue::CodeMismatch, 4)]);
+    report.add(3, 5, vec![finding(Issue::Gap, 3)]);
+    report.add(4, 2, vec![finding(Issue::Outside, 4)]);
+
+    let levels: Vec<(i32, usize, usize, usize, usize)> = report.levels
+        .iter()
+        .map(|counts| (counts.level, counts.regions, counts.outside, counts.code_mismatch,
+            counts.gap))
+        .collect();
+    assert_eq!(levels, vec![(3, 5, 0, 0, 1), (4, 12, 2, 1, 0)]);
+    assert_eq!(report.findings.len(), 4);
+
+    let mut out = vec![];
+    report.write_findings(&mut out).unwrap();
+    let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].contains("\"issue\":\"outside\""));
+    assert!(!lines[0].contains("parents"));
+}
+
+#[test]
+fn test_check_hierarchy_without_li() {
+    let dataset = Dataset::sejong("hierarchy");
+    let rect = |x0: f64, y0: f64, x1: f64, y1: f64| polygon(&[(x0, y0), (x1, y0), (x1, y1), (x0, y1)]);
+    // the first parcel covers jochiwon, the second all of janggun but a
+    // strip in the south, the third lies mostly in janggun with a jochiwon code
+    dataset.write("36000", "AL_D002_36_20250504", &[
+        (rect(127.0, 36.5, 127.05, 36.6), &["3611025021100010000", "", "1-1", ""]),
+        (rect(127.05, 36.52, 127.1, 36.6), &["3611031021100020000", "", "2-1", ""]),
+        (rect(127.04, 36.58, 127.09, 36.6), &["3611025021100030000", "", "3-1", ""]),
+    ]);
+    let mut search = Search::new(dataset.config(&["36000"])).unwrap();
+    search.load().unwrap();
+
+    let report = search.check_hierarchy(0.01).unwrap();
+    let levels: Vec<(i32, usize)> = report.levels
+        .iter()
+        .map(|counts| (counts.level, counts.regions))
+        .collect();
+    assert_eq!(levels, vec![(1, 1), (2, 1), (3, 2), (5, 3)]);
+
+    let mut findings: Vec<(Issue, &str)> = report.findings
+        .iter()
+        .map(|finding| (finding.issue, finding.code.as_str()))
+        .collect();
+    findings.sort();
+    assert_eq!(findings, vec![
+        (Issue::Straddling, "3611025021100030000"),
+        (Issue::CodeMismatch, "3611025021100030000"),
+        (Issue::Gap, "36110310"),
+    ]);
+
+    // without li the parcels are checked against the emd
+    let straddling = report.findings.iter().find(|finding| finding.issue == Issue::Straddling)
+        .unwrap();
+    assert!((straddling.fraction - 0.8).abs() < 0.01, "{}", straddling.fraction);
+    let mut parents: Vec<(i32, &str)> = straddling.parents
+        .iter()
+        .map(|parent| (parent.level, parent.code.as_str()))
+        .collect();
+    parents.sort();
+    assert_eq!(parents, vec![(3, "36110250"), (3, "36110310")]);
+
+    let gap = report.findings.iter().find(|finding| finding.issue == Issue::Gap).unwrap();
+    assert!((gap.fraction - 0.2).abs() < 0.01, "{}", gap.fraction);
+}
@@ -0,0 +1,15 @@
+use std::sync::Arc;
+use tarantula_s2::intern::Interner;
+
+#[test]
+fn test_intern_shares_strings() {
+    let interner = Interner::default();
+    let a = interner.intern("세종특별자치시");
+    let b = interner.intern("세종특별자치시");
+    let c = interner.intern("조치원읍");
+
+    assert!(Arc::ptr_eq(&a, &b));
+    assert!(!Arc::ptr_eq(&a, &c));
+    assert_eq!(interner.len(), 2);
+    assert_eq!(interner.bytes(), "세종특별자치시".len() + "조치원읍".len());
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use common::region;
+use tarantula_s2::lookup::{MatchType, NameIndex};
+
+#[test]
+fn test_name_index() {
+    let index = NameIndex::new(vec![
+        region("36", 1, "세종특별자치시", "Sejong-si", 0),
+        region("36110", 2, "세종시", "Sejong-si", 0),
+        region("36110250", 3, "조치원읍", "Jochiwon-eup", 0),
+        region("41", 1, "경기도", "Gyeonggi-do", 0),
+        region("41110250", 3, "조치원읍", "Jochiwon-eup", 0),
+    ]);
+
+    let r = index.search("조치원읍", 10);
+    assert_eq!(r.len(), 2);
+    assert_eq!(r[0].1, MatchType::Exact);
+
+    let r = index.search("세종 > 조치원읍", 10);
+    assert_eq!(r.len(), 1);
+    assert_eq!(r[0].0.code, "36110250");
+    let parents: Vec<_> = index.parents(r[0].0).iter().map(|p| p.code.clone()).collect();
+    assert_eq!(parents, vec!["36", "36110"]);
+
+    let r = index.search("jochiwon", 10);
+    assert_eq!(r[0].1, MatchType::Prefix);
+
+    let r = index.search("조치원음", 10);
+    assert_eq!(r[0].1, MatchType::Fuzzy);
+}
+
+#[test]
+fn test_parents_of_parcel_code() {
+    let index = NameIndex::new(vec![
+        region("36", 1, "세종특별자치시", "Sejong-si", 0),
+        region("36110", 2, "세종시", "Sejong-si", 0),
+        region("36110250", 3, "조치원읍", "Jochiwon-eup", 0),
+        region("3611025021", 4, "번암리", "Beonam-ri", 0),
+        region("36110310", 3, "장군면", "Janggun-myeon", 0),
+    ]);
+
+    let parents: Vec<&str> = index.parents_of("3611025021100010000")
+        .iter()
+        .map(|region| region.code.as_str())
+        .collect();
+    assert_eq!(parents, vec!["36", "36110", "36110250", "3611025021"]);
+
+    // an emd without li stops at the emd
+    let parents: Vec<&str> = index.parents_of("3611031022100020000")
+        .iter()
+        .map(|region| region.code.as_str())
+        .collect();
+    assert_eq!(parents, vec!["36", "36110", "36110310"]);
+    assert!(index.parents_of("4113025021100010000").is_empty());
+}
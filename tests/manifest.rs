@@ -0,0 +1,90 @@
+use tarantula_s2::discover::LayerFile;
+use tarantula_s2::manifest::{file_entries, release_date, version, FileEntry, Manifest};
+
+fn entry(name: &str, district: &str, layer: &str, sha256: &str) -> FileEntry {
+    FileEntry {
+        name: name.to_string(),
+        layer: layer.to_string(),
+        district: district.to_string(),
+        release: release_date(name),
+        size: 10,
+        modified: 0,
+        sha256: sha256.to_string(),
+        polygons: None,
+        rejected: None,
+    }
+}
+
+#[test]
+fn test_release_date() {
+    assert_eq!(release_date("data/36000/AL_D002_36_20250504.shp").as_deref(), Some("20250504"));
+    assert_eq!(release_date("AL_D002_46_20250204_part3.dbf").as_deref(), Some("20250204"));
+    assert_eq!(release_date("AL_D002_46_20250204_6_part22.shp").as_deref(), Some("20250204"));
+    assert_eq!(release_date("TL_SCCO_CTPRVN.shp"), None);
+    assert_eq!(release_date("AL_D002_46_2025020.shp"), None);
+}
+
+#[test]
+fn test_version() {
+    let a = entry("36000/AL_D002_36_20250504.shp", "36000", "AL_D002_", "aa");
+    let b = entry("46000/AL_D002_46_20250204_part1.shp", "46000", "AL_D002_", "bb");
+    let c = entry("46000/TL_SCCO_EMD.shp", "46000", "TL_SCCO_EMD", "cc");
+
+    let v = version(&[a.clone(), b.clone(), c.clone()]);
+    assert!(v.starts_with("20250504-"));
+    assert_eq!(v.len(), "20250504-".len() + 12);
+    assert_eq!(v, version(&[c.clone(), a.clone(), b.clone()]));
+
+    let changed = entry("46000/TL_SCCO_EMD.shp", "46000", "TL_SCCO_EMD", "cd");
+    assert_ne!(v, version(&[a, b, changed]));
+    assert!(version(&[c]).starts_with("unknown-"));
+}
+
+#[test]
+fn test_replace_district() {
+    let mut manifest = Manifest::new(vec![
+        entry("46000/AL_D002_46_20250204_part1.shp", "46000", "AL_D002_", "a"),
+        entry("46000/AL_D002_46_20250204_part2.shp", "46000", "AL_D002_", "b"),
+        entry("46000/TL_SCCO_CTPRVN.shp", "46000", "TL_SCCO_CTPRVN", "c"),
+        entry("36000/AL_D002_36_20250504.shp", "36000", "AL_D002_", "d"),
+    ], 100, 2.5);
+    assert_eq!(manifest.releases, vec!["20250204", "20250504"]);
+    let before = manifest.version.clone();
+
+    manifest.replace_district("46000", &["AL_D002_".to_string()],
+        vec![entry("46000/AL_D002_46_20250704.shp", "46000", "AL_D002_", "e")]);
+    let names: Vec<&str> = manifest.files.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, vec!["36000/AL_D002_36_20250504.shp", "46000/AL_D002_46_20250704.shp",
+        "46000/TL_SCCO_CTPRVN.shp"]);
+    assert_eq!(manifest.releases, vec!["20250504", "20250704"]);
+    assert!(manifest.version.starts_with("20250704-"));
+    assert_ne!(manifest.version, before);
+    assert_eq!((manifest.loaded_at, manifest.load_secs), (100, 2.5));
+}
+
+#[test]
+fn test_file_entries() {
+    let root = std::env::temp_dir().join(format!("tarantula_manifest_{}", std::process::id()));
+    let dir = root.join("46000");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("AL_D002_46_20250204.shp"), b"shape").unwrap();
+    std::fs::write(dir.join("AL_D002_46_20250204.dbf"), b"").unwrap();
+
+    let files = vec![LayerFile {
+        layer: "AL_D002_".to_string(),
+        district: "46000".to_string(),
+        path: dir.join("AL_D002_46_20250204.shp").display().to_string(),
+    }];
+    let mut entries = file_entries(&root.display().to_string(), &files).unwrap();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    std::fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].name, "46000/AL_D002_46_20250204.dbf");
+    assert_eq!(entries[0].sha256,
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(entries[1].name, "46000/AL_D002_46_20250204.shp");
+    assert_eq!(entries[1].size, 5);
+    assert_eq!(entries[1].release.as_deref(), Some("20250204"));
+    assert_eq!(entries[1].district, "46000");
+}
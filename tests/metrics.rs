@@ -0,0 +1,40 @@
+mod common;
+
+use common::Dataset;
+use std::time::Duration;
+use tarantula_s2::metrics::{error_code_label, METRICS};
+use tarantula_s2::Search;
+
+#[test]
+fn test_render_metrics() {
+    METRICS.observe_request("rest", "/tarantula", "200", Duration::from_millis(3));
+    METRICS.observe_levels(&[1, 2, 5], &[1, 2]);
+    METRICS.observe_polygons(5, 10, &[(error_code_label(2), 1)]);
+
+    let text = METRICS.render(None).expect("render");
+    assert!(text.contains(r#"tarantula_requests_total{api="rest",endpoint="/tarantula",status="200"} 1"#));
+    assert!(text.contains(r#"tarantula_level_results_total{level="5",result="miss"} 1"#));
+    assert!(text.contains(r#"tarantula_polygons_rejected_total{code="outer_curvature",level="5"} 1"#));
+}
+
+#[test]
+fn test_snapshot_without_metrics() {
+    // level 3 is observed by no other test of this file
+    let dataset = Dataset::sejong("metrics");
+    let loaded = r#"tarantula_polygons_loaded_total{level="3"} 2"#;
+    let hit = r#"tarantula_level_results_total{level="3",result="hit"} 1"#;
+
+    let mut snapshot = Search::new(dataset.config(&["36000"])).unwrap().without_metrics();
+    snapshot.load().unwrap();
+    snapshot.search(127.015, 36.515).unwrap();
+    let text = METRICS.render(None).expect("render");
+    assert!(!text.contains(r#"tarantula_polygons_loaded_total{level="3"}"#));
+    assert!(!text.contains(r#"tarantula_level_results_total{level="3""#));
+
+    let mut current = Search::new(dataset.config(&["36000"])).unwrap();
+    current.load().unwrap();
+    current.search(127.015, 36.515).unwrap();
+    let text = METRICS.render(None).expect("render");
+    assert!(text.contains(loaded));
+    assert!(text.contains(hit));
+}
@@ -0,0 +1,91 @@
+use tarantula_s2::overlap::{polygons, Kind, Problem, Report, Source};
+
+fn square(x: f64, y: f64, size: f64) -> Vec<(f64, f64)> {
+    vec![(x, y), (x + size, y), (x + size, y + size), (x, y + size)]
+}
+
+fn problem(kind: Kind, sources: Vec<Source>, area_m2: f64) -> Problem {
+    Problem {
+        kind,
+        layer: "AL_D002_".to_string(),
+        district: "46000".to_string(),
+        level: 5,
+        area_m2,
+        sources,
+        polygons: vec![vec![{
+            let mut ring = square(127.0, 35.0, 0.001);
+            ring.push(ring[0]);
+            ring
+        }]],
+    }
+}
+
+fn source(file: &str, feature: usize, code: &str) -> Source {
+    Source { file: file.to_string(), feature, code: code.to_string(), name: String::new() }
+}
+
+#[test]
+fn test_polygons_split_loops() {
+    let mut lnglats = square(0.0, 0.0, 10.0);
+    lnglats.extend(square(2.0, 2.0, 1.0));
+    lnglats.extend(square(20.0, 0.0, 1.0));
+    lnglats.extend([(30.0, 0.0), (31.0, 0.0)]);
+    let found = polygons(&lnglats, &[4, 4, 4, 2], &[100.0, -1.0, 1.0, 0.0]);
+
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].0, 99.0);
+    assert_eq!(found[0].1.len(), 2);
+    assert_eq!(found[0].1[0].len(), 5);
+    assert_eq!(found[0].1[0].first(), found[0].1[0].last());
+    assert_eq!(found[1].0, 1.0);
+    assert_eq!(found[1].1.len(), 1);
+}
+
+#[test]
+fn test_overlap_id_is_stable() {
+    let a = problem(Kind::Overlap, vec![source("part2.shp", 7, "4613025021100010000"),
+        source("part1.shp", 3, "4613025021100020000")], 12.0);
+    let b = problem(Kind::Overlap, vec![source("part9.shp", 1, "4613025021100020000"),
+        source("part9.shp", 5, "4613025021100010000")], 12.0);
+    assert_eq!(a.id(), "overlap:AL_D002_:4613025021100010000:4613025021100020000");
+    assert_eq!(a.id(), b.id());
+
+    let no_codes = problem(Kind::Overlap, vec![source("data/46000/TL_SCCO_LI.shp", 4, ""),
+        source("data/46000/TL_SCCO_LI.shp", 9, "")], 12.0);
+    assert_eq!(no_codes.id(), "overlap:AL_D002_:TL_SCCO_LI#4:TL_SCCO_LI#9");
+
+    let gap = problem(Kind::Gap, vec![], 3.0);
+    assert_eq!(gap.id(), "gap:AL_D002_:46000:127.00050,35.00050");
+}
+
+#[test]
+fn test_feature_properties() {
+    let overlap = problem(Kind::Overlap, vec![source("part1.shp", 3, "a"),
+        source("part2.shp", 7, "b")], 12.0);
+    let feature = overlap.to_feature();
+    assert_eq!(feature.id, Some(geojson::feature::Id::String("overlap:AL_D002_:a:b".to_string())));
+    let properties = feature.properties.unwrap();
+    assert_eq!(properties["kind"], "overlap");
+    assert_eq!(properties["file_a"], "part1.shp");
+    assert_eq!(properties["feature_b"], 7);
+}
+
+#[test]
+fn test_report_counts() {
+    let mut report = Report::default();
+    report.add("AL_D002_", 5, 2, 100, vec![
+        problem(Kind::Overlap, vec![source("a.shp", 1, "a"), source("a.shp", 2, "b")], 10.0),
+        problem(Kind::Gap, vec![], 4.0),
+        problem(Kind::Gap, vec![], 6.0),
+    ]);
+    report.add("TL_SCCO_LI", 4, 1, 20, vec![]);
+    report.add("AL_D002_", 5, 3, 50, vec![]);
+
+    assert_eq!(report.layers.len(), 2);
+    assert_eq!(report.layers[0].layer, "TL_SCCO_LI");
+    let parcels = &report.layers[1];
+    assert_eq!((parcels.files, parcels.features), (5, 150));
+    assert_eq!((parcels.overlaps, parcels.overlap_area_m2), (1, 10.0));
+    assert_eq!((parcels.gaps, parcels.gap_area_m2), (2, 10.0));
+    assert_eq!(report.problems.len(), 3);
+}
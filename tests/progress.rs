@@ -0,0 +1,54 @@
+use tarantula_s2::progress::{Gate, Progress};
+
+fn progress() -> Progress {
+    let progress = Progress::default();
+    progress.start(vec![
+        ("TL_SCCO_CTPRVN".to_string(), 1, Gate::Partial, 2),
+        ("TL_SCCO_EMD".to_string(), 3, Gate::Partial, 2),
+        ("AL_D002_".to_string(), 5, Gate::Full, 2),
+    ]);
+    progress
+}
+
+#[test]
+fn test_partial_gate() {
+    let progress = progress();
+    assert!(!progress.readiness().partial);
+    assert_eq!(progress.loading_levels(), vec![1, 3, 5]);
+
+    ["36000", "11000"].iter().for_each(|district| {
+        progress.record("TL_SCCO_CTPRVN", district, true);
+        progress.record("TL_SCCO_EMD", district, true);
+    });
+    // a district recorded twice counts once
+    progress.record("TL_SCCO_EMD", "36000", true);
+
+    let readiness = progress.readiness();
+    assert!(readiness.ready(Gate::Partial));
+    assert!(!readiness.ready(Gate::Full));
+    assert_eq!(readiness.layers[1].loaded, 2);
+    assert_eq!(progress.loading_levels(), vec![5]);
+}
+
+#[test]
+fn test_full_gate() {
+    let progress = progress();
+    progress.record("AL_D002_", "36000", false);
+    assert_eq!(progress.readiness().layers[2].failed, 1);
+
+    progress.finish();
+    let readiness = progress.readiness();
+    assert!(readiness.partial && readiness.full);
+    assert!(progress.loading_levels().is_empty());
+}
+
+#[test]
+fn test_failed_load() {
+    let progress = progress();
+    progress.fail("failed to load TL_SCCO_CTPRVN");
+
+    let readiness = progress.readiness();
+    assert!(!readiness.ready(Gate::Partial));
+    assert!(!readiness.ready(Gate::Full));
+    assert_eq!(progress.error().as_deref(), Some("failed to load TL_SCCO_CTPRVN"));
+}
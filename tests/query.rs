@@ -0,0 +1,86 @@
+use tarantula_s2::query::{features, parse_point, Format, Hit, PointResult, Printer};
+
+fn result() -> PointResult {
+    PointResult {
+        lon: 127.2857,
+        lat: 36.5065,
+        hits: vec![
+            Hit {
+                level: 1,
+                code: "36".to_string(),
+                name: "세종특별자치시".to_string(),
+                eng_name: "Sejong-si".to_string(),
+                district: "36000".to_string(),
+                fallback: false,
+                lnglats: vec![(127.0, 36.0), (128.0, 36.0), (128.0, 37.0)],
+            },
+            Hit {
+                level: 3,
+                code: "36110250".to_string(),
+                name: "조치원읍".to_string(),
+                eng_name: "Jochiwon-eup".to_string(),
+                district: "36000".to_string(),
+                fallback: false,
+                lnglats: vec![],
+            },
+        ],
+    }
+}
+
+#[test]
+fn test_parse_point() {
+    assert_eq!(parse_point("127.1,35.1").unwrap().unwrap(), (127.1, 35.1));
+    assert_eq!(parse_point("  127.1 \t 35.1 ").unwrap().unwrap(), (127.1, 35.1));
+    assert_eq!(parse_point("127.1, 35.1").unwrap().unwrap(), (127.1, 35.1));
+    assert!(parse_point("").is_none());
+    assert!(parse_point("# lon,lat").is_none());
+    assert!(parse_point("127.1").unwrap().is_err());
+    assert!(parse_point("a,b").unwrap().is_err());
+}
+
+#[test]
+fn test_print_table() {
+    let mut out = vec![];
+    let mut printer = Printer::new(Format::Table, &mut out);
+    printer.print(&result()).unwrap();
+    printer.print(&PointResult { lon: 0.0, lat: 0.0, hits: vec![] }).unwrap();
+    printer.finish().unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("lon\tlat\tlevel"));
+    assert!(lines[2].contains("36110250\t조치원읍"));
+    assert_eq!(lines[3], "0\t0\t-\t-\t-\t-\t-");
+}
+
+#[test]
+fn test_print_json_lines() {
+    let mut out = vec![];
+    let mut printer = Printer::new(Format::Json, &mut out);
+    printer.print(&result()).unwrap();
+    printer.print(&result()).unwrap();
+    printer.finish().unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 2);
+    let value: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(value["hits"][1]["code"], "36110250");
+    assert!(value["hits"][0].get("lnglats").is_none());
+}
+
+#[test]
+fn test_geojson_features() {
+    let features = features(&result());
+    // the point and the polygon of the only hit with a ring
+    assert_eq!(features.len(), 2);
+    let polygon = features[1].geometry.as_ref().unwrap();
+    match &polygon.value {
+        geojson::Value::Polygon(rings) => {
+            assert_eq!(rings[0].len(), 4);
+            assert_eq!(rings[0].first(), rings[0].last());
+        }
+        _ => panic!("expected a polygon"),
+    }
+}
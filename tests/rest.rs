@@ -0,0 +1,12 @@
+use tarantula_s2::rest::admin_authorized;
+
+#[test]
+fn test_admin_authorized() {
+    assert!(admin_authorized(Some("Bearer s3cret"), "s3cret"));
+    assert!(!admin_authorized(Some("Bearer s3cre"), "s3cret"));
+    assert!(!admin_authorized(Some("Bearer s3cret!"), "s3cret"));
+    assert!(!admin_authorized(Some("s3cret"), "s3cret"));
+    assert!(!admin_authorized(Some("Basic s3cret"), "s3cret"));
+    assert!(!admin_authorized(None, "s3cret"));
+    assert!(!admin_authorized(Some("Bearer "), ""));
+}
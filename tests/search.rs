@@ -0,0 +1,269 @@
+mod common;
+
+use common::{polygon, square, Dataset};
+use tarantula_s2::config::{Covering, LazyLoad, Search as SearchConfig};
+use tarantula_s2::search::eviction_victims;
+use tarantula_s2::Search;
+
+fn load(config: SearchConfig) -> Search {
+    let mut search = Search::new(config).unwrap();
+    search.load().unwrap();
+    search
+}
+
+fn codes(search: &Search, lon: f64, lat: f64) -> Vec<String> {
+    search.search(lon, lat).unwrap().into_iter().map(|info| info.code).collect()
+}
+
+#[test]
+fn test_covering_same_answers() {
+    let dataset = Dataset::sejong("covering");
+    let plain = load(dataset.config(&["36000"]));
+    let mut config = dataset.config(&["36000"]);
+    config.covering = Some(Covering { max_cells: 64, max_level: 20 });
+    let covered = load(config);
+
+    // deep inside, next to the parcel and emd boundaries and outside
+    let points = [(127.015, 36.515), (127.01 + 1e-7, 36.515), (127.05 - 1e-7, 36.55),
+        (127.05 + 1e-7, 36.55), (127.08, 36.58), (127.2, 36.55)];
+    for (lon, lat) in points {
+        assert_eq!(codes(&covered, lon, lat), codes(&plain, lon, lat), "{},{}", lon, lat);
+    }
+    assert_eq!(codes(&covered, 127.015, 36.515),
+        vec!["36", "36110", "36110250", "3611025021100010000"]);
+}
+
+#[test]
+fn test_derive_parents() {
+    let dataset = Dataset::sejong("derive_parents");
+    let mut config = dataset.config(&["36000"]);
+    config.derive_parents = true;
+    let search = load(config);
+
+    let found = search.search(127.015, 36.515).unwrap();
+    let levels: Vec<(i32, &str)> = found.iter().map(|info| (info.level, info.code.as_str())).collect();
+    assert_eq!(levels, vec![(1, "36"), (2, "36110"), (3, "36110250"), (5, "3611025021100010000")]);
+    assert_eq!(found[2].name, "Jochiwon");
+}
+
+// district 43000 east of sejong, its level 1 polygon a little short of the
+// lower levels like the real boundaries
+fn write_chungbuk(dataset: &Dataset) {
+    dataset.write("43000", "TL_SCCO_CTPRVN", &[
+        (polygon(&[(127.1001, 36.5), (127.2, 36.5), (127.2, 36.6), (127.1001, 36.6)]),
+            &["43", "Chungbuk", "Chungcheongbuk-do"]),
+    ]);
+    dataset.write("43000", "TL_SCCO_SIG", &[
+        (polygon(&square(127.1, 36.5, 0.1)), &["43111", "Cheongju", "Cheongju-si"]),
+    ]);
+    dataset.write("43000", "TL_SCCO_EMD", &[
+        (polygon(&square(127.1, 36.5, 0.1)), &["43111101", "Nangseong", "Nangseong-myeon"]),
+    ]);
+    dataset.write("43000", "AL_D002_43_20250504", &[
+        (polygon(&square(127.15, 36.55, 0.01)), &["4311110121100010000", "", "3-1", ""]),
+    ]);
+}
+
+#[test]
+fn test_border_fallback() {
+    let dataset = Dataset::sejong("border");
+    write_chungbuk(&dataset);
+    let (lon, lat) = (127.10005, 36.55);
+
+    let search = load(dataset.config(&["36000", "43000"]));
+    assert!(codes(&search, lon, lat).is_empty());
+
+    let mut config = dataset.config(&["36000", "43000"]);
+    config.border_distance = 50.0;
+    let search = load(config);
+    let found = search.search(lon, lat).unwrap();
+    let levels: Vec<(i32, &str, bool)> = found
+        .iter()
+        .map(|info| (info.level, info.code.as_str(), info.fallback))
+        .collect();
+    assert_eq!(levels, vec![(2, "43111", true), (3, "43111101", true)]);
+
+    // inside a district nothing is borrowed from the neighbour
+    assert_eq!(codes(&search, 127.015, 36.515),
+        vec!["36", "36110", "36110250", "3611025021100010000"]);
+}
+
+#[test]
+fn test_eviction_victims() {
+    let districts = [("11000", 40, 3), ("36000", 30, 1), ("43000", 20, 2), ("46000", 10, 4)];
+    assert!(eviction_victims(&districts, 100, "46000").is_empty());
+    // the least recently used go first
+    assert_eq!(eviction_victims(&districts, 70, "46000"), vec!["36000"]);
+    assert_eq!(eviction_victims(&districts, 50, "46000"), vec!["36000", "43000"]);
+    // the district just loaded stays even over the budget
+    assert_eq!(eviction_victims(&districts, 5, "46000"), vec!["36000", "43000", "11000"]);
+    assert_eq!(eviction_victims(&districts, 60, "36000"), vec!["43000", "11000"]);
+}
+
+#[test]
+fn test_lazy_load_on_first_search() {
+    let dataset = Dataset::sejong("lazy");
+    write_chungbuk(&dataset);
+    let mut config = dataset.config(&["36000", "43000"]);
+    config.lazy = Some(LazyLoad { memory_budget_mb: 0, partial: false });
+    let search = load(config);
+    assert_eq!(search.lazy_stats().districts, 0);
+
+    assert_eq!(codes(&search, 127.015, 36.515),
+        vec!["36", "36110", "36110250", "3611025021100010000"]);
+    let districts = search.districts().unwrap();
+    assert_eq!(districts.iter().filter(|district| district.loaded).count(), 1);
+    assert!(districts[0].loaded && districts[0].bytes > 0);
+    assert_eq!(search.lazy_stats().loads, 1);
+
+    assert!(search.unload_district("36000").unwrap());
+    assert!(!search.unload_district("36000").unwrap());
+    assert_eq!(search.lazy_stats().districts, 0);
+}
+
+#[test]
+fn test_reload_path_under_shapefile_path() {
+    let dataset = Dataset::sejong("reload_path");
+    let outside = Dataset::sejong("reload_path_outside");
+    let search = load(dataset.config(&["36000"]));
+
+    let fixed = dataset.root.join("fixed");
+    std::fs::create_dir_all(&fixed).unwrap();
+    std::fs::rename(dataset.root.join("36000"), fixed.join("36000")).unwrap();
+    assert!(search.reload_district("36000", Some(&fixed.display().to_string())).is_ok());
+
+    assert!(search.reload_district("36000", Some(&outside.path())).is_err());
+    let escaped = format!("{}/../{}", dataset.path(),
+        outside.root.file_name().unwrap().to_str().unwrap());
+    assert!(search.reload_district("36000", Some(&escaped)).is_err());
+    assert!(search.reload_district("99000", None).is_err());
+}
+
+#[test]
+fn test_lower_levels_before_parcels() {
+    let dataset = Dataset::sejong("before_parcels");
+    // a broken part fails the parcel layer and the load before the district
+    // is recorded, leaving it as it is between the district_par and
+    // district_par_any phases
+    std::fs::write(dataset.root.join("36000/AL_D002_36_20250504.shp"), b"not a shapefile")
+        .unwrap();
+    let mut search = Search::new(dataset.config(&["36000"])).unwrap();
+    assert!(search.load().is_err());
+
+    assert_eq!(codes(&search, 127.015, 36.515), vec!["36", "36110", "36110250"]);
+    assert_eq!(codes(&search, 127.065, 36.515), vec!["36", "36110", "36110310"]);
+    assert!(!search.districts().unwrap()[0].loaded);
+    assert_eq!(search.footprint().polygons.get(&5), None);
+}
+
+#[test]
+fn test_footprint_follows_loads() {
+    let dataset = Dataset::sejong("footprint");
+    let search = load(dataset.config(&["36000"]));
+    let measured = |search: &Search| {
+        let report = search.memory_report().unwrap();
+        (report.index_bytes, report.info_bytes)
+    };
+
+    let footprint = search.footprint();
+    assert_eq!((footprint.index_bytes, footprint.info_bytes), measured(&search));
+    let mut polygons: Vec<(i32, usize)> = footprint.polygons.into_iter().collect();
+    polygons.sort();
+    assert_eq!(polygons, vec![(1, 1), (2, 1), (3, 2), (5, 2)]);
+
+    assert!(search.unload_district("36000").unwrap());
+    let footprint = search.footprint();
+    assert_eq!((footprint.index_bytes, footprint.info_bytes), measured(&search));
+    assert_eq!(footprint.polygons.into_iter().collect::<Vec<_>>(), vec![(1, 1)]);
+
+    search.reload_district("36000", None).unwrap();
+    assert_eq!(search.footprint().polygons.len(), 4);
+    assert_eq!(search.load_status().polygons, 6);
+}
+
+#[test]
+fn test_manifest_after_reload_from_other_path() {
+    let dataset = sejong_with_bow_tie("manifest_reload");
+    let search = load(dataset.config(&["36000"]));
+    assert_eq!(rejected(&search, 5), 1);
+
+    let fixed = dataset.root.join("fixed/36000");
+    std::fs::create_dir_all(&fixed).unwrap();
+    for entry in std::fs::read_dir(dataset.root.join("36000")).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, fixed.join(path.file_name().unwrap())).unwrap();
+    }
+    // the bow tie is still there next to a new parcel
+    dataset.write("fixed/36000", "AL_D002_36_20250504", &[
+        (polygon(&square(127.01, 36.51, 0.01)), &["3611025021100010000", "", "1-1", ""]),
+        (polygon(&square(127.03, 36.51, 0.01)), &["3611025021100040000", "", "4-1", ""]),
+        (polygon(&[(127.06, 36.51), (127.07, 36.52), (127.07, 36.51), (127.06, 36.52)]),
+            &["3611031021100020000", "", "2-1", ""]),
+    ]);
+    search.reload_district("36000", Some(&format!("{}/fixed", dataset.path()))).unwrap();
+
+    let manifest = search.manifest().unwrap();
+    let counts = |name: &str| manifest.files
+        .iter()
+        .find(|file| file.name == name)
+        .map(|file| file.polygons);
+    assert_eq!(counts("fixed/36000/AL_D002_36_20250504.shp"), Some(Some(3)));
+    assert_eq!(counts("fixed/36000/TL_SCCO_EMD.shp"), Some(Some(2)));
+    assert_eq!(counts("36000/TL_SCCO_CTPRVN.shp"), Some(Some(1)));
+    assert_eq!(counts("36000/AL_D002_36_20250504.shp"), None);
+    // counted once, not once per path
+    assert_eq!(rejected(&search, 5), 1);
+    let parcels = manifest.layers.iter().find(|layer| layer.level == 5).unwrap();
+    assert_eq!(parcels.rejected, 1);
+}
+
+#[test]
+fn test_failed_part_reported() {
+    let dataset = Dataset::sejong("failed_part");
+    std::fs::write(dataset.root.join("36000/AL_D002_36_20250504_part2.shp"), b"not a shapefile")
+        .unwrap();
+    let mut search = Search::new(dataset.config(&["36000"])).unwrap();
+    let error = search.load().unwrap_err();
+    assert!(error.to_string().contains("36000"), "{}", error);
+
+    let status = search.load_status();
+    assert!(!status.healthy());
+    let parcels = status.layers.iter().find(|layer| layer.level == 5).unwrap();
+    assert_eq!((parcels.districts, parcels.failed), (0, 1));
+    let emd = status.layers.iter().find(|layer| layer.level == 3).unwrap();
+    assert_eq!((emd.districts, emd.failed), (1, 0));
+}
+
+// sejong with a bow tie parcel, rejected at load
+fn sejong_with_bow_tie(name: &str) -> Dataset {
+    let dataset = Dataset::sejong(name);
+    dataset.write("36000", "AL_D002_36_20250504", &[
+        (polygon(&square(127.01, 36.51, 0.01)), &["3611025021100010000", "", "1-1", ""]),
+        (polygon(&[(127.06, 36.51), (127.07, 36.52), (127.07, 36.51), (127.06, 36.52)]),
+            &["3611031021100020000", "", "2-1", ""]),
+    ]);
+    dataset
+}
+
+fn rejected(search: &Search, level: i32) -> usize {
+    search.load_status().layers.iter().find(|layer| layer.level == level).unwrap().rejected
+}
+
+#[test]
+fn test_rejected_polygons() {
+    let dataset = sejong_with_bow_tie("rejected");
+    let search = load(dataset.config(&["36000"]));
+    assert_eq!((rejected(&search, 3), rejected(&search, 5)), (0, 1));
+}
+
+#[test]
+fn test_unload_forgets_rejected() {
+    let dataset = sejong_with_bow_tie("unload_rejected");
+    let search = load(dataset.config(&["36000"]));
+    assert_eq!(rejected(&search, 5), 1);
+
+    assert!(search.unload_district("36000").unwrap());
+    assert_eq!(rejected(&search, 5), 0);
+    search.reload_district("36000", None).unwrap();
+    assert_eq!(rejected(&search, 5), 1);
+}
@@ -0,0 +1,12 @@
+use tarantula_s2::server_error;
+
+#[tokio::test]
+async fn test_server_error() {
+    assert_eq!(server_error("rest", Ok(Ok(()))), "rest server stopped unexpectedly");
+    assert_eq!(server_error("grpc", Ok(Err("address in use".to_string()))),
+        "grpc server failed: address in use");
+
+    let panicked = tokio::spawn(async { panic!("boom") }).await.map(|()| Ok(()));
+    let error = server_error("rest", panicked);
+    assert!(error.starts_with("rest server panicked: "), "{}", error);
+}
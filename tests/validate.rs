@@ -0,0 +1,114 @@
+mod common;
+
+use common::{polygon, square};
+use shapefile::Polygon;
+use tarantula_s2::config::Validate;
+use tarantula_s2::utils::shape::Feature;
+use tarantula_s2::validate::{check_feature, Counts, LayerReport, Report};
+
+fn report() -> Report {
+    let counts = Counts {
+        features: 120,
+        duplicate_vertices: 40,
+        crossing_edges: 2,
+        curvature: 1,
+        empty_rings: 3,
+        empty_attributes: 0,
+        invalid_loops: 0,
+    };
+    Report {
+        layers: vec![LayerReport {
+            layer: "AL_D002_".to_string(),
+            level: 5,
+            files: 4,
+            failed_files: 0,
+            counts: counts.clone(),
+        }],
+        total: counts,
+        errors: vec![],
+        features: vec![],
+    }
+}
+
+#[test]
+fn test_default_thresholds() {
+    let violations = report().violations(&Validate::default());
+    assert_eq!(violations, vec![
+        "crossing_edges 2 above 0".to_string(),
+        "curvature 1 above 0".to_string(),
+    ]);
+}
+
+#[test]
+fn test_configured_thresholds() {
+    let thresholds: Validate = toml::from_str("crossing_edges = 5\ncurvature = 1\nduplicate_vertices = 10")
+        .unwrap();
+    // unset kinds keep their defaults
+    assert_eq!(thresholds.invalid_loops, Some(0));
+    assert_eq!(report().violations(&thresholds), vec!["duplicate_vertices 40 above 10".to_string()]);
+
+    let mut report = report();
+    report.errors.push("36000/TL_SCCO_LI.shp: failed to get string".to_string());
+    assert_eq!(report.violations(&thresholds).last().unwrap(), "1 files failed to load");
+}
+
+#[test]
+fn test_write_table() {
+    let mut out = vec![];
+    report().write_table(&mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1], "AL_D002_\t5\t4\t0\t120\t40\t2\t1\t3\t0\t0");
+    assert_eq!(lines[2], "total\t-\t4\t0\t120\t40\t2\t1\t3\t0\t0");
+}
+
+fn feature(gp: Polygon, code: &str, name: &str) -> Feature {
+    Feature::check("fixture.shp", 0, gp, code.to_string(), name.to_string()).unwrap().0
+}
+
+fn check(gp: Polygon) -> (Counts, Vec<String>) {
+    check_feature(&feature(gp, "36110250", "Jochiwon"))
+}
+
+#[test]
+fn test_check_valid_feature() {
+    let (counts, problems) = check(polygon(&square(127.0, 36.5, 0.01)));
+    assert_eq!(counts, Counts { features: 1, ..Counts::default() });
+    assert!(problems.is_empty(), "{:?}", problems);
+
+    let (counts, _) = check_feature(&feature(polygon(&square(127.0, 36.5, 0.01)), "", ""));
+    assert_eq!(counts.empty_attributes, 1);
+}
+
+#[test]
+fn test_check_duplicate_vertices() {
+    let (counts, problems) = check(polygon(&[(127.0, 36.5), (127.01, 36.5), (127.01, 36.5),
+        (127.01, 36.51), (127.0, 36.51)]));
+    assert_eq!(counts, Counts { features: 1, duplicate_vertices: 1, ..Counts::default() });
+    assert_eq!(problems, vec!["ring 0: 1 duplicate vertices"]);
+}
+
+#[test]
+fn test_check_crossing_edges() {
+    // a bow tie, its edges cross in the middle
+    let (counts, problems) = check(polygon(&[(127.0, 36.5), (127.01, 36.51), (127.01, 36.5),
+        (127.0, 36.51)]));
+    assert_eq!(counts, Counts { features: 1, crossing_edges: 1, ..Counts::default() });
+    assert_eq!(problems, vec!["ring 0: outer crossing edges"]);
+    assert!(!feature(polygon(&[(127.0, 36.5), (127.01, 36.51), (127.01, 36.5), (127.0, 36.51)]),
+        "36110250", "Jochiwon").valid());
+    assert!(feature(polygon(&square(127.0, 36.5, 0.01)), "36110250", "Jochiwon").valid());
+}
+
+#[test]
+fn test_check_empty_rings() {
+    let (counts, problems) = check(Polygon::with_rings(vec![]));
+    assert_eq!(counts, Counts { features: 1, empty_rings: 1, ..Counts::default() });
+    assert_eq!(problems, vec!["no rings"]);
+
+    // two distinct vertices once closed
+    let (counts, problems) = check(polygon(&[(127.0, 36.5), (127.01, 36.5)]));
+    assert_eq!(counts, Counts { features: 1, empty_rings: 1, ..Counts::default() });
+    assert_eq!(problems, vec!["ring 0: outer fewer than 3 vertices"]);
+}
@@ -0,0 +1,68 @@
+use tarantula_s2::config::Snapshot;
+use tarantula_s2::versions::{days, parse_date, retained, select, CURRENT};
+
+fn snapshot(name: &str, as_of: Option<&str>) -> Snapshot {
+    Snapshot {
+        name: name.to_string(),
+        path: format!("./data/snapshots/{}", name),
+        as_of: as_of.map(|as_of| as_of.to_string()),
+    }
+}
+
+#[test]
+fn test_parse_date() {
+    assert_eq!(parse_date("2025-02-04").as_deref(), Some("20250204"));
+    assert_eq!(parse_date("20250204").as_deref(), Some("20250204"));
+    assert_eq!(parse_date("2024-02-29").as_deref(), Some("20240229"));
+    assert_eq!(parse_date("2025-02-29"), None);
+    assert_eq!(parse_date("2025-13-01"), None);
+    assert_eq!(parse_date("2025-2-4"), None);
+    assert_eq!(parse_date("yesterday"), None);
+}
+
+#[test]
+fn test_days() {
+    assert_eq!(days("19700101"), Some(0));
+    assert_eq!(days("20000301"), Some(11017));
+    assert_eq!(days("20250504").zip(days("20250204")).map(|(a, b)| a - b), Some(89));
+    assert_eq!(days("2025050"), None);
+}
+
+#[test]
+fn test_select() {
+    let datasets = vec![
+        (CURRENT.to_string(), Some("20250504".to_string())),
+        ("20250204".to_string(), Some("20250204".to_string())),
+        ("20241104".to_string(), Some("20241104".to_string())),
+        ("unknown".to_string(), None),
+    ];
+    assert_eq!(select(&datasets, "20250601"), Some(CURRENT));
+    assert_eq!(select(&datasets, "20250504"), Some(CURRENT));
+    assert_eq!(select(&datasets, "20250503"), Some("20250204"));
+    assert_eq!(select(&datasets, "20250204"), Some("20250204"));
+    assert_eq!(select(&datasets, "20250101"), Some("20241104"));
+    assert_eq!(select(&datasets, "20240101"), None);
+
+    // a snapshot of the same release as the current dataset
+    let same = vec![
+        ("copy".to_string(), Some("20250504".to_string())),
+        (CURRENT.to_string(), Some("20250504".to_string())),
+    ];
+    assert_eq!(select(&same, "20250504"), Some(CURRENT));
+}
+
+#[test]
+fn test_retained() {
+    let snapshots = vec![
+        snapshot("20250204", Some("20250204")),
+        snapshot("20240204", Some("20240204")),
+        snapshot("undated", None),
+    ];
+    let names = |snapshots: Vec<Snapshot>| -> Vec<String> {
+        snapshots.into_iter().map(|snapshot| snapshot.name).collect()
+    };
+    assert_eq!(names(retained(snapshots.clone(), Some("20250504"), 180)),
+        vec!["20250204", "undated"]);
+    assert_eq!(names(retained(snapshots.clone(), Some("20250504"), 0)).len(), 3);
+    assert_eq!(names(retained(snapshots, None, 180)).len(), 3);
+}